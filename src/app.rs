@@ -1,3 +1,4 @@
+use super::fitter::batch_fit::FitTemplate;
 use super::pane::Pane;
 use super::tree::TreeBehavior;
 
@@ -23,7 +24,10 @@ impl Default for NATApp {
 
         // let mut tabs = vec![];
         // tabs.push(tiles.insert_pane(Pane::Workspace(workspacer.clone())));
-        let tabs = vec![tiles.insert_pane(Pane::Workspace(workspacer.clone()))];
+        let tabs = vec![
+            tiles.insert_pane(Pane::Workspace(workspacer.clone())),
+            tiles.insert_pane(Pane::Columns(Box::default())),
+        ];
 
         let root = tiles.insert_tab_tile(tabs);
 
@@ -55,6 +59,18 @@ impl NATApp {
     // }
 
     fn add_histograms_to_tree(&mut self) {
+        // put the saved cuts back on the new histograms, cuts that found no histogram get
+        // another try
+        let cut_handler = &self.processer.cut_handler;
+        let (cuts, range_cuts) = cut_handler.all_cuts();
+        for hist in self.processer.histogrammer.histograms1d.iter_mut() {
+            hist.place_cuts(&range_cuts);
+        }
+        for hist in self.processer.histogrammer.histograms2d.iter_mut() {
+            hist.place_cuts(&cuts);
+        }
+        self.processer.cut_handler.imported_cuts = Some((cuts, range_cuts));
+
         // let mut panes = self.processer.histogrammer.get_histogram1d_panes();
 
         // panes.push(Pane::Workspace(self.workspacer.clone()));
@@ -69,6 +85,223 @@ impl NATApp {
 
         // self.tree.tiles.insert_container(container);
     }
+
+    // Histograms created inside a pane (e.g. background subtracted) are added next to their source,
+    // a pane with the same name is replaced, e.g. when the background is subtracted again
+    fn add_new_histograms_to_tree(&mut self) {
+        let mut new_hists = Vec::new();
+        for (tile_id, tile) in self.tree.tiles.iter_mut() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                for new_hist in hist.new_histograms.drain(..) {
+                    new_hists.push((*tile_id, new_hist));
+                }
+            }
+        }
+
+        let mut new_panes = Vec::new();
+        for (source_id, new_hist) in new_hists {
+            let existing = self.tree.tiles.tiles_mut().find_map(|tile| match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) if hist.name == new_hist.name => {
+                    Some(hist)
+                }
+                _ => None,
+            });
+
+            match existing {
+                Some(hist) => **hist = new_hist,
+                None => new_panes.push((source_id, Pane::Histogram(Box::new(new_hist)))),
+            }
+        }
+
+        for (source_id, pane) in new_panes {
+            let new_id = self.tree.tiles.insert_pane(pane);
+            let parent = self.tree.tiles.parent_of(source_id);
+            match parent.and_then(|id| self.tree.tiles.get_mut(id)) {
+                Some(egui_tiles::Tile::Container(container)) => container.add_child(new_id),
+                _ => log::error!("Could not find where to place the new histogram"),
+            }
+        }
+    }
+
+    // Give the column browsers the selected files and add the histograms they made next to them
+    fn update_column_browser(&mut self) {
+        let files = self.workspacer.selected_files.borrow().clone();
+
+        let mut new_panes = Vec::new();
+        for (tile_id, tile) in self.tree.tiles.iter_mut() {
+            if let egui_tiles::Tile::Pane(Pane::Columns(browser)) = tile {
+                browser.set_files(&files);
+                for hist in browser.new_histograms.drain(..) {
+                    new_panes.push((*tile_id, Pane::Histogram(Box::new(hist))));
+                }
+                for hist in browser.new_histograms2d.drain(..) {
+                    new_panes.push((*tile_id, Pane::Histogram2D(Box::new(hist))));
+                }
+            }
+        }
+
+        for (source_id, pane) in new_panes {
+            let new_id = self.tree.tiles.insert_pane(pane);
+            let parent = self.tree.tiles.parent_of(source_id);
+            match parent.and_then(|id| self.tree.tiles.get_mut(id)) {
+                Some(egui_tiles::Tile::Container(container)) => container.add_child(new_id),
+                _ => log::error!("Could not find where to place the new histogram"),
+            }
+        }
+    }
+
+    // Collect the cuts drawn on the histograms and the gates edited in the gate manager
+    // so they can be applied to the next calculation
+    fn update_cuts(&mut self) {
+        let imported = self.processer.cut_handler.imported_cuts.take();
+        if let Some((cuts, range_cuts)) = &imported {
+            for tile in self.tree.tiles.tiles_mut() {
+                match tile {
+                    egui_tiles::Tile::Pane(Pane::Histogram(hist)) => hist.place_cuts(range_cuts),
+                    egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => hist.place_cuts(cuts),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut cuts = Vec::new();
+        let mut range_cuts = Vec::new();
+        let mut histograms = Vec::new();
+        for tile in self.tree.tiles.tiles() {
+            match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                    range_cuts.extend(hist.cuts.iter().cloned());
+                    if hist.gate.is_none() {
+                        histograms.push(hist.name.clone());
+                    }
+                }
+                egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => {
+                    cuts.extend(hist.cuts.iter().cloned());
+                    if hist.gate.is_none() {
+                        histograms.push(hist.name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let cut_handler = &mut self.processer.cut_handler;
+        cut_handler.cuts = cuts;
+        cut_handler.range_cuts = range_cuts;
+        cut_handler.available_histograms = histograms;
+
+        if let Some((cuts, range_cuts)) = imported {
+            cut_handler.keep_unplaced_cuts(cuts, range_cuts);
+        }
+
+        // the panes check new and renamed cuts against every name but their own cuts
+        let used_names = cut_handler.used_names();
+        let mut renamed = Vec::new();
+        for tile in self.tree.tiles.tiles_mut() {
+            match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                    hist.used_names =
+                        names_without(&used_names, hist.cuts.iter().map(|c| c.name.as_str()));
+                    renamed.append(&mut hist.renamed_cuts);
+                }
+                egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => {
+                    hist.used_names =
+                        names_without(&used_names, hist.cuts.iter().map(|c| c.name()));
+                    renamed.append(&mut hist.renamed_cuts);
+                }
+                _ => {}
+            }
+        }
+
+        for (old, new) in renamed {
+            cut_handler.rename_gate_references(&old, &new);
+        }
+    }
+
+    // Hand the histograms and stored fits to the batch fit panes and run their requests
+    fn update_batch_fit(&mut self) {
+        let mut histograms = Vec::new();
+        let mut fits = Vec::new();
+        for tile in self.tree.tiles.tiles() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                histograms.push(hist.name.clone());
+                fits.extend((0..hist.fits.stored_fits.len()).map(|i| (hist.name.clone(), i)));
+            }
+        }
+
+        let mut template_requests = Vec::new();
+        let mut apply_requests = Vec::new();
+        for tile in self.tree.tiles.tiles_mut() {
+            if let egui_tiles::Tile::Pane(Pane::BatchFit(batch)) = tile {
+                batch.available_histograms.clone_from(&histograms);
+                batch.available_fits.clone_from(&fits);
+                template_requests.extend(batch.template_request.take());
+                apply_requests.extend(batch.apply_request.take());
+            }
+        }
+
+        if template_requests.is_empty() && apply_requests.is_empty() {
+            return;
+        }
+
+        let mut templates = Vec::new();
+        let mut results = Vec::new();
+        for tile in self.tree.tiles.tiles_mut() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                for (name, fit) in &template_requests {
+                    if *name == hist.name {
+                        let template_name = format!("{} Fit {}", name, fit);
+                        templates.extend(
+                            hist.fits.stored_fits.get(*fit).and_then(|fitter| {
+                                FitTemplate::from_fitter(&template_name, fitter)
+                            }),
+                        );
+                    }
+                }
+
+                for (template, targets) in &apply_requests {
+                    if targets.contains(&hist.name) {
+                        results.extend(hist.apply_fit_template(template));
+                    }
+                }
+            }
+        }
+
+        for tile in self.tree.tiles.tiles_mut() {
+            if let egui_tiles::Tile::Pane(Pane::BatchFit(batch)) = tile {
+                for template in &templates {
+                    batch.add_template(template.clone());
+                }
+                if !apply_requests.is_empty() {
+                    batch.results.clone_from(&results);
+                }
+            }
+        }
+    }
+
+    // The histogram panes own their fits, so gather the stored fits into the results table and trend plots each frame
+    fn update_fit_results(&mut self) {
+        let rows: Vec<_> = self
+            .tree
+            .tiles
+            .tiles()
+            .filter_map(|tile| match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                    Some(hist.fits.result_rows(&hist.name))
+                }
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        for tile in self.tree.tiles.tiles_mut() {
+            match tile {
+                egui_tiles::Tile::Pane(Pane::FitResults(table)) => table.rows.clone_from(&rows),
+                egui_tiles::Tile::Pane(Pane::TrendPlot(trend)) => trend.rows.clone_from(&rows),
+                _ => {}
+            }
+        }
+    }
 }
 
 impl eframe::App for NATApp {
@@ -95,6 +328,12 @@ impl eframe::App for NATApp {
                         self.processer.calculate_histograms();
                         self.add_histograms_to_tree();
                     }
+
+                    self.processer.cuts_ui(ui);
+
+                    ui.menu_button("Export Events", |ui| {
+                        self.processer.event_export_ui(ui);
+                    });
                 }
             });
         });
@@ -137,8 +376,19 @@ impl eframe::App for NATApp {
                 }
             });
 
+        self.update_fit_results();
+        self.add_new_histograms_to_tree();
+        self.update_column_browser();
+        self.update_batch_fit();
+        self.update_cuts();
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            // the gate manager pane edits the cut handler of the processer
+            self.behavior.cut_handler = Some(std::mem::take(&mut self.processer.cut_handler));
             self.tree.ui(&mut self.behavior, ui);
+            if let Some(cut_handler) = self.behavior.cut_handler.take() {
+                self.processer.cut_handler = cut_handler;
+            }
         });
     }
 }
@@ -198,3 +448,14 @@ fn tree_ui(
     // Put the tile back
     tiles.insert(tile_id, tile);
 }
+
+// Removes one occurrence of each own name, so a pane's own cuts don't count as taken
+fn names_without<'a>(names: &[String], own: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut names = names.to_vec();
+    for name in own {
+        if let Some(index) = names.iter().position(|n| n == name) {
+            names.remove(index);
+        }
+    }
+    names
+}
@@ -1,209 +1,670 @@
-use super::egui_polygon::EditableEguiPolygon;
+use super::cuts::{Cut, RangeCut};
+use super::gate_library::GateLibrary;
+use super::gate_statistics::GateStatistics;
+use super::gates::{
+    parse_formula, rename_in_formula, Comparison, Gate, GateFormula, GateKind, GatedHistogram,
+};
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::path::PathBuf;
-
-use egui_plot::PlotUi;
 use polars::prelude::*;
 
-#[derive(Default, serde::Deserialize, serde::Serialize)]
+/// The named gates: polygon and range cuts collected from the histograms by the app, and the
+/// range, condition and compound gates defined in the gate manager.
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct CutHandler {
-    pub cuts: HashMap<String, EditableEguiPolygon>,
-    pub active_cut_id: Option<String>,
-    pub column_names: Vec<String>,
-    pub draw_flag: bool,
-    pub selected_cut: Option<String>,
+    #[serde(default)]
+    pub cuts: Vec<Cut>,
+    #[serde(default)]
+    pub range_cuts: Vec<RangeCut>, // collected from the 1D histograms
+    #[serde(default)]
+    pub unplaced_cuts: Vec<Cut>, // saved or imported for a histogram that does not exist
+    #[serde(default)]
+    pub unplaced_range_cuts: Vec<RangeCut>,
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    #[serde(default)]
+    pub active_gate: Option<String>, // gate applied to every histogram
+    #[serde(default)]
+    pub gated_histograms: Vec<GatedHistogram>,
+    #[serde(default)]
+    pub statistics: Option<GateStatistics>, // from the last histogram calculation
+    #[serde(skip)]
+    pub available_histograms: Vec<String>, // ungated histograms, set by the app
+    #[serde(skip)]
+    new_gated_histogram: Option<GatedHistogram>,
+    #[serde(skip)]
+    pub imported_cuts: Option<(Vec<Cut>, Vec<RangeCut>)>, // placed on the histograms by the app
+    #[serde(skip)]
+    overwrite_on_import: bool,
+    #[serde(skip)]
+    renaming: Option<(usize, String)>, // gate whose name is being edited and its name before
 }
 
 impl CutHandler {
-    // Creates a new `CutHandler` instance.
     pub fn new() -> Self {
-        Self {
-            cuts: HashMap::new(),
-            active_cut_id: None,
-            column_names: Vec::new(),
-            draw_flag: true,
-            selected_cut: None,
-        }
-    }
-
-    // Adds a new cut and makes it the active one
-    pub fn add_new_cut(&mut self) {
-        self.draw_flag = true;
-        let new_id = format!("cut_{}", self.cuts.len() + 1);
-        self.selected_cut = Some(new_id.clone());
-        self.cuts.insert(
-            new_id.clone(),
-            EditableEguiPolygon::new(self.column_names.clone()),
-        );
-        self.active_cut_id = Some(new_id); // Automatically make the new cut active
+        Self::default()
     }
 
-    // Method to update the column names
-    pub fn update_column_names(&mut self, column_names: Vec<String>) {
-        // Assuming `CutHandler` has a field to store column names
-        // Update it with the new column names
-        self.column_names = column_names;
+    pub fn valid_cuts(&self) -> impl Iterator<Item = &Cut> {
+        self.cuts
+            .iter()
+            .chain(&self.unplaced_cuts)
+            .filter(|cut| cut.is_valid())
     }
 
-    // Method to draw the active cut
-    pub fn draw_active_cut(&mut self, plot_ui: &mut PlotUi) {
-        if self.draw_flag {
-            if let Some(active_id) = &self.active_cut_id {
-                if let Some(active_cut) = self.cuts.get_mut(active_id) {
-                    active_cut.draw(plot_ui);
-                }
-            }
-        }
+    fn all_range_cuts(&self) -> impl Iterator<Item = &RangeCut> {
+        self.range_cuts.iter().chain(&self.unplaced_range_cuts)
     }
 
-    pub fn filter_lf_with_all_cuts(&mut self, lf: &LazyFrame) -> Result<LazyFrame, PolarsError> {
-        let mut filtered_lf = lf.clone();
+    /// The cuts on the histograms and the ones waiting for their histogram.
+    pub fn all_cuts(&self) -> (Vec<Cut>, Vec<RangeCut>) {
+        (
+            self.cuts
+                .iter()
+                .chain(&self.unplaced_cuts)
+                .cloned()
+                .collect(),
+            self.all_range_cuts().cloned().collect(),
+        )
+    }
 
-        // Iterate through all cuts and apply their respective filters.
-        for cut in self.cuts.values() {
-            // Directly call filter_lf_with_cut on each cut.
-            filtered_lf = cut.filter_lf_with_cut(&filtered_lf)?;
+    /// Keeps the handed out cuts that no histogram took, they stay usable as gates and are
+    /// placed once a histogram with their name exists. Call after collecting the placed cuts.
+    pub fn keep_unplaced_cuts(&mut self, cuts: Vec<Cut>, range_cuts: Vec<RangeCut>) {
+        self.unplaced_cuts = cuts
+            .into_iter()
+            .filter(|cut| !self.cuts.iter().any(|placed| placed.name() == cut.name()))
+            .collect();
+        self.unplaced_range_cuts = range_cuts
+            .into_iter()
+            .filter(|cut| !self.range_cuts.iter().any(|placed| placed.name == cut.name))
+            .collect();
+
+        let names: Vec<&str> = self
+            .unplaced_cuts
+            .iter()
+            .map(|cut| cut.name())
+            .chain(self.unplaced_range_cuts.iter().map(|cut| cut.name.as_str()))
+            .collect();
+        if !names.is_empty() {
+            log::warn!(
+                "No histogram for the cuts {}, they are kept in the gate manager",
+                names.join(", ")
+            );
         }
+    }
+
+    /// Names of every cut, also incomplete ones, and gate, set on the histograms by the app.
+    pub fn used_names(&self) -> Vec<String> {
+        self.cuts
+            .iter()
+            .chain(&self.unplaced_cuts)
+            .map(|cut| cut.name().to_string())
+            .chain(self.all_range_cuts().map(|cut| cut.name.clone()))
+            .chain(self.gates.iter().map(|gate| gate.name.clone()))
+            .collect()
+    }
 
-        Ok(filtered_lf)
+    pub fn gate_names(&self) -> Vec<String> {
+        self.valid_cuts()
+            .map(|cut| cut.name().to_string())
+            .chain(
+                self.all_range_cuts()
+                    .filter(|cut| cut.is_valid())
+                    .map(|cut| cut.name.clone()),
+            )
+            .chain(self.gates.iter().map(|gate| gate.name.clone()))
+            .collect()
     }
 
-    pub fn _filter_files_and_save_to_one_file(
-        &mut self,
-        file_paths: Vec<PathBuf>,
-        output_path: &PathBuf,
-    ) -> Result<(), PolarsError> {
-        let files_arc: Arc<[PathBuf]> = Arc::from(file_paths.clone());
+    pub fn gate_expr(&self, name: &str) -> Result<Expr, String> {
+        self.resolve_gate(name, &mut Vec::new())
+    }
 
-        let args = ScanArgsParquet::default();
+    // `visiting` holds the compound gates being resolved to catch gates that refer to themselves
+    fn resolve_gate(&self, name: &str, visiting: &mut Vec<String>) -> Result<Expr, String> {
+        if let Some(cut) = self
+            .cuts
+            .iter()
+            .chain(&self.unplaced_cuts)
+            .find(|cut| cut.name() == name)
+        {
+            return cut
+                .filter_expr()
+                .ok_or(format!("Cut '{}' is incomplete", name));
+        }
 
-        // Assuming LazyFrame::scan_parquet_files constructs a LazyFrame from the list of files
-        let lf = LazyFrame::scan_parquet_files(files_arc, args)?;
+        if let Some(cut) = self.all_range_cuts().find(|cut| cut.name == name) {
+            return cut
+                .filter_expr()
+                .ok_or(format!("Cut '{}' has an empty range", name));
+        }
 
-        // Apply filtering logic as before, leading to a filtered LazyFrame
-        let filtered_lf = self.filter_lf_with_all_cuts(&lf)?; // Placeholder for applying cuts
+        let gate = self
+            .gates
+            .iter()
+            .find(|gate| gate.name == name)
+            .ok_or(format!("Unknown gate '{}'", name))?;
 
-        // Collect the LazyFrame into a DataFrame
-        let mut filtered_df = filtered_lf.collect()?;
+        match &gate.kind {
+            GateKind::Compound { formula } => {
+                if visiting.iter().any(|n| n == name) {
+                    return Err(format!("Gate '{}' refers to itself", name));
+                }
 
-        // Open a file in write mode at the specified output path
-        let file = File::create(output_path)
-            .map_err(|e| PolarsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                let parsed = parse_formula(formula).map_err(|e| format!("{}: {}", name, e))?;
 
-        // Write the filtered DataFrame to a Parquet file
-        ParquetWriter::new(file)
-            .set_parallel(true)
-            .finish(&mut filtered_df)?;
+                visiting.push(name.to_string());
+                let expr = self.resolve_formula(&parsed, visiting);
+                visiting.pop();
+                expr
+            }
+            _ => gate
+                .simple_expr()
+                .ok_or(format!("Gate '{}' has no expression", name)),
+        }
+    }
 
-        Ok(())
+    fn resolve_formula(
+        &self,
+        formula: &GateFormula,
+        visiting: &mut Vec<String>,
+    ) -> Result<Expr, String> {
+        Ok(match formula {
+            GateFormula::Gate(name) => self.resolve_gate(name, visiting)?,
+            GateFormula::Not(inner) => self.resolve_formula(inner, visiting)?.not(),
+            GateFormula::And(a, b) => self
+                .resolve_formula(a, visiting)?
+                .and(self.resolve_formula(b, visiting)?),
+            GateFormula::Or(a, b) => self
+                .resolve_formula(a, visiting)?
+                .or(self.resolve_formula(b, visiting)?),
+        })
     }
 
-    /*
-    pub fn _filter_files_and_save_separately(
-        &mut self,
-        file_paths: Vec<PathBuf>,
-        output_dir: &PathBuf,
-        custom_text: &str,
-    ) -> Result<(), PolarsError> {
-        for file_path in file_paths.iter() {
-            let args = ScanArgsParquet::default();
+    pub fn filter_lf_with_gate(&self, lf: &LazyFrame, name: &str) -> Result<LazyFrame, String> {
+        Ok(lf.clone().filter(self.gate_expr(name)?))
+    }
 
-            let file_arc: Arc<PathBuf> = Arc::from(file_path.clone());
+    // Applies the active gate, an unusable gate is logged and skipped
+    pub fn filter_lf_with_active_gate(&self, lf: &LazyFrame) -> LazyFrame {
+        match &self.active_gate {
+            Some(name) => self.filter_lf_with_gate(lf, name).unwrap_or_else(|e| {
+                log::error!("Failed to apply gate: {}", e);
+                lf.clone()
+            }),
+            None => lf.clone(),
+        }
+    }
 
-            // Construct a LazyFrame for each file
-            let lf = LazyFrame::scan_parquet(file_arc.as_ref(), args.clone())?;
+    /// Points the compound formulas, the active gate and the gated histograms at the new
+    /// name of a gate. Returns false for an empty or taken name, which the caller reverts.
+    pub fn rename_gate_references(&mut self, old: &str, new: &str) -> bool {
+        if old == new {
+            return true;
+        }
 
-            // Apply filtering logic as before, leading to a filtered LazyFrame
-            let filtered_lf = self.filter_lf_with_all_cuts(&lf)?; // Placeholder for applying cuts
+        if new.is_empty() || self.used_names().iter().filter(|name| *name == new).count() > 1 {
+            log::error!("Name '{}' is empty or already used, keeping '{}'", new, old);
+            return false;
+        }
 
-            // Collect the LazyFrame into a DataFrame
-            let mut filtered_df = filtered_lf.collect()?;
+        for gate in self.gates.iter_mut() {
+            if let GateKind::Compound { formula } = &mut gate.kind {
+                *formula = rename_in_formula(formula, old, new);
+            }
+        }
 
-            // Generate a new output file name by appending custom text to the original file name
-            let original_file_name = file_path.file_stem().unwrap_or(OsStr::new("default"));
-            let new_file_name = format!(
-                "{}_{}.parquet",
-                original_file_name.to_string_lossy(),
-                custom_text
-            );
-            let output_file_path = output_dir.join(new_file_name);
+        if self.active_gate.as_deref() == Some(old) {
+            self.active_gate = Some(new.to_string());
+        }
 
-            // Open a file in write mode at the newly specified output path
-            let file = File::create(&output_file_path)
-                .map_err(|e| PolarsError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+        for gated in self.gated_histograms.iter_mut() {
+            if gated.gate == old {
+                gated.gate = new.to_string();
+            }
+        }
+
+        true
+    }
 
-            // Write the filtered DataFrame to a new Parquet file
-            ParquetWriter::new(file)
-                .set_parallel(true)
-                .finish(&mut filtered_df)?;
+    fn unique_gate_name(&self, base: &str) -> String {
+        let names = self.gate_names();
+        (1..)
+            .map(|i| format!("{} {}", base, i))
+            .find(|name| !names.contains(name))
+            .unwrap_or_default()
+    }
+
+    pub fn active_gate_ui(&mut self, ui: &mut egui::Ui) {
+        let names = self.gate_names();
+        if names.is_empty() {
+            return;
         }
 
-        Ok(())
+        egui::ComboBox::from_label("Gate")
+            .selected_text(self.active_gate.clone().unwrap_or("None".to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.active_gate, None, "None");
+                for name in names {
+                    ui.selectable_value(&mut self.active_gate, Some(name.clone()), name);
+                }
+            })
+            .response
+            .on_hover_text("Only events passing this gate fill the histograms");
     }
-    */
 
-    pub fn cut_handler_ui(&mut self, ui: &mut egui::Ui) {
+    fn gated_histograms_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Gated Histograms");
+        ui.label("Filled next to the ungated histograms when the histograms are calculated");
+
+        let gate_names = self.gate_names();
+        let new = self
+            .new_gated_histogram
+            .get_or_insert_with(|| GatedHistogram {
+                histogram: String::new(),
+                gate: String::new(),
+            });
+
         ui.horizontal(|ui| {
-            ui.label("Cutter: ");
+            egui::ComboBox::from_id_source("gated_histogram_histogram")
+                .selected_text(&new.histogram)
+                .show_ui(ui, |ui| {
+                    for name in &self.available_histograms {
+                        ui.selectable_value(&mut new.histogram, name.clone(), name);
+                    }
+                });
+
+            egui::ComboBox::from_id_source("gated_histogram_gate")
+                .selected_text(&new.gate)
+                .show_ui(ui, |ui| {
+                    for name in &gate_names {
+                        ui.selectable_value(&mut new.gate, name.clone(), name);
+                    }
+                });
+
+            let complete = !new.histogram.is_empty() && !new.gate.is_empty();
+            if ui.add_enabled(complete, egui::Button::new("Add")).clicked()
+                && !self.gated_histograms.contains(new)
+            {
+                self.gated_histograms.push(new.clone());
+            }
+        });
 
-            if ui.button("New Cut").clicked() {
-                self.add_new_cut();
+        let mut index_to_remove = None;
+        for (index, gated) in self.gated_histograms.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("🗙").clicked() {
+                    index_to_remove = Some(index);
+                }
+                ui.label(gated.name());
+                if !gate_names.contains(&gated.gate) {
+                    ui.colored_label(egui::Color32::RED, "Unknown gate");
+                }
+            });
+        }
+
+        if let Some(index) = index_to_remove {
+            self.gated_histograms.remove(index);
+        }
+    }
+
+    fn save_library(&self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_file_name("gates.json")
+            .add_filter("JSON", &["json"])
+            .save_file()
+        {
+            if let Err(e) = GateLibrary::from_cut_handler(self).save(&path) {
+                log::error!("Failed to save gate library: {}", e);
             }
+        }
+    }
 
-            // only display the cut selection UI if there are cuts
-            if !self.cuts.is_empty() {
-                self.select_cut_ui(ui);
+    fn import_library(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        {
+            match GateLibrary::load(&path) {
+                Ok(library) => {
+                    log::info!("Imported {} gates from {}", library.len(), path.display());
+                    self.merge_library(&library, self.overwrite_on_import);
+                }
+                Err(e) => log::error!("Failed to import gate library: {}", e),
             }
+        }
+    }
 
-            self.selected_cut_ui(ui);
+    fn library_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Save Library")
+                .on_hover_text("Save all gates and cuts with their columns to a JSON file")
+                .clicked()
+            {
+                self.save_library();
+            }
+
+            if ui
+                .button("Import Library")
+                .on_hover_text(
+                    "Add the gates and cuts of a gate library, e.g. from another workspace",
+                )
+                .clicked()
+            {
+                self.import_library();
+            }
+
+            ui.checkbox(&mut self.overwrite_on_import, "Replace Same Names")
+                .on_hover_text(
+                    "Imported gates replace gates with the same name instead of being skipped",
+                );
         });
     }
 
-    pub fn select_cut_ui(&mut self, ui: &mut egui::Ui) {
-        // Start with a separator for visual clarity
-        ui.separator();
+    fn unplaced_cuts_ui(&mut self, ui: &mut egui::Ui) {
+        if self.unplaced_cuts.is_empty() && self.unplaced_range_cuts.is_empty() {
+            return;
+        }
 
-        // Label for the combo box to indicate its purpose
-        ui.label("Select Cut:");
+        ui.separator();
 
-        // Initialize a temporary variable for the selected cut ID for the combo box
-        let mut current_selection = self.selected_cut.clone().unwrap_or_default();
+        ui.heading("Unplaced Cuts");
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            "No histogram has these names, the cuts still work as gates",
+        );
 
-        // Create a combo box to list all available cuts
-        egui::ComboBox::from_label("")
-            .selected_text(current_selection.clone())
-            .show_ui(ui, |ui| {
-                // Iterate over all cut IDs in the HashMap and add them as selectable items
-                for cut_id in self.cuts.keys() {
-                    ui.selectable_value(&mut current_selection, cut_id.clone(), cut_id);
+        let mut cut_to_remove = None;
+        for (index, cut) in self.unplaced_cuts.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("🗙").clicked() {
+                    cut_to_remove = Some(index);
                 }
+                ui.label(format!(
+                    "{}: {} v {} on {}",
+                    cut.name(),
+                    cut.y_column,
+                    cut.x_column,
+                    cut.histogram
+                ));
             });
+        }
+        if let Some(index) = cut_to_remove {
+            self.unplaced_cuts.remove(index);
+        }
 
-        // Check if the selection has changed, and update the selected cut ID accordingly
-        if current_selection != self.selected_cut.clone().unwrap_or_default() {
-            self.selected_cut = Some(current_selection);
+        let mut range_cut_to_remove = None;
+        for (index, cut) in self.unplaced_range_cuts.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("🗙").clicked() {
+                    range_cut_to_remove = Some(index);
+                }
+                ui.label(format!("{}: {} on {}", cut.name, cut.column, cut.histogram));
+            });
+        }
+        if let Some(index) = range_cut_to_remove {
+            self.unplaced_range_cuts.remove(index);
         }
     }
 
-    pub fn selected_cut_ui(&mut self, ui: &mut egui::Ui) {
-        // Display UI for the selected cut
-        if let Some(selected_id) = &self.selected_cut {
-            if let Some(selected_cut) = self.cuts.get_mut(selected_id) {
-                selected_cut.cut_ui(ui); // Display the `cut_ui` of the selected cut
+    pub fn gate_manager_ui(&mut self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Gates");
+
+                if ui.button("+ Range").clicked() {
+                    let name = self.unique_gate_name("Range");
+                    self.gates.push(Gate::new(
+                        &name,
+                        GateKind::Range {
+                            column: String::new(),
+                            min: 0.0,
+                            max: 0.0,
+                        },
+                    ));
+                }
 
-                // check box to draw/edit the cut
-                ui.checkbox(&mut self.draw_flag, "Draw");
+                if ui.button("+ Condition").clicked() {
+                    let name = self.unique_gate_name("Condition");
+                    self.gates.push(Gate::new(
+                        &name,
+                        GateKind::Condition {
+                            column: String::new(),
+                            comparison: Comparison::Greater,
+                            value: 0.0,
+                        },
+                    ));
+                }
 
-                ui.separator();
+                if ui.button("+ Compound").clicked() {
+                    let name = self.unique_gate_name("Compound");
+                    self.gates.push(Gate::new(
+                        &name,
+                        GateKind::Compound {
+                            formula: String::new(),
+                        },
+                    ));
+                }
+            });
 
-                // button to remove cut
-                if ui.button("Delete").clicked() {
-                    self.cuts.remove(selected_id);
-                    self.selected_cut = None;
+            self.library_ui(ui);
+
+            if let Some(active) = &self.active_gate {
+                if !self.gate_names().contains(active) {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("The active gate '{}' does not exist", active),
+                    );
                 }
             }
-        }
+
+            ui.separator();
+
+            ui.heading("Polygon Cuts");
+            if self.cuts.is_empty() {
+                ui.label("Add a cut from the context menu of a 2D histogram");
+            }
+            for cut in &self.cuts {
+                let status = if cut.is_valid() {
+                    format!("{} vertices", cut.polygon.vertices.len())
+                } else {
+                    "incomplete".to_string()
+                };
+                ui.label(format!(
+                    "{}: {} v {} ({})",
+                    cut.name(),
+                    cut.y_column,
+                    cut.x_column,
+                    status
+                ));
+            }
+
+            ui.separator();
+
+            ui.heading("Range Cuts");
+            if self.range_cuts.is_empty() {
+                ui.label("Add a cut from the region markers of a 1D histogram");
+            }
+            for cut in &self.range_cuts {
+                let (min, max) = cut.range();
+                ui.label(format!(
+                    "{}: {:.2} ≤ {} < {:.2}",
+                    cut.name, min, cut.column, max
+                ));
+            }
+
+            self.unplaced_cuts_ui(ui);
+
+            ui.separator();
+
+            let statuses: Vec<Option<String>> = self
+                .gates
+                .iter()
+                .map(|gate| self.gate_expr(&gate.name).err())
+                .collect();
+            let names = self.gate_names();
+
+            let mut index_to_remove = None;
+            let mut renamed = None;
+            egui::Grid::new("gate_manager_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Name");
+                    ui.label("Type");
+                    ui.label("Definition");
+                    ui.label("");
+                    ui.end_row();
+
+                    for (index, gate) in self.gates.iter_mut().enumerate() {
+                        let response = ui
+                            .push_id(index, |ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut gate.name).desired_width(100.0),
+                                )
+                            })
+                            .inner;
+
+                        // references follow the name once the edit is done
+                        if response.gained_focus() {
+                            self.renaming = Some((index, gate.name.clone()));
+                        }
+                        if response.lost_focus() {
+                            if let Some((renaming_index, old)) = self.renaming.take() {
+                                if renaming_index == index {
+                                    renamed = Some((index, old, gate.name.clone()));
+                                }
+                            }
+                        }
+                        ui.label(gate.kind.label());
+                        ui.push_id(index, |ui| {
+                            ui.horizontal(|ui| gate.parameters_ui(ui));
+                        });
+
+                        ui.horizontal(|ui| {
+                            if ui.button("🗙").clicked() {
+                                index_to_remove = Some(index);
+                            }
+
+                            if names.iter().filter(|n| **n == gate.name).count() > 1 {
+                                ui.colored_label(egui::Color32::YELLOW, "Duplicate name");
+                            } else if let Some(error) = &statuses[index] {
+                                ui.colored_label(egui::Color32::RED, error);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+
+            if let Some((index, old, new)) = renamed {
+                if !self.rename_gate_references(&old, &new) {
+                    self.gates[index].name = old;
+                }
+            }
+
+            if let Some(index) = index_to_remove {
+                self.gates.remove(index);
+                self.renaming = None;
+            }
+
+            ui.separator();
+
+            self.gated_histograms_ui(ui);
+
+            if let Some(statistics) = &self.statistics {
+                ui.separator();
+
+                statistics.ui(ui);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler() -> CutHandler {
+        let mut cut = Cut::new("Square", "x", "y");
+        cut.polygon.vertices = vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+
+        let mut handler = CutHandler::new();
+        handler.cuts.push(cut);
+        handler.gates = vec![
+            Gate::new(
+                "Energy",
+                GateKind::Range {
+                    column: "e".to_string(),
+                    min: 2.0,
+                    max: 4.0,
+                },
+            ),
+            Gate::new(
+                "Both",
+                GateKind::Compound {
+                    formula: "Square and not Energy".to_string(),
+                },
+            ),
+            Gate::new(
+                "Loop",
+                GateKind::Compound {
+                    formula: "Energy or Loop".to_string(),
+                },
+            ),
+        ];
+        handler
+    }
+
+    #[test]
+    fn test_compound_gate() {
+        let df = df!(
+            "x" => [1.0, 2.0, 3.0, 20.0],
+            "y" => [1.0, 2.0, 3.0, 1.0],
+            "e" => [1.0, 2.0, 5.0, 1.0],
+        )
+        .unwrap();
+
+        let filtered = handler()
+            .filter_lf_with_gate(&df.lazy(), "Both")
+            .unwrap()
+            .collect()
+            .unwrap();
+
+        let kept: Vec<f64> = filtered
+            .column("x")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(kept, vec![1.0, 3.0]);
+    }
+
+    #[test]
+    fn test_gate_errors() {
+        let handler = handler();
+        assert!(handler.gate_expr("Loop").unwrap_err().contains("itself"));
+        assert!(handler.gate_expr("Missing").is_err());
+    }
+
+    #[test]
+    fn test_rename_gate_references() {
+        let mut handler = handler();
+        handler.active_gate = Some("Energy".to_string());
+
+        handler.gates[0].name = "E1".to_string();
+        assert!(handler.rename_gate_references("Energy", "E1"));
+        assert_eq!(handler.active_gate.as_deref(), Some("E1"));
+        assert!(handler.gate_expr("Both").is_ok());
+        assert!(matches!(
+            &handler.gates[1].kind,
+            GateKind::Compound { formula } if formula == "Square and not E1"
+        ));
+
+        // renaming onto a taken name is rejected and keeps the references
+        handler.gates[0].name = "Square".to_string();
+        assert!(!handler.rename_gate_references("E1", "Square"));
+        assert_eq!(handler.active_gate.as_deref(), Some("E1"));
+        assert!(!handler.rename_gate_references("E1", ""));
     }
 }
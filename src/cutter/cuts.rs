@@ -0,0 +1,396 @@
+use geo::Contains;
+use polars::prelude::*;
+
+use crate::egui_plot_stuff::egui_polygon::EguiPolygon;
+use crate::egui_plot_stuff::egui_vertical_line::EguiVerticalLine;
+
+/// First "<histogram> Cut n" that is not taken. Cuts are used as gates by name, so a
+/// repeated name would hide the later cut.
+pub fn unique_cut_name(histogram: &str, taken: &[String]) -> String {
+    (1..)
+        .map(|n| format!("{} Cut {}", histogram, n))
+        .find(|name| !taken.contains(name))
+        .unwrap_or_default()
+}
+
+/// A polygon drawn on a 2D histogram, bound to the columns of the histogram axes.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Cut {
+    pub polygon: EguiPolygon,
+    pub x_column: String,
+    pub y_column: String,
+    #[serde(default)]
+    pub histogram: String, // histogram the cut was drawn on
+}
+
+impl Cut {
+    pub fn new(name: &str, x_column: &str, y_column: &str) -> Self {
+        Self {
+            polygon: EguiPolygon::new(name),
+            x_column: x_column.to_string(),
+            y_column: y_column.to_string(),
+            histogram: String::new(),
+        }
+    }
+
+    // Cuts from older sessions without a histogram go to a histogram with the same columns
+    pub fn belongs_to(&self, histogram: &str, x_column: &str, y_column: &str) -> bool {
+        if self.histogram.is_empty() {
+            self.x_column == x_column && self.y_column == y_column
+        } else {
+            self.histogram == histogram
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.polygon.name
+    }
+
+    // A cut needs an area and the columns to filter on
+    pub fn is_valid(&self) -> bool {
+        self.polygon.vertices.len() >= 3 && !self.x_column.is_empty() && !self.y_column.is_empty()
+    }
+
+    fn to_geo_polygon(&self) -> geo::Polygon<f64> {
+        let exterior_coords: Vec<_> = self.polygon.vertices.iter().map(|&[x, y]| (x, y)).collect();
+        geo::Polygon::new(geo::LineString::from(exterior_coords), vec![])
+    }
+
+    pub fn is_inside(&self, x: f64, y: f64) -> bool {
+        self.to_geo_polygon().contains(&geo::Point::new(x, y))
+    }
+
+    // x min, x max, y min, y max of the vertices
+    fn bounding_box(&self) -> [f64; 4] {
+        self.polygon.vertices.iter().fold(
+            [
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ],
+            |[x_min, x_max, y_min, y_max], &[x, y]| {
+                [x_min.min(x), x_max.max(x), y_min.min(y), y_max.max(y)]
+            },
+        )
+    }
+
+    /// Point-in-polygon test as a polars expression (even-odd rule), so the cut stays
+    /// in the lazy plan and runs in parallel/streaming like any other filter.
+    pub fn filter_expr(&self) -> Option<Expr> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        let x = || col(&self.x_column).cast(DataType::Float64);
+        let y = || col(&self.y_column).cast(DataType::Float64);
+        let [x_min, x_max, y_min, y_max] = self.bounding_box();
+
+        let bounding_box = x()
+            .gt_eq(lit(x_min))
+            .and(x().lt_eq(lit(x_max)))
+            .and(y().gt_eq(lit(y_min)))
+            .and(y().lt_eq(lit(y_max)))
+            .and(x().neq(lit(-1e6)))
+            .and(y().neq(lit(-1e6)));
+
+        // a ray from (x, y) towards +x crosses an odd number of edges for points inside
+        let vertices = &self.polygon.vertices;
+        let crossings = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .filter(|([_, y1], [_, y2])| y1 != y2) // horizontal edges are never crossed
+            .map(|(&[x1, y1], &[x2, y2])| {
+                let slope = (x2 - x1) / (y2 - y1);
+                let spans_y = y().gt(lit(y1)).neq(y().gt(lit(y2)));
+                let left_of_edge = x().lt(lit(x1) + (y() - lit(y1)) * lit(slope));
+                spans_y.and(left_of_edge)
+            })
+            .reduce(|inside, crossing| inside.xor(crossing))?;
+
+        Some(bounding_box.and(crossings))
+    }
+
+    pub fn filter_lf(&self, lf: &LazyFrame) -> LazyFrame {
+        match self.filter_expr() {
+            Some(expr) => lf.clone().filter(expr),
+            None => lf.clone(),
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.polygon.name = name.to_string();
+    }
+
+    /// Returns the old and new name once an edit of the name is finished.
+    pub fn menu_button(&mut self, ui: &mut egui::Ui) -> Option<(String, String)> {
+        ui.vertical(|ui| {
+            let renamed = self.polygon.menu_button(ui);
+            ui.label(format!("{} v {}", self.y_column, self.x_column))
+                .on_hover_text("Columns the cut is applied to");
+            renamed
+        })
+        .inner
+    }
+
+    pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
+        self.polygon.draw(plot_ui);
+    }
+
+    pub fn handle_interactions(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
+        self.polygon.handle_interactions(plot_response);
+    }
+}
+
+/// Two draggable lines on a 1D histogram, bound to the column of the histogram.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RangeCut {
+    pub name: String,
+    pub column: String,
+    pub lines: [EguiVerticalLine; 2],
+    #[serde(default)]
+    pub histogram: String, // histogram the cut was drawn on
+    #[serde(skip)]
+    renaming_from: Option<String>, // name before the current edit of the name
+}
+
+impl RangeCut {
+    pub fn new(name: &str, column: &str, min: f64, max: f64) -> Self {
+        let color = egui::Color32::from_rgb(255, 140, 0);
+        let mut cut = Self {
+            name: name.to_string(),
+            column: column.to_string(),
+            lines: [
+                EguiVerticalLine::new(min, color),
+                EguiVerticalLine::new(max, color),
+            ],
+            histogram: String::new(),
+            renaming_from: None,
+        };
+        cut.update_line_names();
+        cut
+    }
+
+    pub fn belongs_to(&self, histogram: &str, column: &str) -> bool {
+        if self.histogram.is_empty() {
+            self.column == column
+        } else {
+            self.histogram == histogram
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.update_line_names();
+    }
+
+    // the line names are the ids used for dragging
+    fn update_line_names(&mut self) {
+        self.lines[0].name = format!("{} Min", self.name);
+        self.lines[1].name = format!("{} Max", self.name);
+    }
+
+    // the lines can be dragged past each other
+    pub fn range(&self) -> (f64, f64) {
+        let (a, b) = (self.lines[0].x_value, self.lines[1].x_value);
+        (a.min(b), a.max(b))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let (min, max) = self.range();
+        !self.column.is_empty() && min < max
+    }
+
+    pub fn filter_expr(&self) -> Option<Expr> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        let (min, max) = self.range();
+        let x = || col(&self.column).cast(DataType::Float64);
+        Some(x().gt_eq(lit(min)).and(x().lt(lit(max))))
+    }
+
+    pub fn filter_lf(&self, lf: &LazyFrame) -> LazyFrame {
+        match self.filter_expr() {
+            Some(expr) => lf.clone().filter(expr),
+            None => lf.clone(),
+        }
+    }
+
+    /// Returns the old and new name once an edit of the name is finished.
+    pub fn menu_button(&mut self, ui: &mut egui::Ui) -> Option<(String, String)> {
+        let mut renamed = None;
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(100.0));
+            if response.changed() {
+                self.update_line_names();
+            }
+            if response.gained_focus() {
+                self.renaming_from = Some(self.name.clone());
+            }
+            if response.lost_focus() {
+                renamed = self
+                    .renaming_from
+                    .take()
+                    .filter(|old| *old != self.name)
+                    .map(|old| (old, self.name.clone()));
+            }
+
+            ui.add(
+                egui::DragValue::new(&mut self.lines[0].x_value)
+                    .speed(0.1)
+                    .prefix("Min: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.lines[1].x_value)
+                    .speed(0.1)
+                    .prefix("Max: "),
+            );
+            ui.label(&self.column)
+                .on_hover_text("Column the cut is applied to");
+        });
+        renamed
+    }
+
+    pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
+        for line in &self.lines {
+            line.draw(plot_ui);
+        }
+    }
+
+    pub fn interactive_dragging(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
+        for line in &mut self.lines {
+            line.interactive_dragging(plot_response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Cut {
+        let mut cut = Cut::new("Triangle", "x", "y");
+        cut.polygon.vertices = vec![[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        cut
+    }
+
+    #[test]
+    fn test_filter_uses_polygon_not_bounding_box() {
+        let df = df!(
+            "x" => [1.0, 9.0, 2.0, 20.0, -1e6],
+            "y" => [1.0, 9.0, 2.0, 1.0, 1.0],
+            "e" => [1, 2, 3, 4, 5],
+        )
+        .unwrap();
+
+        let filtered = triangle().filter_lf(&df.lazy()).collect().unwrap();
+
+        // (9, 9) is inside the bounding box but outside the triangle
+        let kept: Vec<i32> = filtered
+            .column("e")
+            .unwrap()
+            .i32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(kept, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_filter_expr_matches_geo() {
+        // concave "C" shape
+        let mut cut = Cut::new("C", "x", "y");
+        cut.polygon.vertices = vec![
+            [0.0, 0.0],
+            [6.0, 0.0],
+            [6.0, 2.0],
+            [2.0, 2.0],
+            [2.0, 4.0],
+            [6.0, 4.0],
+            [6.0, 6.0],
+            [0.0, 6.0],
+        ];
+
+        let points: Vec<(f64, f64)> = (0..40)
+            .flat_map(|i| (0..40).map(move |j| (i as f64 * 0.17 - 0.3, j as f64 * 0.17 - 0.3)))
+            .collect();
+        let df = df!(
+            "x" => points.iter().map(|p| p.0).collect::<Vec<_>>(),
+            "y" => points.iter().map(|p| p.1).collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        let mask = df
+            .lazy()
+            .select([cut.filter_expr().unwrap().alias("inside")])
+            .collect()
+            .unwrap();
+        let inside: Vec<bool> = mask
+            .column("inside")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.unwrap_or(false))
+            .collect();
+
+        for (&(x, y), inside) in points.iter().zip(inside) {
+            assert_eq!(inside, cut.is_inside(x, y), "point ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_range_cut() {
+        let df = df!("x" => [1.0, 2.0, 3.0, 4.0]).unwrap();
+
+        // dragged past each other
+        let cut = RangeCut::new("Window", "x", 3.5, 1.5);
+        assert_eq!(cut.range(), (1.5, 3.5));
+
+        let filtered = cut.filter_lf(&df.lazy()).collect().unwrap();
+        assert_eq!(filtered.height(), 2);
+    }
+
+    #[test]
+    fn test_invalid_cut_keeps_everything() {
+        let df = df!("x" => [1.0, 2.0], "y" => [1.0, 2.0]).unwrap();
+        let cut = Cut::new("Empty", "x", "y");
+
+        let filtered = cut.filter_lf(&df.lazy()).collect().unwrap();
+        assert_eq!(filtered.height(), 2);
+    }
+
+    #[test]
+    fn test_edit_polygon_vertices() {
+        let mut cut = triangle();
+        let polygon = &mut cut.polygon;
+
+        // a point below the bottom edge splits it
+        polygon.insert_vertex(5.0, -1.0);
+        assert_eq!(polygon.vertices[1], [5.0, -1.0]);
+        assert_eq!(polygon.vertices.len(), 4);
+
+        polygon.remove_vertex(0);
+        polygon.translate(1.0, 2.0);
+        assert_eq!(polygon.vertices, vec![[6.0, 1.0], [11.0, 2.0], [1.0, 12.0]]);
+
+        polygon.undo();
+        polygon.undo();
+        assert_eq!(polygon.vertices[0], [0.0, 0.0]);
+        assert_eq!(polygon.vertices.len(), 3);
+        assert!(!polygon.can_undo());
+
+        polygon.snap_to_bins = true;
+        polygon.bin_grid = Some([0.0, 2.0, 0.0, 0.5]);
+        assert_eq!(polygon.snap([3.1, 1.2]), [3.0, 1.25]);
+    }
+
+    #[test]
+    fn test_unique_cut_name() {
+        let taken = vec!["X Cut 1".to_string(), "X Cut 3".to_string()];
+        assert_eq!(unique_cut_name("X", &taken), "X Cut 2");
+        assert_eq!(unique_cut_name("Y", &taken), "Y Cut 1");
+    }
+}
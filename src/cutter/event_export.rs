@@ -0,0 +1,331 @@
+use super::cut_handler::CutHandler;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use polars::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub enum ExportMode {
+    #[default]
+    Merged,
+    PerRun,
+}
+
+/// One output file and the lazy events written to it.
+pub struct ExportJob {
+    pub lf: LazyFrame,
+    pub output: PathBuf,
+}
+
+/// Writes the events passing the active gate to parquet. The files are streamed with the
+/// polars sink, so the events never have to fit in memory.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EventExport {
+    pub mode: ExportMode,
+    pub suffix: String,                // appended to the file stem of each run
+    pub selected_columns: Vec<String>, // empty exports every column
+    #[serde(skip)]
+    pub available_columns: Vec<String>,
+    #[serde(skip)]
+    running: Arc<AtomicBool>,
+}
+
+impl Default for EventExport {
+    fn default() -> Self {
+        Self {
+            mode: ExportMode::Merged,
+            suffix: "_gated".to_string(),
+            selected_columns: Vec::new(),
+            available_columns: Vec::new(),
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl EventExport {
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    // `prepare` adds the calculated columns the gates and the selection can refer to
+    pub fn jobs(
+        &self,
+        files: &[PathBuf],
+        output: &Path,
+        prepare: impl Fn(LazyFrame) -> LazyFrame,
+    ) -> Result<Vec<ExportJob>, PolarsError> {
+        let mut jobs = match self.mode {
+            ExportMode::Merged => {
+                let files_arc: Arc<[PathBuf]> = Arc::from(files.to_vec());
+                let lf = LazyFrame::scan_parquet_files(files_arc, ScanArgsParquet::default())?;
+                vec![ExportJob {
+                    lf: prepare(lf),
+                    output: output.to_path_buf(),
+                }]
+            }
+            ExportMode::PerRun => {
+                // without a suffix the output in the input folder is the input itself
+                if self.suffix.trim().is_empty() {
+                    return Err(PolarsError::InvalidOperation(
+                        "the suffix of per run exports can not be empty".into(),
+                    ));
+                }
+
+                files
+                    .iter()
+                    .map(|file| {
+                        let stem = file
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let lf = LazyFrame::scan_parquet(file, ScanArgsParquet::default())?;
+                        Ok(ExportJob {
+                            lf: prepare(lf),
+                            output: output.join(format!("{}{}.parquet", stem, self.suffix)),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, PolarsError>>()?
+            }
+        };
+
+        // the sink truncates the output while it is still reading the input
+        let inputs: Vec<PathBuf> = files.iter().filter_map(|f| f.canonicalize().ok()).collect();
+        jobs.retain(|job| match job.output.canonicalize() {
+            Ok(output) if inputs.contains(&output) => {
+                log::error!(
+                    "Not exporting to {}, it is one of the input files",
+                    job.output.display()
+                );
+                false
+            }
+            _ => true,
+        });
+
+        Ok(jobs)
+    }
+
+    pub fn run_jobs(
+        jobs: Vec<ExportJob>,
+        gate: Option<Expr>,
+        columns: &[String],
+    ) -> Result<(), PolarsError> {
+        for job in jobs {
+            let mut lf = job.lf;
+
+            if let Some(gate) = &gate {
+                lf = lf.filter(gate.clone());
+            }
+
+            if !columns.is_empty() {
+                lf = lf.select(columns.iter().map(|c| col(c)).collect::<Vec<_>>());
+            }
+
+            lf.sink_parquet(job.output.clone(), ParquetWriteOptions::default())?;
+            log::info!("Exported events to {}", job.output.display());
+        }
+
+        Ok(())
+    }
+
+    fn export(
+        &mut self,
+        files: &[PathBuf],
+        cut_handler: &CutHandler,
+        prepare: impl Fn(LazyFrame) -> LazyFrame,
+    ) {
+        let output = match self.mode {
+            ExportMode::Merged => rfd::FileDialog::new()
+                .set_file_name("events.parquet")
+                .add_filter("Parquet file", &["parquet"])
+                .save_file(),
+            ExportMode::PerRun => rfd::FileDialog::new().pick_folder(),
+        };
+
+        let Some(output) = output else {
+            return;
+        };
+
+        let gate = match &cut_handler.active_gate {
+            Some(name) => match cut_handler.gate_expr(name) {
+                Ok(expr) => Some(expr),
+                Err(e) => {
+                    log::error!("Failed to export events: {}", e);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        let jobs = match self.jobs(files, &output, prepare) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                log::error!("Failed to export events: {}", e);
+                return;
+            }
+        };
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        let columns = self.selected_columns.clone();
+        let running = self.running.clone();
+        running.store(true, Ordering::Relaxed);
+
+        std::thread::spawn(move || {
+            if let Err(e) = Self::run_jobs(jobs, gate, &columns) {
+                log::error!("Failed to export events: {}", e);
+            }
+            running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    fn columns_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Columns: {}",
+                if self.selected_columns.is_empty() {
+                    "all".to_string()
+                } else {
+                    self.selected_columns.len().to_string()
+                }
+            ));
+
+            if ui.button("All").clicked() {
+                self.selected_columns.clear();
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("event_export_columns")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for column in &self.available_columns {
+                    let mut selected = self.selected_columns.contains(column);
+                    if ui.checkbox(&mut selected, column).changed() {
+                        if selected {
+                            self.selected_columns.push(column.clone());
+                        } else {
+                            self.selected_columns.retain(|c| c != column);
+                        }
+                    }
+                }
+            });
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        files: &[PathBuf],
+        cut_handler: &CutHandler,
+        prepare: impl Fn(LazyFrame) -> LazyFrame,
+    ) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.mode, ExportMode::Merged, "Merged")
+                .on_hover_text("Write the events of every file to one parquet file");
+            ui.radio_value(&mut self.mode, ExportMode::PerRun, "Per Run")
+                .on_hover_text("Write one parquet file per input file to a folder");
+        });
+
+        let empty_suffix = self.mode == ExportMode::PerRun && self.suffix.trim().is_empty();
+        if self.mode == ExportMode::PerRun {
+            ui.horizontal(|ui| {
+                ui.label("Suffix:");
+                ui.text_edit_singleline(&mut self.suffix);
+            });
+
+            if empty_suffix {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    "The suffix keeps the exports from replacing the inputs",
+                );
+            }
+        }
+
+        ui.label(format!(
+            "Gate: {}",
+            cut_handler.active_gate.as_deref().unwrap_or("None")
+        ));
+
+        self.columns_ui(ui);
+
+        ui.separator();
+
+        if self.is_running() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Exporting...");
+            });
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(200));
+        } else if ui
+            .add_enabled(
+                !files.is_empty() && !empty_suffix,
+                egui::Button::new("Export"),
+            )
+            .clicked()
+        {
+            self.export(files, cut_handler, prepare);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_per_run() {
+        let dir = std::env::temp_dir().join(format!("event_export_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut files = Vec::new();
+        for (run, values) in [(1, [1.0, 5.0, 6.0]), (2, [2.0, 7.0, 3.0])] {
+            let path = dir.join(format!("run_{}.parquet", run));
+            let mut df = df!("x" => values, "y" => [0.0, 0.0, 0.0]).unwrap();
+            ParquetWriter::new(std::fs::File::create(&path).unwrap())
+                .finish(&mut df)
+                .unwrap();
+            files.push(path);
+        }
+
+        let export = EventExport {
+            mode: ExportMode::PerRun,
+            ..Default::default()
+        };
+        let jobs = export
+            .jobs(&files, &dir, |lf| {
+                lf.with_column((col("x") * lit(2.0)).alias("x2"))
+            })
+            .unwrap();
+        EventExport::run_jobs(
+            jobs,
+            Some(col("x2").gt(lit(8.0))),
+            &["x".to_string(), "x2".to_string()],
+        )
+        .unwrap();
+
+        let exported =
+            LazyFrame::scan_parquet(dir.join("run_1_gated.parquet"), ScanArgsParquet::default())
+                .unwrap()
+                .collect()
+                .unwrap();
+        assert_eq!(exported.get_column_names(), vec!["x", "x2"]);
+        assert_eq!(exported.height(), 2);
+
+        // the inputs are never outputs
+        let no_suffix = EventExport {
+            mode: ExportMode::PerRun,
+            suffix: String::new(),
+            ..Default::default()
+        };
+        assert!(no_suffix.jobs(&files, &dir, |lf| lf).is_err());
+
+        let merged = EventExport::default();
+        assert!(merged.jobs(&files, &files[0], |lf| lf).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
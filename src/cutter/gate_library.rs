@@ -0,0 +1,213 @@
+use super::cut_handler::CutHandler;
+use super::cuts::{Cut, RangeCut};
+use super::gates::{Gate, GatedHistogram};
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Version of the gate library layout, bump when the fields change
+pub const GATE_LIBRARY_SCHEMA_VERSION: u32 = 1;
+
+/// Every named gate with its bound columns, saved to and loaded from a JSON file.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct GateLibrary {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub polygon_cuts: Vec<Cut>,
+    #[serde(default)]
+    pub range_cuts: Vec<RangeCut>,
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    #[serde(default)]
+    pub gated_histograms: Vec<GatedHistogram>,
+}
+
+impl GateLibrary {
+    pub fn from_cut_handler(cut_handler: &CutHandler) -> Self {
+        let (polygon_cuts, range_cuts) = cut_handler.all_cuts();
+        Self {
+            schema_version: GATE_LIBRARY_SCHEMA_VERSION,
+            polygon_cuts,
+            range_cuts,
+            gates: cut_handler.gates.clone(),
+            gated_histograms: cut_handler.gated_histograms.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let library: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+        if library.schema_version > GATE_LIBRARY_SCHEMA_VERSION {
+            return Err(format!(
+                "Gate library version {} is newer than the supported version {}",
+                library.schema_version, GATE_LIBRARY_SCHEMA_VERSION
+            ));
+        }
+
+        Ok(library)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = self.to_json().map_err(|e| e.to_string())?;
+        File::create(path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .map_err(|e| format!("Error writing {}: {:?}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Error reading {}: {:?}", path.display(), e))?;
+
+        Self::from_json(&contents)
+    }
+
+    pub fn len(&self) -> usize {
+        self.polygon_cuts.len() + self.range_cuts.len() + self.gates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Adds the imported items, an item with the name of an existing one replaces it or is skipped
+fn merge_by_name<T: Clone>(
+    existing: &mut Vec<T>,
+    imported: &[T],
+    name: impl Fn(&T) -> String,
+    overwrite: bool,
+) {
+    for item in imported {
+        match existing.iter().position(|e| name(e) == name(item)) {
+            Some(index) if overwrite => existing[index] = item.clone(),
+            Some(_) => {}
+            None => existing.push(item.clone()),
+        }
+    }
+}
+
+impl CutHandler {
+    /// Merges the gates of a library. The merged cuts are handed to the app to be placed on
+    /// their histograms, they are collected from there again.
+    pub fn merge_library(&mut self, library: &GateLibrary, overwrite: bool) {
+        merge_by_name(
+            &mut self.gates,
+            &library.gates,
+            |gate| gate.name.clone(),
+            overwrite,
+        );
+
+        for gated in &library.gated_histograms {
+            if !self.gated_histograms.contains(gated) {
+                self.gated_histograms.push(gated.clone());
+            }
+        }
+
+        let (mut cuts, mut range_cuts) = self.all_cuts();
+        merge_by_name(
+            &mut cuts,
+            &library.polygon_cuts,
+            |cut| cut.name().to_string(),
+            overwrite,
+        );
+
+        merge_by_name(
+            &mut range_cuts,
+            &library.range_cuts,
+            |cut| cut.name.clone(),
+            overwrite,
+        );
+
+        self.imported_cuts = Some((cuts, range_cuts));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutter::gates::GateKind;
+
+    fn range(name: &str, min: f64) -> Gate {
+        Gate::new(
+            name,
+            GateKind::Range {
+                column: "x".to_string(),
+                min,
+                max: 10.0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_round_trip_and_version() {
+        let mut handler = CutHandler::new();
+        handler.gates.push(range("A", 0.0));
+        handler.range_cuts.push(RangeCut::new("B", "e", 1.0, 2.0));
+
+        let json = GateLibrary::from_cut_handler(&handler).to_json().unwrap();
+        let library = GateLibrary::from_json(&json).unwrap();
+        assert_eq!(library.gates, handler.gates);
+        assert_eq!(library.range_cuts[0].range(), (1.0, 2.0));
+
+        let newer = json.replace(
+            &format!("\"schema_version\": {}", GATE_LIBRARY_SCHEMA_VERSION),
+            &format!("\"schema_version\": {}", GATE_LIBRARY_SCHEMA_VERSION + 1),
+        );
+        assert!(GateLibrary::from_json(&newer).is_err());
+    }
+
+    #[test]
+    fn test_merge_library() {
+        let mut handler = CutHandler::new();
+        handler.gates = vec![range("A", 0.0), range("B", 0.0)];
+
+        let library = GateLibrary {
+            gates: vec![range("B", 5.0), range("C", 5.0)],
+            ..Default::default()
+        };
+
+        let mut kept = handler.clone();
+        kept.merge_library(&library, false);
+        assert_eq!(
+            kept.gates,
+            vec![range("A", 0.0), range("B", 0.0), range("C", 5.0)]
+        );
+
+        handler.merge_library(&library, true);
+        assert_eq!(
+            handler.gates,
+            vec![range("A", 0.0), range("B", 5.0), range("C", 5.0)]
+        );
+    }
+
+    #[test]
+    fn test_merge_cuts_without_histogram() {
+        let mut cut = RangeCut::new("Lost", "e", 1.0, 2.0);
+        cut.histogram = "Missing".to_string();
+        let library = GateLibrary {
+            range_cuts: vec![cut],
+            ..Default::default()
+        };
+
+        let mut handler = CutHandler::new();
+        handler.merge_library(&library, false);
+
+        // no histogram takes the cut, the app collects nothing
+        let (cuts, range_cuts) = handler.imported_cuts.take().unwrap();
+        handler.keep_unplaced_cuts(cuts, range_cuts);
+        assert_eq!(handler.unplaced_range_cuts.len(), 1);
+        assert!(handler.gate_names().contains(&"Lost".to_string()));
+        assert!(handler.gate_expr("Lost").is_ok());
+
+        // saved again with the library and handed out for the next histograms
+        assert_eq!(GateLibrary::from_cut_handler(&handler).range_cuts.len(), 1);
+        assert_eq!(handler.all_cuts().1.len(), 1);
+    }
+}
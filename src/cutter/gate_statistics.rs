@@ -0,0 +1,296 @@
+use super::cut_handler::CutHandler;
+use crate::fitter::fit_results::csv_escape;
+
+use rfd::FileDialog;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use polars::prelude::*;
+
+/// Events of one input file and how many of them pass each gate.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct FileGateCounts {
+    pub file: String,
+    pub total: u64,
+    pub passed: Vec<u64>, // same order as the gates of the statistics
+}
+
+/// Gate counts from the last histogram calculation.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct GateStatistics {
+    pub gates: Vec<String>,
+    pub files: Vec<FileGateCounts>,
+}
+
+fn fraction(passed: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        passed as f64 / total as f64
+    }
+}
+
+impl GateStatistics {
+    // Name and number of rows of each file from the parquet metadata
+    pub fn file_rows(files: &[PathBuf]) -> Vec<(String, usize)> {
+        files
+            .iter()
+            .map(|path| {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let rows = File::open(path)
+                    .map_err(PolarsError::from)
+                    .and_then(|file| ParquetReader::new(file).num_rows())
+                    .unwrap_or_else(|e| {
+                        log::error!("Failed to read the row count of {}: {}", name, e);
+                        0
+                    });
+                (name, rows)
+            })
+            .collect()
+    }
+
+    /// Counts the events passing the given gates in one aggregation, grouped by the file
+    /// each row came from. The files have to be in the order they were scanned.
+    pub fn compute(
+        lf: &LazyFrame,
+        cut_handler: &CutHandler,
+        gates: &[String],
+        file_rows: &[(String, usize)],
+    ) -> Result<Self, PolarsError> {
+        let gates: Vec<(&String, Expr)> = gates
+            .iter()
+            .filter_map(|name| cut_handler.gate_expr(name).ok().map(|expr| (name, expr)))
+            .collect();
+
+        // rows are numbered across the files, a row belongs to the file whose range holds it
+        let mut file_index = lit(0u32);
+        let mut start = 0;
+        for (_, rows) in file_rows.iter().take(file_rows.len().saturating_sub(1)) {
+            start += rows;
+            file_index = file_index
+                + col("gate statistics row")
+                    .gt_eq(lit(start as u64))
+                    .cast(DataType::UInt32);
+        }
+
+        let mut aggregations = vec![count().alias("total")];
+        aggregations.extend(gates.iter().enumerate().map(|(index, (_, expr))| {
+            expr.clone()
+                .fill_null(lit(false))
+                .cast(DataType::UInt64)
+                .sum()
+                .alias(&format!("gate {}", index))
+        }));
+
+        let df = lf
+            .clone()
+            .with_row_count("gate statistics row", None)
+            .group_by([file_index.alias("file")])
+            .agg(aggregations)
+            .sort("file", SortOptions::default())
+            .collect()?;
+
+        let as_u64 = |column: &str| -> Result<Vec<u64>, PolarsError> {
+            Ok(df
+                .column(column)?
+                .cast(&DataType::UInt64)?
+                .u64()?
+                .into_iter()
+                .map(|v| v.unwrap_or(0))
+                .collect())
+        };
+
+        let file_indices = as_u64("file")?;
+        let totals = as_u64("total")?;
+        let passed: Vec<Vec<u64>> = (0..gates.len())
+            .map(|index| as_u64(&format!("gate {}", index)))
+            .collect::<Result<_, _>>()?;
+
+        let files = file_indices
+            .iter()
+            .enumerate()
+            .map(|(row, &file_index)| FileGateCounts {
+                file: file_rows
+                    .get(file_index as usize)
+                    .map(|(name, _)| name.clone())
+                    .unwrap_or("All files".to_string()),
+                total: totals[row],
+                passed: passed.iter().map(|counts| counts[row]).collect(),
+            })
+            .collect();
+
+        Ok(Self {
+            gates: gates.into_iter().map(|(name, _)| name.clone()).collect(),
+            files,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.files.iter().map(|f| f.total).sum()
+    }
+
+    pub fn passed(&self, gate: usize) -> u64 {
+        self.files.iter().map(|f| f.passed[gate]).sum()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "file,gate,passed,total,fraction\n".to_string();
+
+        let total = self.total();
+        for (index, gate) in self.gates.iter().enumerate() {
+            let passed = self.passed(index);
+            csv.push_str(&format!(
+                "all,{},{},{},{}\n",
+                csv_escape(gate),
+                passed,
+                total,
+                fraction(passed, total)
+            ));
+        }
+
+        for file in &self.files {
+            for (index, gate) in self.gates.iter().enumerate() {
+                let passed = file.passed[index];
+                csv.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    csv_escape(&file.file),
+                    csv_escape(gate),
+                    passed,
+                    file.total,
+                    fraction(passed, file.total)
+                ));
+            }
+        }
+
+        csv
+    }
+
+    fn export_csv(&self) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name("gate_statistics.csv")
+            .add_filter("CSV", &["csv"])
+            .save_file()
+        {
+            match File::create(path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.write_all(self.to_csv().as_bytes()) {
+                        log::error!("Error writing gate statistics: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error creating file: {:?}", e);
+                }
+            }
+        }
+    }
+
+    fn counts_grid(ui: &mut egui::Ui, id: &str, gates: &[String], passed: &[u64], total: u64) {
+        egui::Grid::new(id).striped(true).show(ui, |ui| {
+            ui.label("Gate");
+            ui.label("Passed");
+            ui.label("Total");
+            ui.label("Fraction");
+            ui.end_row();
+
+            for (gate, &passed) in gates.iter().zip(passed) {
+                ui.label(gate);
+                ui.label(passed.to_string());
+                ui.label(total.to_string());
+                ui.label(format!("{:.2} %", 100.0 * fraction(passed, total)));
+                ui.end_row();
+            }
+        });
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Statistics");
+            if ui.button("Export CSV").clicked() {
+                self.export_csv();
+            }
+        });
+
+        if self.gates.is_empty() {
+            ui.label("No gates were used when the histograms were calculated");
+            return;
+        }
+
+        let passed: Vec<u64> = (0..self.gates.len()).map(|i| self.passed(i)).collect();
+        Self::counts_grid(
+            ui,
+            "gate_statistics_all",
+            &self.gates,
+            &passed,
+            self.total(),
+        );
+
+        if self.files.len() > 1 {
+            ui.collapsing("Per File", |ui| {
+                for file in &self.files {
+                    ui.label(&file.file);
+                    Self::counts_grid(
+                        ui,
+                        &format!("gate_statistics_{}", file.file),
+                        &self.gates,
+                        &file.passed,
+                        file.total,
+                    );
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutter::gates::{Gate, GateKind};
+
+    #[test]
+    fn test_compute_per_file() {
+        let lf = df!("x" => [1.0, 5.0, 6.0, 2.0, 7.0]).unwrap().lazy();
+
+        let mut cut_handler = CutHandler::new();
+        cut_handler.gates.push(Gate::new(
+            "High",
+            GateKind::Range {
+                column: "x".to_string(),
+                min: 4.0,
+                max: 10.0,
+            },
+        ));
+
+        let file_rows = vec![
+            ("run_1.parquet".to_string(), 3),
+            ("run_2.parquet".to_string(), 2),
+        ];
+        let gates = vec!["High".to_string()];
+        let statistics = GateStatistics::compute(&lf, &cut_handler, &gates, &file_rows).unwrap();
+
+        assert_eq!(statistics.gates, vec!["High"]);
+        assert_eq!(statistics.total(), 5);
+        assert_eq!(statistics.passed(0), 3);
+        assert_eq!(statistics.files[0].file, "run_1.parquet");
+        assert_eq!(statistics.files[0].passed, vec![2]);
+        assert_eq!(statistics.files[1].total, 2);
+        assert_eq!(statistics.files[1].passed, vec![1]);
+
+        // names are quoted only where needed, with quotes doubled
+        let statistics = GateStatistics {
+            gates: vec!["E, \"low\"".to_string()],
+            files: vec![FileGateCounts {
+                file: "run_1.parquet".to_string(),
+                total: 4,
+                passed: vec![1],
+            }],
+        };
+        assert!(statistics
+            .to_csv()
+            .contains("run_1.parquet,\"E, \"\"low\"\"\",1,4,0.25\n"));
+    }
+}
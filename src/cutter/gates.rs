@@ -0,0 +1,422 @@
+use polars::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 6] = [
+        Comparison::Less,
+        Comparison::LessEqual,
+        Comparison::Greater,
+        Comparison::GreaterEqual,
+        Comparison::Equal,
+        Comparison::NotEqual,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        }
+    }
+
+    fn apply(&self, lhs: Expr, rhs: Expr) -> Expr {
+        match self {
+            Comparison::Less => lhs.lt(rhs),
+            Comparison::LessEqual => lhs.lt_eq(rhs),
+            Comparison::Greater => lhs.gt(rhs),
+            Comparison::GreaterEqual => lhs.gt_eq(rhs),
+            Comparison::Equal => lhs.eq(rhs),
+            Comparison::NotEqual => lhs.neq(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum GateKind {
+    Range {
+        column: String,
+        min: f64,
+        max: f64,
+    },
+    Condition {
+        column: String,
+        comparison: Comparison,
+        value: f64,
+    },
+    Compound {
+        formula: String, // e.g. "PID and not (Timing or \"X2 v X1 Cut 1\")"
+    },
+}
+
+impl GateKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GateKind::Range { .. } => "Range",
+            GateKind::Condition { .. } => "Condition",
+            GateKind::Compound { .. } => "Compound",
+        }
+    }
+}
+
+/// A named gate. Polygon cuts drawn on 2D histograms are gates as well, they are
+/// looked up by name next to these.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Gate {
+    pub name: String,
+    pub kind: GateKind,
+}
+
+impl Gate {
+    pub fn new(name: &str, kind: GateKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+        }
+    }
+
+    // Expression of a range or condition gate, compound gates are resolved by the cut handler
+    pub fn simple_expr(&self) -> Option<Expr> {
+        match &self.kind {
+            GateKind::Range { column, min, max } => {
+                let x = || col(column).cast(DataType::Float64);
+                Some(x().gt_eq(lit(*min)).and(x().lt(lit(*max))))
+            }
+            GateKind::Condition {
+                column,
+                comparison,
+                value,
+            } => Some(comparison.apply(col(column).cast(DataType::Float64), lit(*value))),
+            GateKind::Compound { .. } => None,
+        }
+    }
+
+    pub fn parameters_ui(&mut self, ui: &mut egui::Ui) {
+        match &mut self.kind {
+            GateKind::Range { column, min, max } => {
+                ui.add(
+                    egui::TextEdit::singleline(column)
+                        .hint_text("Column")
+                        .desired_width(120.0),
+                );
+                ui.add(egui::DragValue::new(min).speed(0.1).prefix("Min: "));
+                ui.add(egui::DragValue::new(max).speed(0.1).prefix("Max: "));
+            }
+            GateKind::Condition {
+                column,
+                comparison,
+                value,
+            } => {
+                ui.add(
+                    egui::TextEdit::singleline(column)
+                        .hint_text("Column")
+                        .desired_width(120.0),
+                );
+                egui::ComboBox::from_id_source(ui.id().with("comparison"))
+                    .width(40.0)
+                    .selected_text(comparison.symbol())
+                    .show_ui(ui, |ui| {
+                        for option in Comparison::ALL {
+                            ui.selectable_value(comparison, option, option.symbol());
+                        }
+                    });
+                ui.add(egui::DragValue::new(value).speed(0.1));
+            }
+            GateKind::Compound { formula } => {
+                ui.add(
+                    egui::TextEdit::singleline(formula)
+                        .hint_text("A and not (B or C)")
+                        .desired_width(240.0),
+                )
+                .on_hover_text("Combine gates by name with and, or, not and parentheses.\nQuote names with spaces: \"X2 v X1 Cut 1\"");
+            }
+        }
+    }
+}
+
+/// A gated copy of a histogram, filled next to the ungated one.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GatedHistogram {
+    pub histogram: String,
+    pub gate: String,
+}
+
+impl GatedHistogram {
+    pub fn name(&self) -> String {
+        format!("{} [{}]", self.histogram, self.gate)
+    }
+}
+
+/// Parsed compound gate formula.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateFormula {
+    Gate(String),
+    Not(Box<GateFormula>),
+    And(Box<GateFormula>, Box<GateFormula>),
+    Or(Box<GateFormula>, Box<GateFormula>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(formula: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = formula.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '&' => {
+                chars.next();
+                tokens.push(Token::And);
+            }
+            '|' => {
+                chars.next();
+                tokens.push(Token::Or);
+            }
+            '!' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("Missing closing quote".to_string()),
+                    }
+                }
+                tokens.push(Token::Name(name));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if is_name_char(c) {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                if word.is_empty() {
+                    return Err(format!("Unexpected character '{}'", c));
+                }
+
+                tokens.push(match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Name(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+// Name as written in a formula, quoted when it is not a plain word
+fn formula_name(name: &str) -> String {
+    let keyword = matches!(name.to_lowercase().as_str(), "and" | "or" | "not");
+    if name.is_empty() || keyword || !name.chars().all(is_name_char) {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Replaces the references to a gate in a formula, the rest of the text is kept as written.
+pub fn rename_in_formula(formula: &str, old: &str, new: &str) -> String {
+    let mut renamed = String::new();
+    let mut chars = formula.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            let mut name = String::new();
+            let mut closed = false;
+            for c in chars.by_ref() {
+                if c == '"' {
+                    closed = true;
+                    break;
+                }
+                name.push(c);
+            }
+
+            if closed && name == old {
+                renamed.push_str(&formula_name(new));
+            } else {
+                renamed.push('"');
+                renamed.push_str(&name);
+                if closed {
+                    renamed.push('"');
+                }
+            }
+        } else if is_name_char(c) {
+            let mut word = c.to_string();
+            while let Some(&c) = chars.peek() {
+                if !is_name_char(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+
+            if word == old {
+                renamed.push_str(&formula_name(new));
+            } else {
+                renamed.push_str(&word);
+            }
+        } else {
+            renamed.push(c);
+        }
+    }
+
+    renamed
+}
+
+// Recursive descent with the precedence not > and > or
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or(&mut self) -> Result<GateFormula, String> {
+        let mut formula = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            formula = GateFormula::Or(Box::new(formula), Box::new(self.and()?));
+        }
+        Ok(formula)
+    }
+
+    fn and(&mut self) -> Result<GateFormula, String> {
+        let mut formula = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            formula = GateFormula::And(Box::new(formula), Box::new(self.not()?));
+        }
+        Ok(formula)
+    }
+
+    fn not(&mut self) -> Result<GateFormula, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(GateFormula::Not(Box::new(self.not()?))),
+            Some(Token::Name(name)) => Ok(GateFormula::Gate(name)),
+            Some(Token::Open) => {
+                let formula = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(formula),
+                    _ => Err("Missing closing parenthesis".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Formula ends early".to_string()),
+        }
+    }
+}
+
+pub fn parse_formula(formula: &str) -> Result<GateFormula, String> {
+    let mut parser = Parser {
+        tokens: tokenize(formula)?,
+        position: 0,
+    };
+
+    let parsed = parser.or()?;
+    match parser.peek() {
+        None => Ok(parsed),
+        Some(token) => Err(format!("Unexpected {:?}", token)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate(name: &str) -> Box<GateFormula> {
+        Box::new(GateFormula::Gate(name.to_string()))
+    }
+
+    #[test]
+    fn test_parse_formula_precedence() {
+        assert_eq!(
+            parse_formula("A or not B and \"C 1\"").unwrap(),
+            GateFormula::Or(
+                gate("A"),
+                Box::new(GateFormula::And(
+                    Box::new(GateFormula::Not(gate("B"))),
+                    gate("C 1")
+                ))
+            )
+        );
+
+        assert_eq!(
+            parse_formula("!(A | B) & C").unwrap(),
+            GateFormula::And(
+                Box::new(GateFormula::Not(Box::new(GateFormula::Or(
+                    gate("A"),
+                    gate("B")
+                )))),
+                gate("C")
+            )
+        );
+
+        assert!(parse_formula("A and").is_err());
+        assert!(parse_formula("(A or B").is_err());
+        assert!(parse_formula("A B").is_err());
+    }
+
+    #[test]
+    fn test_rename_in_formula() {
+        assert_eq!(
+            rename_in_formula("A and (\"A\" or AB) & !A", "A", "Alpha 1"),
+            "\"Alpha 1\" and (\"Alpha 1\" or AB) & !\"Alpha 1\""
+        );
+        assert_eq!(rename_in_formula("\"C 1\" or B", "C 1", "C2"), "C2 or B");
+    }
+}
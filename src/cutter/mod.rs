@@ -1,2 +1,6 @@
-// pub mod cut_handler;
-pub mod egui_polygon;
+pub mod cut_handler;
+pub mod cuts;
+pub mod event_export;
+pub mod gate_library;
+pub mod gate_statistics;
+pub mod gates;
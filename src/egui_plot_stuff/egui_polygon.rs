@@ -28,8 +28,28 @@ pub struct EguiPolygon {
     is_dragging: bool,
     #[serde(skip)]
     dragged_vertex_index: Option<usize>,
+
+    #[serde(skip)]
+    editing: bool,
+    #[serde(default)]
+    pub snap_to_bins: bool,
+    #[serde(skip)]
+    pub bin_grid: Option<[f64; 4]>, // x min, x bin width, y min, y bin width used for snapping
+    #[serde(skip)]
+    undo_stack: Vec<Vec<[f64; 2]>>,
+    #[serde(skip)]
+    translating_from: Option<[f64; 2]>,
+    #[serde(skip)]
+    hovered_vertex: Option<usize>,
+    #[serde(skip)]
+    renaming_from: Option<String>, // name before the current edit of the name
 }
 
+// Number of edits that can be undone
+const MAX_UNDO: usize = 50;
+// Distance in pixels from a vertex the pointer has to be within to grab it
+const VERTEX_GRAB_DISTANCE: f64 = 10.0;
+
 impl Default for EguiPolygon {
     fn default() -> Self {
         EguiPolygon {
@@ -51,6 +71,14 @@ impl Default for EguiPolygon {
             interactive_dragging: true,
             is_dragging: false,
             dragged_vertex_index: None,
+
+            editing: false,
+            snap_to_bins: false,
+            bin_grid: None,
+            undo_stack: Vec::new(),
+            translating_from: None,
+            hovered_vertex: None,
+            renaming_from: None,
         }
     }
 }
@@ -84,7 +112,9 @@ impl EguiPolygon {
                 self.temp_vertex = None;
             }
 
-            if self.interactive_dragging && self.draw {
+            if self.editing && self.draw && !self.interactive_clicking {
+                self.edit_interactions(plot_response, [x_value, y_value]);
+            } else if self.interactive_dragging && self.draw {
                 if let Some(hovered_id) = plot_response.hovered_plot_item {
                     if hovered_id == Id::new(self.name.clone()) {
                         self.highlighted = true;
@@ -128,6 +158,276 @@ impl EguiPolygon {
             }
         } else if pointer_state.button_released(egui::PointerButton::Middle) {
             self.is_dragging = false;
+            self.translating_from = None;
+        }
+    }
+
+    /*
+    Edit mode:
+        click: insert a vertex into the closest edge
+        middle drag: move a vertex
+        shift + middle drag: move the whole polygon
+        delete/backspace: remove the hovered vertex
+        ctrl + z: undo
+    */
+    fn edit_interactions(&mut self, plot_response: &PlotResponse<()>, pointer: [f64; 2]) {
+        let scale = plot_response.transform.dpos_dvalue();
+        self.hovered_vertex = self.closest_vertex(pointer, scale, VERTEX_GRAB_DISTANCE);
+        self.highlighted = self.hovered_vertex.is_some();
+
+        let (pointer_state, shift, undo, delete) = plot_response.response.ctx.input(|i| {
+            (
+                i.pointer.clone(),
+                i.modifiers.shift,
+                i.modifiers.command && i.key_pressed(egui::Key::Z),
+                i.key_pressed(egui::Key::Delete) || i.key_pressed(egui::Key::Backspace),
+            )
+        });
+
+        if undo {
+            self.undo();
+        }
+
+        if pointer_state.button_pressed(egui::PointerButton::Middle) {
+            if shift {
+                self.save_undo();
+                self.translating_from = Some(pointer);
+            } else if let Some(index) = self.hovered_vertex {
+                self.save_undo();
+                self.is_dragging = true;
+                self.dragged_vertex_index = Some(index);
+            }
+        }
+
+        if self.is_dragging {
+            if let Some(index) = self.dragged_vertex_index {
+                self.move_vertex(index, pointer[0], pointer[1]);
+            }
+        }
+
+        if let Some(from) = self.translating_from {
+            let delta = self.snap_delta([pointer[0] - from[0], pointer[1] - from[1]]);
+            self.translate(delta[0], delta[1]);
+            self.translating_from = Some([from[0] + delta[0], from[1] + delta[1]]);
+        }
+
+        if pointer_state.button_released(egui::PointerButton::Middle) {
+            self.is_dragging = false;
+            self.dragged_vertex_index = None;
+            self.translating_from = None;
+        }
+
+        if plot_response.response.clicked() && self.hovered_vertex.is_none() {
+            self.insert_vertex_scaled(pointer[0], pointer[1], scale);
+        }
+
+        if delete {
+            if let Some(index) = self.hovered_vertex.take() {
+                self.remove_vertex(index);
+            }
+        }
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    pub fn set_editing(&mut self, editing: bool) {
+        self.editing = editing;
+        if !editing {
+            self.hovered_vertex = None;
+            self.highlighted = false;
+        }
+    }
+
+    fn save_undo(&mut self) {
+        self.undo_stack.push(self.vertices.clone());
+        if self.undo_stack.len() > MAX_UNDO {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(vertices) = self.undo_stack.pop() {
+            self.vertices = vertices;
+            self.hovered_vertex = None;
+        }
+    }
+
+    // Moves a point to the center of its bin when snapping is on
+    pub fn snap(&self, point: [f64; 2]) -> [f64; 2] {
+        match self.bin_grid {
+            Some([x_min, x_width, y_min, y_width])
+                if self.snap_to_bins && x_width > 0.0 && y_width > 0.0 =>
+            {
+                [
+                    x_min + (((point[0] - x_min) / x_width).floor() + 0.5) * x_width,
+                    y_min + (((point[1] - y_min) / y_width).floor() + 0.5) * y_width,
+                ]
+            }
+            _ => point,
+        }
+    }
+
+    // Rounds a translation to whole bins when snapping is on
+    fn snap_delta(&self, delta: [f64; 2]) -> [f64; 2] {
+        match self.bin_grid {
+            Some([_, x_width, _, y_width])
+                if self.snap_to_bins && x_width > 0.0 && y_width > 0.0 =>
+            {
+                [
+                    (delta[0] / x_width).round() * x_width,
+                    (delta[1] / y_width).round() * y_width,
+                ]
+            }
+            _ => delta,
+        }
+    }
+
+    // `scale` converts plot units to pixels so the distances match what the user sees
+    fn closest_vertex(&self, point: [f64; 2], scale: [f64; 2], max_distance: f64) -> Option<usize> {
+        self.vertices
+            .iter()
+            .map(|v| {
+                let dx = (v[0] - point[0]) * scale[0];
+                let dy = (v[1] - point[1]) * scale[1];
+                (dx * dx + dy * dy).sqrt()
+            })
+            .enumerate()
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    /// Index the point has to be inserted at to split the edge closest to it.
+    pub fn closest_edge(&self, point: [f64; 2], scale: [f64; 2]) -> Option<usize> {
+        let n = self.vertices.len();
+        if n < 2 {
+            return None;
+        }
+
+        let to_pixels = |v: [f64; 2]| [v[0] * scale[0], v[1] * scale[1]];
+        let p = to_pixels(point);
+
+        (0..n)
+            .map(|i| {
+                let a = to_pixels(self.vertices[i]);
+                let b = to_pixels(self.vertices[(i + 1) % n]);
+                let ab = [b[0] - a[0], b[1] - a[1]];
+                let length = ab[0] * ab[0] + ab[1] * ab[1];
+                let t = if length > 0.0 {
+                    (((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let dx = a[0] + t * ab[0] - p[0];
+                let dy = a[1] + t * ab[1] - p[1];
+                (i + 1, dx * dx + dy * dy)
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    pub fn insert_vertex(&mut self, x: f64, y: f64) {
+        self.insert_vertex_scaled(x, y, [1.0, 1.0]);
+    }
+
+    fn insert_vertex_scaled(&mut self, x: f64, y: f64, scale: [f64; 2]) {
+        self.save_undo();
+        let vertex = self.snap([x, y]);
+        match self.closest_edge(vertex, scale) {
+            Some(index) => self.vertices.insert(index, vertex),
+            None => self.vertices.push(vertex),
+        }
+    }
+
+    pub fn remove_vertex(&mut self, index: usize) {
+        if index < self.vertices.len() {
+            self.save_undo();
+            self.vertices.remove(index);
+        }
+    }
+
+    pub fn move_vertex(&mut self, index: usize, x: f64, y: f64) {
+        let vertex = self.snap([x, y]);
+        if let Some(v) = self.vertices.get_mut(index) {
+            *v = vertex;
+        }
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for vertex in self.vertices.iter_mut() {
+            vertex[0] += dx;
+            vertex[1] += dy;
+        }
+    }
+
+    fn vertex_table_ui(&mut self, ui: &mut Ui) {
+        let before = self.vertices.clone();
+        let mut edit_started = false;
+        let mut insert_after = None;
+        let mut remove = None;
+
+        egui::ScrollArea::vertical()
+            .id_source(format!("{} vertices", self.name))
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new(format!("{} vertex table", self.name))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("#");
+                        ui.label("X");
+                        ui.label("Y");
+                        ui.label("");
+                        ui.end_row();
+
+                        for (index, vertex) in self.vertices.iter_mut().enumerate() {
+                            ui.label(index.to_string());
+                            for value in vertex.iter_mut() {
+                                let response = ui.add(DragValue::new(value).speed(0.1));
+                                edit_started |= response.drag_started() || response.gained_focus();
+                            }
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .small_button("+")
+                                    .on_hover_text("Insert a vertex after this one")
+                                    .clicked()
+                                {
+                                    insert_after = Some(index);
+                                }
+                                if ui
+                                    .small_button("🗙")
+                                    .on_hover_text("Remove this vertex")
+                                    .clicked()
+                                {
+                                    remove = Some(index);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if edit_started {
+            self.undo_stack.push(before);
+        }
+
+        if let Some(index) = insert_after {
+            // halfway to the next vertex
+            let n = self.vertices.len();
+            let a = self.vertices[index];
+            let b = self.vertices[(index + 1) % n];
+            self.save_undo();
+            self.vertices
+                .insert(index + 1, [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0]);
+        }
+
+        if let Some(index) = remove {
+            self.remove_vertex(index);
         }
     }
 
@@ -168,7 +468,7 @@ impl EguiPolygon {
             plot_ui.polygon(polygon);
 
             // if the user can drag the vertices, draw the vertices
-            if self.interactive_dragging {
+            if self.interactive_dragging || self.editing {
                 let vertices_points = egui_plot::Points::new(self.vertices.clone())
                     .radius(5.0)
                     .color(self.stroke.color)
@@ -177,13 +477,35 @@ impl EguiPolygon {
 
                 plot_ui.points(vertices_points);
             }
+
+            if self.editing {
+                if let Some(vertex) = self.hovered_vertex.and_then(|i| self.vertices.get(i)) {
+                    plot_ui.points(
+                        egui_plot::Points::new(vec![*vertex])
+                            .radius(8.0)
+                            .color(self.stroke.color),
+                    );
+                }
+            }
         }
     }
 
-    pub fn menu_button(&mut self, ui: &mut Ui) {
+    /// Returns the old and new name once an edit of the name is finished.
+    pub fn menu_button(&mut self, ui: &mut Ui) -> Option<(String, String)> {
+        let mut renamed = None;
         ui.menu_button(self.name.to_string(), |ui| {
             ui.vertical(|ui| {
-                ui.text_edit_singleline(&mut self.name);
+                let response = ui.text_edit_singleline(&mut self.name);
+                if response.gained_focus() {
+                    self.renaming_from = Some(self.name.clone());
+                }
+                if response.lost_focus() {
+                    renamed = self
+                        .renaming_from
+                        .take()
+                        .filter(|old| *old != self.name)
+                        .map(|old| (old, self.name.clone()));
+                }
                 ui.checkbox(&mut self.draw, "Draw Polygon");
                 ui.checkbox(
                     &mut self.interactive_clicking,
@@ -228,10 +550,40 @@ impl EguiPolygon {
             });
 
             ui.separator();
-            if ui.button("Clear Vertices").clicked() {
-                self.clear_vertices();
+
+            let mut editing = self.editing;
+            if ui
+                .checkbox(&mut editing, "Edit Mode")
+                .on_hover_text("Click: insert vertex\nMiddle drag: move vertex\nShift + middle drag: move polygon\nDelete: remove hovered vertex\nCtrl + Z: undo")
+                .changed()
+            {
+                self.set_editing(editing);
             }
+
+            ui.add_enabled(
+                self.bin_grid.is_some(),
+                egui::Checkbox::new(&mut self.snap_to_bins, "Snap to Bin Centers"),
+            );
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.can_undo(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    self.undo();
+                }
+
+                if ui.button("Clear Vertices").clicked() {
+                    self.save_undo();
+                    self.clear_vertices();
+                }
+            });
+
+            ui.collapsing("Vertices", |ui| {
+                self.vertex_table_ui(ui);
+            });
         });
+        renamed
     }
 
     pub fn stroke_color_selection_buttons(&mut self, ui: &mut Ui) {
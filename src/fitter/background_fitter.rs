@@ -9,6 +9,8 @@ pub struct BackgroundFitter {
     pub model: FitModel,
     pub result: Option<FitResult>,
     pub fit_line: EguiLine,
+    #[serde(default)]
+    pub fixed_points: Option<Vec<[f64; 2]>>, // precomputed background, e.g. SNIP, used as is
 }
 
 impl BackgroundFitter {
@@ -19,10 +21,24 @@ impl BackgroundFitter {
             model,
             result: None,
             fit_line: EguiLine::new(egui::Color32::GREEN),
+            fixed_points: None,
         }
     }
 
+    // Background that is not fitted, the values are interpolated between the points
+    pub fn fixed(points: Vec<[f64; 2]>) -> Self {
+        let mut fitter = BackgroundFitter::new(Vec::new(), Vec::new(), FitModel::Linear);
+        fitter.fixed_points = Some(points);
+        fitter
+    }
+
     pub fn fit(&mut self) {
+        if let Some(points) = &self.fixed_points {
+            self.fit_line.points.clone_from(points);
+            self.fit_line.name = "Background".to_string();
+            return;
+        }
+
         match self.model {
             FitModel::Gaussian(_) => {
                 log::error!("Gaussian background fitting not yet implemented");
@@ -53,7 +69,29 @@ impl BackgroundFitter {
         self.fit_line.draw(plot_ui);
     }
 
+    // Linear interpolation of the fixed points, held constant past the ends
+    fn interpolate_fixed(points: &[[f64; 2]], x: f64) -> f64 {
+        let index = points.partition_point(|p| p[0] < x);
+        match (
+            index.checked_sub(1).map(|i| points[i]),
+            points.get(index).copied(),
+        ) {
+            (Some([x0, y0]), Some([x1, y1])) if x1 > x0 => y0 + (y1 - y0) * (x - x0) / (x1 - x0),
+            (_, Some([_, y])) | (Some([_, y]), None) => y,
+            (None, None) => 0.0,
+        }
+    }
+
     pub fn get_background(&self, x_data: &[f64]) -> Option<Vec<f64>> {
+        if let Some(points) = &self.fixed_points {
+            return Some(
+                x_data
+                    .iter()
+                    .map(|&x| Self::interpolate_fixed(points, x))
+                    .collect(),
+            );
+        }
+
         if let Some(FitResult::Linear(fitter)) = &self.result {
             Some(fitter.calculate_background(x_data))
         } else {
@@ -61,6 +99,19 @@ impl BackgroundFitter {
         }
     }
 
+    pub fn get_background_uncertainty(&self, x_data: &[f64]) -> Option<Vec<f64>> {
+        // a fixed background is treated as exact
+        if self.fixed_points.is_some() {
+            return Some(vec![0.0; x_data.len()]);
+        }
+
+        if let Some(FitResult::Linear(fitter)) = &self.result {
+            Some(fitter.calculate_background_uncertainty(x_data))
+        } else {
+            None
+        }
+    }
+
     pub fn get_slope_intercept(&self) -> Option<(f64, f64)> {
         if let Some(FitResult::Linear(fitter)) = &self.result {
             fitter
@@ -0,0 +1,306 @@
+use super::fit_handler::{FitMethod, FitModel, Fitter, WeightingMode};
+use super::fit_results::FitResultRow;
+
+/// The markers and settings of a stored fit, to repeat the same fit on other histograms.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FitTemplate {
+    pub name: String,
+    pub region: (f64, f64),
+    pub peak_markers: Vec<f64>,
+    pub background_markers: Vec<f64>,
+    pub method: FitMethod,
+    pub weighting: WeightingMode,
+}
+
+impl FitTemplate {
+    pub fn from_fitter(name: &str, fitter: &Fitter) -> Option<Self> {
+        if !matches!(fitter.model, FitModel::Gaussian(_)) {
+            log::error!("Only gaussian fits can be used as a fit template");
+            return None;
+        }
+
+        // fits from before the region was kept span the outer bin centers, widened to the bin edges
+        let region = match fitter.region {
+            Some(region) => region,
+            None => {
+                let (first, last) = (*fitter.x_data.first()?, *fitter.x_data.last()?);
+                let half_bin = match fitter.x_data.get(1) {
+                    Some(second) => (second - first) / 2.0,
+                    None => 0.0,
+                };
+                (first - half_bin, last + half_bin)
+            }
+        };
+
+        // fixed backgrounds have no markers, the region edges are used instead
+        let background_markers = fitter
+            .background
+            .as_ref()
+            .filter(|background| background.fixed_points.is_none())
+            .map(|background| background.x_data.clone())
+            .unwrap_or_default();
+
+        Some(Self {
+            name: name.to_string(),
+            region,
+            peak_markers: fitter.get_peak_markers(),
+            background_markers,
+            method: fitter.method,
+            weighting: fitter.weighting,
+        })
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "Region: {:.2} to {:.2}\nPeaks: {}\nBackground markers: {}\n{}, {}",
+            self.region.0,
+            self.region.1,
+            self.peak_markers
+                .iter()
+                .map(|x| format!("{:.2}", x))
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.background_markers.len(),
+            self.method.name(),
+            self.weighting.name(),
+        )
+    }
+}
+
+// Mean and sample standard deviation
+fn mean_and_spread(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let spread = if values.len() > 1 {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    (mean, spread)
+}
+
+/// Applies a fit template to a selection of histograms. The app fills in the
+/// available histograms and fits and handles the requests, since the histograms
+/// live in their own panes.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BatchFitter {
+    pub templates: Vec<FitTemplate>,
+    pub selected_template: Option<usize>,
+    pub selected_histograms: Vec<String>,
+    pub histogram_filter: String,
+
+    #[serde(skip)]
+    pub available_histograms: Vec<String>,
+    #[serde(skip)]
+    pub available_fits: Vec<(String, usize)>, // histogram name and stored fit index
+    #[serde(skip)]
+    pub template_request: Option<(String, usize)>,
+    #[serde(skip)]
+    pub apply_request: Option<(FitTemplate, Vec<String>)>,
+    #[serde(skip)]
+    pub results: Vec<FitResultRow>,
+}
+
+impl BatchFitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_template(&mut self, template: FitTemplate) {
+        self.templates.push(template);
+        self.selected_template = Some(self.templates.len() - 1);
+    }
+
+    fn templates_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Templates");
+
+        ui.menu_button("Create from stored fit", |ui| {
+            if self.available_fits.is_empty() {
+                ui.label("No stored fits");
+            }
+
+            for (histogram, fit) in &self.available_fits {
+                if ui.button(format!("{}: Fit {}", histogram, fit)).clicked() {
+                    self.template_request = Some((histogram.clone(), *fit));
+                    ui.close_menu();
+                }
+            }
+        });
+
+        let mut to_remove = None;
+        for (i, template) in self.templates.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.selected_template, Some(i), "")
+                    .on_hover_text(template.summary());
+                ui.text_edit_singleline(&mut template.name);
+                if ui.button("X").clicked() {
+                    to_remove = Some(i);
+                }
+            });
+        }
+
+        if let Some(index) = to_remove {
+            self.templates.remove(index);
+            self.selected_template = None;
+        }
+    }
+
+    fn histograms_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Histograms");
+
+        ui.horizontal(|ui| {
+            ui.label("Filter: ");
+            ui.text_edit_singleline(&mut self.histogram_filter);
+        });
+
+        let filter = self.histogram_filter.to_lowercase();
+        let visible: Vec<String> = self
+            .available_histograms
+            .iter()
+            .filter(|name| filter.is_empty() || name.to_lowercase().contains(&filter))
+            .cloned()
+            .collect();
+
+        ui.horizontal(|ui| {
+            if ui.button("Select All").clicked() {
+                for name in &visible {
+                    if !self.selected_histograms.contains(name) {
+                        self.selected_histograms.push(name.clone());
+                    }
+                }
+            }
+
+            if ui.button("Select None").clicked() {
+                self.selected_histograms
+                    .retain(|name| !visible.contains(name));
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("batch_fit_histograms")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for name in &visible {
+                    let mut selected = self.selected_histograms.contains(name);
+                    if ui.checkbox(&mut selected, name).changed() {
+                        if selected {
+                            self.selected_histograms.push(name.clone());
+                        } else {
+                            self.selected_histograms.retain(|n| n != name);
+                        }
+                    }
+                }
+            });
+    }
+
+    // Spread of the fitted values of each peak over the histograms of the last batch
+    fn summary_ui(&self, ui: &mut egui::Ui) {
+        let number_of_peaks = self.results.iter().map(|row| row.peak + 1).max();
+        let Some(number_of_peaks) = number_of_peaks else {
+            return;
+        };
+
+        ui.heading("Summary");
+
+        egui::Grid::new("batch_fit_summary")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Peak");
+                ui.label("Fits");
+                ui.label("Centroid (mean ± std)");
+                ui.label("FWHM (mean ± std)");
+                ui.label("Area (mean ± std)");
+                ui.end_row();
+
+                for peak in 0..number_of_peaks {
+                    let rows: Vec<&FitResultRow> =
+                        self.results.iter().filter(|row| row.peak == peak).collect();
+                    let column = |value: fn(&FitResultRow) -> f64| {
+                        let values: Vec<f64> = rows.iter().map(|row| value(row)).collect();
+                        let (mean, spread) = mean_and_spread(&values);
+                        format!("{:.2} ± {:.2}", mean, spread)
+                    };
+
+                    ui.label(format!("{}", peak));
+                    ui.label(format!("{}", rows.len()));
+                    ui.label(column(|row| row.centroid));
+                    ui.label(column(|row| row.fwhm));
+                    ui.label(column(|row| row.area));
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+
+        egui::Grid::new("batch_fit_results")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Histogram");
+                ui.label("Peak");
+                ui.label("Centroid");
+                ui.label("FWHM");
+                ui.label("Area");
+                ui.label("χ²/dof");
+                ui.end_row();
+
+                for row in &self.results {
+                    ui.label(&row.histogram);
+                    ui.label(format!("{}", row.peak));
+                    ui.label(format!(
+                        "{:.2} ± {:.2}",
+                        row.centroid, row.centroid_uncertainty
+                    ));
+                    ui.label(format!("{:.2} ± {:.2}", row.fwhm, row.fwhm_uncertainty));
+                    ui.label(format!("{:.2} ± {:.2}", row.area, row.area_uncertainty));
+                    ui.label(
+                        row.reduced_chi_squared
+                            .map_or(String::new(), |chi| format!("{:.2}", chi)),
+                    );
+                    ui.end_row();
+                }
+            });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            self.templates_ui(ui);
+
+            ui.separator();
+
+            self.histograms_ui(ui);
+
+            ui.separator();
+
+            let template = self.selected_template.and_then(|i| self.templates.get(i));
+            let enabled = template.is_some() && !self.selected_histograms.is_empty();
+
+            if ui
+                .add_enabled(enabled, egui::Button::new("Fit Selected Histograms"))
+                .on_hover_text("Fit the template in each selected histogram and store the fits")
+                .clicked()
+            {
+                if let Some(template) = template {
+                    self.apply_request = Some((template.clone(), self.selected_histograms.clone()));
+                }
+            }
+
+            ui.separator();
+
+            self.summary_ui(ui);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_and_spread() {
+        let (mean, spread) = mean_and_spread(&[1.0, 2.0, 3.0]);
+        assert!((mean - 2.0).abs() < 1e-12);
+        assert!((spread - 1.0).abs() < 1e-12);
+
+        assert_eq!(mean_and_spread(&[5.0]), (5.0, 0.0));
+    }
+}
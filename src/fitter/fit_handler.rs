@@ -3,8 +3,11 @@ use rfd::FileDialog;
 use std::fs::File;
 use std::io::{Read, Write};
 
+use super::fit_results::FitResultRow;
 use super::gaussian::GaussianFitter;
+use super::goodness_of_fit::GoodnessOfFit;
 use super::linear::LinearFitter;
+use super::peak_library::PeakLibrary;
 use crate::egui_plot_stuff::egui_line::EguiLine;
 
 use crate::fitter::background_fitter::BackgroundFitter;
@@ -15,6 +18,50 @@ pub enum FitModel {
     Linear,
 }
 
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum FitMethod {
+    #[default]
+    LeastSquares,
+    PoissonLikelihood, // Cash statistic, better for bins with few counts
+}
+
+impl FitMethod {
+    pub fn name(&self) -> &str {
+        match self {
+            FitMethod::LeastSquares => "Least Squares",
+            FitMethod::PoissonLikelihood => "Poisson Likelihood",
+        }
+    }
+}
+
+// The histograms are filled with unit weights, so Sumw2 only differs from Poisson in the
+// empty bins. It gives other uncertainties once weighted fills exist.
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum WeightingMode {
+    #[default]
+    Unweighted,
+    Poisson, // σ² = max(counts, 1), empty bins keep a small weight
+    Sumw2,   // σ² = sum of squared weights, empty bins are ignored
+}
+
+impl WeightingMode {
+    pub fn name(&self) -> &str {
+        match self {
+            WeightingMode::Unweighted => "Unweighted",
+            WeightingMode::Poisson => "Poisson",
+            WeightingMode::Sumw2 => "Sumw2",
+        }
+    }
+
+    // Name with what the mode does for the unit weight fills of the histograms
+    pub fn label(&self) -> &str {
+        match self {
+            WeightingMode::Sumw2 => "Sumw2 (Poisson, empty bins dropped)",
+            _ => self.name(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub enum FitResult {
     Gaussian(GaussianFitter),
@@ -26,11 +73,25 @@ pub struct Fitter {
     pub x_data: Vec<f64>,
     pub y_data: Vec<f64>,
     pub y_err: Option<Vec<f64>>,
+    #[serde(default)]
+    pub region: Option<(f64, f64)>, // region marker positions the fit was made with
     pub background: Option<BackgroundFitter>,
     pub model: FitModel,
+    #[serde(default)]
+    pub method: FitMethod,
+    #[serde(default)]
+    pub weighting: WeightingMode,
     pub result: Option<FitResult>,
     pub decomposition_lines: Vec<EguiLine>,
     pub composition_line: EguiLine,
+    #[serde(default)]
+    pub confidence_band: Vec<[f64; 3]>, // (x, lower, upper) of the composition ±1σ
+    #[serde(default)]
+    pub show_confidence_band: bool,
+    #[serde(default)]
+    pub note: String, // free text, e.g. the transition assigned to the peaks
+    #[serde(default = "Fitter::default_visible")]
+    pub visible: bool,
 }
 
 impl Fitter {
@@ -41,14 +102,25 @@ impl Fitter {
             x_data: Vec::new(),
             y_data: Vec::new(),
             y_err: None,
+            region: None,
             background,
             model,
+            method: FitMethod::default(),
+            weighting: WeightingMode::default(),
             result: None,
             decomposition_lines: Vec::new(),
             composition_line: EguiLine::default(),
+            confidence_band: Vec::new(),
+            show_confidence_band: true,
+            note: String::new(),
+            visible: true,
         }
     }
 
+    fn default_visible() -> bool {
+        true
+    }
+
     fn subtract_background(&self) -> Vec<f64> {
         if let Some(bg_fitter) = &self.background {
             if let Some(bg_result) = bg_fitter.get_background(&self.x_data) {
@@ -65,6 +137,159 @@ impl Fitter {
         }
     }
 
+    // Background values at each x_data point, zero if there is no background fit
+    fn get_background_values(&self) -> Vec<f64> {
+        self.background
+            .as_ref()
+            .and_then(|bg_fitter| bg_fitter.get_background(&self.x_data))
+            .unwrap_or_else(|| vec![0.0; self.x_data.len()])
+    }
+
+    // Bin uncertainties for the weighting mode, including the background subtraction error
+    fn calculate_y_err(&self) -> Option<Vec<f64>> {
+        let counts_variance: Vec<f64> = match self.weighting {
+            WeightingMode::Unweighted => return None,
+            WeightingMode::Poisson => self.y_data.iter().map(|&y| y.max(1.0)).collect(),
+            // histograms are filled with unit weights, so sumw2 is the bin count
+            WeightingMode::Sumw2 => self.y_data.iter().map(|&y| y.max(0.0)).collect(),
+        };
+
+        let background_uncertainty = self
+            .background
+            .as_ref()
+            .and_then(|bg_fitter| bg_fitter.get_background_uncertainty(&self.x_data))
+            .unwrap_or_else(|| vec![0.0; self.x_data.len()]);
+
+        Some(
+            counts_variance
+                .iter()
+                .zip(background_uncertainty.iter())
+                .map(|(&variance, &bg_err)| {
+                    if variance > 0.0 {
+                        (variance + bg_err * bg_err).sqrt()
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    // Uncertainty of each bin, using y_err if it is set, otherwise Poisson statistics
+    fn get_y_uncertainties(&self) -> Vec<f64> {
+        match &self.y_err {
+            Some(y_err) if y_err.len() == self.y_data.len() => y_err.clone(),
+            _ => GoodnessOfFit::poisson_uncertainties(&self.y_data),
+        }
+    }
+
+    // Compare the raw data with the full model (fit + background)
+    fn calculate_goodness_of_fit(
+        &self,
+        evaluate: impl Fn(f64) -> f64,
+        number_of_parameters: usize,
+    ) -> Option<GoodnessOfFit> {
+        let y_model: Vec<f64> = self
+            .x_data
+            .iter()
+            .zip(self.get_background_values())
+            .map(|(&x, bg)| evaluate(x) + bg)
+            .collect();
+
+        GoodnessOfFit::new(
+            &self.x_data,
+            &self.y_data,
+            &y_model,
+            &self.get_y_uncertainties(),
+            number_of_parameters,
+        )
+    }
+
+    // Composition ±1σ with the fit covariance and the background uncertainty added in quadrature
+    fn calculate_confidence_band(&self, fit: &GaussianFitter) -> Vec<[f64; 3]> {
+        if fit.covariance.is_none() {
+            return Vec::new();
+        }
+
+        // the band does not need the resolution of the composition line
+        let x_points: Vec<f64> = fit.composition_x_points().into_iter().step_by(15).collect();
+
+        let background = self
+            .background
+            .as_ref()
+            .and_then(|bg_fitter| bg_fitter.get_background(&x_points))
+            .unwrap_or_else(|| vec![0.0; x_points.len()]);
+        let background_uncertainty = self
+            .background
+            .as_ref()
+            .and_then(|bg_fitter| bg_fitter.get_background_uncertainty(&x_points))
+            .unwrap_or_else(|| vec![0.0; x_points.len()]);
+
+        x_points
+            .iter()
+            .zip(background.iter().zip(background_uncertainty.iter()))
+            .map(|(&x, (&bg, &bg_err))| {
+                let y = fit.evaluate(x) + bg;
+                let fit_err = fit.evaluate_uncertainty(x);
+                let err = (fit_err * fit_err + bg_err * bg_err).sqrt();
+                [x, y - err, y + err]
+            })
+            .collect()
+    }
+
+    pub fn goodness_of_fit(&self) -> Option<&GoodnessOfFit> {
+        match &self.result {
+            Some(FitResult::Gaussian(fit)) => fit.goodness_of_fit.as_ref(),
+            Some(FitResult::Linear(fit)) => fit.goodness_of_fit.as_ref(),
+            None => None,
+        }
+    }
+
+    // One row per fitted peak, used by the fit results table
+    pub fn result_rows(&self, histogram: &str, fit: usize) -> Vec<FitResultRow> {
+        let params = match &self.result {
+            Some(FitResult::Gaussian(fit)) => match &fit.fit_params {
+                Some(params) => params,
+                None => return Vec::new(),
+            },
+            _ => return Vec::new(),
+        };
+
+        let gof = self.goodness_of_fit();
+
+        params
+            .iter()
+            .enumerate()
+            .map(|(peak, param)| FitResultRow {
+                histogram: histogram.to_string(),
+                fit,
+                peak,
+                centroid: param.mean.value,
+                centroid_uncertainty: param.mean.uncertainty,
+                fwhm: param.fwhm.value,
+                fwhm_uncertainty: param.fwhm.uncertainty,
+                area: param.area.value,
+                area_uncertainty: param.area.uncertainty,
+                chi_squared: gof.map(|g| g.chi_squared),
+                degrees_of_freedom: gof.map(|g| g.degrees_of_freedom),
+                reduced_chi_squared: gof.map(|g| g.reduced_chi_squared),
+                method: self.method.name().to_string(),
+                weighting: self.weighting.name().to_string(),
+                note: self.note.clone(),
+                identification: param.identification.clone().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    // Tag each fitted peak with the library line closest to its centroid
+    pub fn identify_peaks(&mut self, library: &PeakLibrary) {
+        if let Some(FitResult::Gaussian(fit)) = &mut self.result {
+            for params in fit.fit_params.iter_mut().flatten() {
+                params.identification = library.identify(params.mean.value).map(|l| l.label());
+            }
+        }
+    }
+
     pub fn get_peak_markers(&self) -> Vec<f64> {
         if let Some(FitResult::Gaussian(fit)) = &self.result {
             fit.peak_markers.clone()
@@ -86,6 +311,9 @@ impl Fitter {
         // Perform the background subtraction if necessary
         let y_data_corrected = self.subtract_background();
 
+        // Bin uncertainties used as weights, recorded with the fit
+        self.y_err = self.calculate_y_err();
+
         // Perform the fit based on the model
         match &self.model {
             FitModel::Gaussian(peak_markers) => {
@@ -96,8 +324,21 @@ impl Fitter {
                     peak_markers.clone(),
                 );
 
+                if let Some(y_err) = &self.y_err {
+                    fit.set_uncertainties(y_err.clone());
+                }
+
                 fit.multi_gauss_fit();
 
+                if self.method == FitMethod::PoissonLikelihood && fit.fit_params.is_some() {
+                    fit.poisson_likelihood_fit(&self.y_data, &self.get_background_values());
+                }
+
+                if fit.fit_params.is_some() {
+                    fit.goodness_of_fit = self
+                        .calculate_goodness_of_fit(|x| fit.evaluate(x), fit.number_of_parameters());
+                }
+
                 // get the fit_lines and store them in the decomposition_lines
                 let decomposition_default_color = egui::Color32::from_rgb(255, 0, 255);
                 if let Some(fit_lines) = &fit.fit_lines {
@@ -113,10 +354,17 @@ impl Fitter {
 
                 // calculate the composition line
                 if let Some(background) = &self.background {
-                    if let Some((slope, intercept)) = background.get_slope_intercept() {
-                        let composition_points =
-                            fit.composition_fit_points_linear_bg(slope, intercept);
-
+                    let composition_points =
+                        if let Some((slope, intercept)) = background.get_slope_intercept() {
+                            Some(fit.composition_fit_points_linear_bg(slope, intercept))
+                        } else {
+                            let x_points = fit.composition_x_points();
+                            background
+                                .get_background(&x_points)
+                                .map(|bg| fit.composition_fit_points(&x_points, &bg))
+                        };
+
+                    if let Some(composition_points) = composition_points {
                         let mut line = EguiLine::new(egui::Color32::BLUE);
                         line.name = "Composition".to_string();
                         line.points = composition_points;
@@ -124,15 +372,26 @@ impl Fitter {
                     }
                 }
 
+                self.confidence_band = self.calculate_confidence_band(&fit);
+
                 self.result = Some(FitResult::Gaussian(fit));
             }
 
             FitModel::Linear => {
                 // Perform Linear fit
                 let mut fit = LinearFitter::new(self.x_data.clone(), y_data_corrected);
+                fit.y_err.clone_from(&self.y_err);
 
                 fit.perform_linear_fit();
 
+                if self.method == FitMethod::PoissonLikelihood && fit.fit_params.is_some() {
+                    fit.poisson_likelihood_fit(&self.y_data, &self.get_background_values());
+                }
+
+                if fit.fit_params.is_some() {
+                    fit.goodness_of_fit = self.calculate_goodness_of_fit(|x| fit.evaluate(x), 2);
+                }
+
                 self.result = Some(FitResult::Linear(fit));
             }
         }
@@ -147,6 +406,59 @@ impl Fitter {
         }
     }
 
+    // Least squares vs likelihood results, only for fits done with the Poisson likelihood
+    // The grids share an id, callers showing several fits give each its own `ui.push_id`
+    pub fn method_comparison_ui(&self, ui: &mut egui::Ui) {
+        match &self.result {
+            Some(FitResult::Gaussian(fit)) if fit.least_squares_params.is_some() => {
+                egui::Grid::new("method comparison")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Peak");
+                        ui.label("Mean (LS)");
+                        ui.label("Mean (ML)");
+                        ui.label("FWHM (LS)");
+                        ui.label("FWHM (ML)");
+                        ui.label("Area (LS)");
+                        ui.label("Area (ML)");
+                        ui.end_row();
+
+                        fit.method_comparison_ui(ui);
+                    });
+            }
+            Some(FitResult::Linear(fit)) if fit.least_squares_params.is_some() => {
+                egui::Grid::new("method comparison")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.label("LS");
+                        ui.label("ML");
+                        ui.end_row();
+
+                        fit.method_comparison_ui(ui);
+                    });
+            }
+            _ => {}
+        }
+    }
+
+    // Short description of how the fit was done
+    pub fn settings_summary(&self) -> String {
+        format!(
+            "Method: {}\nWeighting: {}",
+            self.method.name(),
+            self.weighting.name()
+        )
+    }
+
+    pub fn has_method_comparison(&self) -> bool {
+        match &self.result {
+            Some(FitResult::Gaussian(fit)) => fit.least_squares_params.is_some(),
+            Some(FitResult::Linear(fit)) => fit.least_squares_params.is_some(),
+            None => false,
+        }
+    }
+
     pub fn set_background_color(&mut self, color: egui::Color32) {
         if let Some(background) = &mut self.background {
             background.fit_line.color = color;
@@ -179,6 +491,16 @@ impl Fitter {
         }
     }
 
+    pub fn show_confidence_band(&mut self, show: bool) {
+        self.show_confidence_band = show;
+    }
+
+    // Name shown for a stored fit, also used as the prefix of its line names
+    pub fn rename(&mut self, name: String) {
+        self.set_name(name.clone());
+        self.name = name;
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.composition_line.name = format!("{}-Composition", name);
 
@@ -207,6 +529,10 @@ impl Fitter {
 
     // Draw the background, decomposition, and composition lines
     pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
+        if !self.visible {
+            return;
+        }
+
         // Draw the decomposition lines
         for line in &self.decomposition_lines {
             line.draw(plot_ui);
@@ -217,10 +543,70 @@ impl Fitter {
             background.draw(plot_ui);
         }
 
+        self.draw_confidence_band(plot_ui);
+
         // Draw the composition line
         self.composition_line.draw(plot_ui);
     }
 
+    // The band is drawn as one quad per segment since egui only fills convex polygons
+    fn draw_confidence_band(&self, plot_ui: &mut egui_plot::PlotUi) {
+        if !self.show_confidence_band || !self.composition_line.draw {
+            return;
+        }
+
+        let log_y = self.composition_line.log_y;
+        let log_x = self.composition_line.log_x;
+        let transform = |value: f64, log: bool| {
+            if log && value > 0.0 {
+                value.log10().max(0.0001)
+            } else {
+                value
+            }
+        };
+
+        let color = self.composition_line.color;
+        let fill = egui::Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 40);
+
+        for segment in self.confidence_band.windows(2) {
+            let [x0, low0, high0] = segment[0];
+            let [x1, low1, high1] = segment[1];
+            let (x0, x1) = (transform(x0, log_x), transform(x1, log_x));
+
+            let quad = vec![
+                [x0, transform(low0, log_y)],
+                [x1, transform(low1, log_y)],
+                [x1, transform(high1, log_y)],
+                [x0, transform(high0, log_y)],
+            ];
+
+            plot_ui.polygon(
+                egui_plot::Polygon::new(quad)
+                    .fill_color(fill)
+                    .stroke(egui::Stroke::NONE),
+            );
+        }
+    }
+
+    // Draw the residuals (or pulls) of the fit as points with stems to zero
+    pub fn draw_residuals(&self, plot_ui: &mut egui_plot::PlotUi, pulls: bool) {
+        if let Some(goodness_of_fit) = self.goodness_of_fit() {
+            let points = if pulls {
+                goodness_of_fit.pulls.clone()
+            } else {
+                goodness_of_fit.residuals.clone()
+            };
+
+            let residual_points = egui_plot::Points::new(points)
+                .color(self.composition_line.color)
+                .radius(2.0)
+                .stems(0.0)
+                .name(self.composition_line.name.clone());
+
+            plot_ui.points(residual_points);
+        }
+    }
+
     // Set the log_y flag for all lines
     pub fn set_log(&mut self, log_y: bool, log_x: bool) {
         for line in &mut self.decomposition_lines {
@@ -245,6 +631,16 @@ pub struct FitSettings {
     pub show_background: bool,
     pub show_fit_stats: bool,
     pub fit_stats_height: f32,
+    #[serde(default)]
+    pub show_residuals: bool,
+    #[serde(default)]
+    pub residuals_as_pulls: bool,
+    #[serde(default)]
+    pub fit_method: FitMethod,
+    #[serde(default)]
+    pub weighting: WeightingMode,
+    #[serde(default = "FitSettings::default_show_confidence_band")]
+    pub show_confidence_band: bool,
 }
 
 impl Default for FitSettings {
@@ -255,12 +651,45 @@ impl Default for FitSettings {
             show_background: true,
             show_fit_stats: false,
             fit_stats_height: 0.0,
+            show_residuals: false,
+            residuals_as_pulls: false,
+            fit_method: FitMethod::default(),
+            weighting: WeightingMode::default(),
+            show_confidence_band: true,
         }
     }
 }
 
 impl FitSettings {
+    fn default_show_confidence_band() -> bool {
+        true
+    }
+
     pub fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Fit Method: ");
+            for method in [FitMethod::LeastSquares, FitMethod::PoissonLikelihood] {
+                ui.radio_value(&mut self.fit_method, method, method.name());
+            }
+        })
+        .response
+        .on_hover_text("Method used for new fits. Poisson likelihood (Cash statistic) is unbiased for bins with few counts and is compared against the least squares result.");
+
+        ui.horizontal(|ui| {
+            ui.label("Weighting: ");
+            for weighting in [
+                WeightingMode::Unweighted,
+                WeightingMode::Poisson,
+                WeightingMode::Sumw2,
+            ] {
+                ui.radio_value(&mut self.weighting, weighting, weighting.label());
+            }
+        })
+        .response
+        .on_hover_text("Bin uncertainties used as least squares weights for new fits, including the background subtraction error.\nPoisson: empty bins use an uncertainty of 1\nSumw2: empty bins are ignored, otherwise the same as Poisson for unweighted fills");
+
+        ui.separator();
+
         ui.horizontal(|ui| {
             ui.label("Fit Stats: ");
             ui.checkbox(&mut self.show_fit_stats, "Show")
@@ -286,16 +715,49 @@ impl FitSettings {
                 .on_hover_text("Show the composition line");
             ui.checkbox(&mut self.show_background, "Background")
                 .on_hover_text("Show the background line");
+            ui.checkbox(&mut self.show_confidence_band, "Confidence Band")
+                .on_hover_text(
+                    "Show the ±1σ band of the composition line from the full fit covariance",
+                );
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Residuals: ");
+            ui.checkbox(&mut self.show_residuals, "Show")
+                .on_hover_text("Show the residuals of the current fit and the selected stored fit below the histogram");
+            ui.checkbox(&mut self.residuals_as_pulls, "Pulls")
+                .on_hover_text("Divide the residuals by the bin uncertainty");
         });
     }
 }
 
+/// The stored fit being refit as the temp fit. It stays in the stored fits until the
+/// temp fit replaces it, so discarding the refit leaves it as it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReopenedFit {
+    pub index: usize,
+    pub method: FitMethod, // used for the refit instead of the histogram settings
+    pub weighting: WeightingMode,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Fits {
     pub temp_fit: Option<Fitter>,
     pub temp_background_fit: Option<BackgroundFitter>,
+    #[serde(default)]
+    pub temp_cluster_fits: Vec<Fitter>, // one per peak cluster from the automatic peak search
     pub stored_fits: Vec<Fitter>,
     pub settings: FitSettings,
+    #[serde(default)]
+    pub selected_fit: Option<usize>,
+    #[serde(skip)]
+    pub reopen_request: Option<usize>, // stored fit to move back to the temp fit, handled by the histogram
+    #[serde(skip)]
+    pub reopened: Option<ReopenedFit>, // replaced by the temp fit when it is stored
+    #[serde(skip)]
+    renaming: Option<(usize, String)>, // stored fit whose name is being edited and its name before
 }
 
 impl Default for Fits {
@@ -309,25 +771,122 @@ impl Fits {
         Fits {
             temp_fit: None,
             temp_background_fit: None,
+            temp_cluster_fits: Vec::new(),
             stored_fits: Vec::new(),
             settings: FitSettings::default(),
+            selected_fit: None,
+            reopen_request: None,
+            reopened: None,
+            renaming: None,
         }
     }
 
     pub fn store_temp_fit(&mut self) {
-        if let Some(temp_fit) = &mut self.temp_fit.take() {
-            temp_fit.set_background_color(egui::Color32::DARK_GREEN);
-            temp_fit.set_composition_color(egui::Color32::DARK_BLUE);
-            temp_fit.set_decomposition_color(egui::Color32::from_rgb(150, 0, 255));
-
-            temp_fit.set_name(format!("Fit {}", self.stored_fits.len()));
+        // a reopened fit is replaced in place and keeps its name, note and visibility
+        if let Some(mut temp_fit) = self.temp_fit.take() {
+            let reopened = self
+                .reopened
+                .take()
+                .filter(|reopened| reopened.index < self.stored_fits.len());
+
+            match reopened {
+                Some(ReopenedFit { index, .. }) => {
+                    let original = &self.stored_fits[index];
+                    temp_fit.note = original.note.clone();
+                    temp_fit.visible = original.visible;
+                    let name = original.name.clone();
+                    self.stored_fits[index] = Self::stored_colors(temp_fit, name);
+                }
+                None => {
+                    let name = self.unique_fit_name();
+                    self.stored_fits.push(Self::stored_colors(temp_fit, name));
+                }
+            }
+        }
 
-            self.stored_fits.push(temp_fit.clone());
+        let cluster_fits: Vec<Fitter> = self.temp_cluster_fits.drain(..).collect();
+        for temp_fit in cluster_fits {
+            let name = self.unique_fit_name();
+            self.stored_fits.push(Self::stored_colors(temp_fit, name));
         }
 
         self.temp_background_fit = None;
     }
 
+    fn stored_colors(mut fit: Fitter, name: String) -> Fitter {
+        fit.set_background_color(egui::Color32::DARK_GREEN);
+        fit.set_composition_color(egui::Color32::DARK_BLUE);
+        fit.set_decomposition_color(egui::Color32::from_rgb(150, 0, 255));
+        fit.rename(name);
+        fit
+    }
+
+    // The line names are the plot ids, so stored fits need distinct names
+    fn unique_fit_name(&self) -> String {
+        (self.stored_fits.len()..)
+            .map(|n| format!("Fit {}", n))
+            .find(|name| !self.stored_fits.iter().any(|fit| &fit.name == name))
+            .unwrap_or_default()
+    }
+
+    // Remove a stored fit, keeping the selection on the same fit if it is still there
+    pub fn remove_stored_fit(&mut self, index: usize) -> Option<Fitter> {
+        if index >= self.stored_fits.len() {
+            return None;
+        }
+
+        self.selected_fit = match self.selected_fit {
+            Some(selected) if selected == index => None,
+            Some(selected) if selected > index => Some(selected - 1),
+            selected => selected,
+        };
+
+        // a refit of the removed fit is stored as a new fit
+        self.reopened = match self.reopened.take() {
+            Some(reopened) if reopened.index == index => None,
+            Some(reopened) if reopened.index > index => Some(ReopenedFit {
+                index: reopened.index - 1,
+                ..reopened
+            }),
+            reopened => reopened,
+        };
+
+        Some(self.stored_fits.remove(index))
+    }
+
+    // Copy of a stored fit as the temp fit, with the temp fit colors
+    pub fn reopen_stored_fit(&mut self, index: usize) -> Option<&Fitter> {
+        let mut fit = self.stored_fits.get(index)?.clone();
+
+        fit.set_background_color(egui::Color32::GREEN);
+        fit.set_composition_color(egui::Color32::BLUE);
+        fit.set_decomposition_color(egui::Color32::from_rgb(255, 0, 255));
+
+        self.reopened = Some(ReopenedFit {
+            index,
+            method: fit.method,
+            weighting: fit.weighting,
+        });
+        fit.visible = true;
+
+        self.temp_cluster_fits.clear();
+        self.temp_background_fit = fit.background.clone();
+        self.temp_fit = Some(fit);
+
+        self.temp_fit.as_ref()
+    }
+
+    pub fn identify_peaks(&mut self, library: &PeakLibrary) {
+        for fit in self
+            .temp_fit
+            .iter_mut()
+            .chain(self.temp_cluster_fits.iter_mut())
+            .chain(self.stored_fits.iter_mut())
+        {
+            fit.identify_peaks(library);
+        }
+    }
+
     pub fn set_log(&mut self, log_y: bool, log_x: bool) {
         if let Some(temp_fit) = &mut self.temp_fit {
             temp_fit.set_log(log_y, log_x);
@@ -338,6 +897,10 @@ impl Fits {
             temp_background_fit.fit_line.log_x = log_x;
         }
 
+        for fit in &mut self.temp_cluster_fits {
+            fit.set_log(log_y, log_x);
+        }
+
         for fit in &mut self.stored_fits {
             fit.set_log(log_y, log_x);
         }
@@ -370,12 +933,21 @@ impl Fits {
             temp_fit.show_decomposition(self.settings.show_decomposition);
             temp_fit.show_composition(self.settings.show_composition);
             temp_fit.show_background(self.settings.show_background);
+            temp_fit.show_confidence_band(self.settings.show_confidence_band);
+        }
+
+        for fit in &mut self.temp_cluster_fits {
+            fit.show_decomposition(self.settings.show_decomposition);
+            fit.show_composition(self.settings.show_composition);
+            fit.show_background(self.settings.show_background);
+            fit.show_confidence_band(self.settings.show_confidence_band);
         }
 
         for fit in &mut self.stored_fits {
             fit.show_decomposition(self.settings.show_decomposition);
             fit.show_composition(self.settings.show_composition);
             fit.show_background(self.settings.show_background);
+            fit.show_confidence_band(self.settings.show_confidence_band);
         }
     }
 
@@ -437,6 +1009,13 @@ impl Fits {
     pub fn remove_temp_fits(&mut self) {
         self.temp_fit = None;
         self.temp_background_fit = None;
+        self.temp_cluster_fits.clear();
+    }
+
+    // Throw away the temp fits, a reopened fit is not stored again
+    pub fn discard_temp_fits(&mut self) {
+        self.remove_temp_fits();
+        self.reopened = None;
     }
 
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
@@ -450,14 +1029,25 @@ impl Fits {
             temp_background_fit.draw(plot_ui);
         }
 
-        for fit in &mut self.stored_fits.iter() {
+        for fit in &self.temp_cluster_fits {
             fit.draw(plot_ui);
         }
+
+        // the reopened fit is drawn as the temp fit
+        let reopened = self.reopened.as_ref().map(|reopened| reopened.index);
+        for (index, fit) in self.stored_fits.iter().enumerate() {
+            if Some(index) != reopened {
+                fit.draw(plot_ui);
+            }
+        }
     }
 
     pub fn fit_stats_grid_ui(&mut self, ui: &mut egui::Ui) {
         // only show the grid if there is something to show
-        if self.temp_fit.is_none() && self.stored_fits.is_empty() {
+        if self.temp_fit.is_none()
+            && self.temp_cluster_fits.is_empty()
+            && self.stored_fits.is_empty()
+        {
             return;
         }
 
@@ -471,20 +1061,47 @@ impl Fits {
                 ui.label("Mean");
                 ui.label("FWHM");
                 ui.label("Area");
+                ui.label("χ²/dof");
                 ui.end_row();
 
                 if self.temp_fit.is_some() {
-                    ui.label("Current");
+                    ui.label("Current").on_hover_text(
+                        self.temp_fit
+                            .as_ref()
+                            .map_or(String::new(), |fit| fit.settings_summary()),
+                    );
 
                     if let Some(temp_fit) = &self.temp_fit {
                         temp_fit.fitter_stats(ui);
                     }
                 }
 
+                for (i, fit) in self.temp_cluster_fits.iter().enumerate() {
+                    ui.label(format!("Current {}", i))
+                        .on_hover_text(fit.settings_summary());
+                    fit.fitter_stats(ui);
+                }
+
                 if !self.stored_fits.is_empty() {
                     for (i, fit) in self.stored_fits.iter().enumerate() {
                         ui.horizontal(|ui| {
-                            ui.label(format!("{}", i));
+                            let is_selected = self.selected_fit == Some(i);
+                            let note = if fit.note.is_empty() {
+                                String::new()
+                            } else {
+                                format!("{}\n", fit.note)
+                            };
+                            if ui
+                                .selectable_label(is_selected, &fit.name)
+                                .on_hover_text(format!(
+                                    "{}{}\nSelect to show the residuals of this fit",
+                                    note,
+                                    fit.settings_summary()
+                                ))
+                                .clicked()
+                            {
+                                self.selected_fit = if is_selected { None } else { Some(i) };
+                            }
 
                             ui.separator();
 
@@ -500,10 +1117,188 @@ impl Fits {
             });
 
         if let Some(index) = to_remove {
-            self.stored_fits.remove(index);
+            self.remove_stored_fit(index);
         }
     }
 
+    // Rename, annotate, hide, reopen or delete single stored fits
+    pub fn stored_fits_ui(&mut self, ui: &mut egui::Ui) {
+        if self.stored_fits.is_empty() {
+            return;
+        }
+
+        let mut to_remove = None;
+        let mut renamed = None;
+
+        ui.collapsing("Stored Fits", |ui| {
+            egui::Grid::new("stored_fits_editor")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Show");
+                    ui.label("Name");
+                    ui.label("Note");
+                    ui.label("Method");
+                    ui.end_row();
+
+                    for (i, fit) in self.stored_fits.iter_mut().enumerate() {
+                        ui.checkbox(&mut fit.visible, "");
+
+                        // the lines follow the name once the edit is done
+                        let response = ui
+                            .add(egui::TextEdit::singleline(&mut fit.name).desired_width(80.0));
+                        if response.gained_focus() {
+                            self.renaming = Some((i, fit.name.clone()));
+                        }
+                        if response.lost_focus() {
+                            renamed = Some(i);
+                        }
+
+                        ui.add(
+                            egui::TextEdit::singleline(&mut fit.note)
+                                .hint_text("e.g. 2+ → 0+, 1332 keV")
+                                .desired_width(160.0),
+                        );
+
+                        // the reopened fit picks the method and weighting of its refit here
+                        match &mut self.reopened {
+                            Some(reopened) if reopened.index == i => {
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_id_source(("refit method", i))
+                                        .selected_text(reopened.method.name())
+                                        .show_ui(ui, |ui| {
+                                            for method in
+                                                [FitMethod::LeastSquares, FitMethod::PoissonLikelihood]
+                                            {
+                                                ui.selectable_value(
+                                                    &mut reopened.method,
+                                                    method,
+                                                    method.name(),
+                                                );
+                                            }
+                                        });
+                                    egui::ComboBox::from_id_source(("refit weighting", i))
+                                        .selected_text(reopened.weighting.label())
+                                        .show_ui(ui, |ui| {
+                                            for weighting in [
+                                                WeightingMode::Unweighted,
+                                                WeightingMode::Poisson,
+                                                WeightingMode::Sumw2,
+                                            ] {
+                                                ui.selectable_value(
+                                                    &mut reopened.weighting,
+                                                    weighting,
+                                                    weighting.label(),
+                                                );
+                                            }
+                                        });
+                                })
+                                .response
+                                .on_hover_text("Method and weighting of the refit");
+                            }
+                            _ => {
+                                ui.label(format!("{}, {}", fit.method.name(), fit.weighting.name()));
+                            }
+                        }
+
+                        if ui
+                            .button("Reopen")
+                            .on_hover_text(
+                                "Move this fit back to the current fit and restore its markers to refit it, with the method and weighting chosen in its row",
+                            )
+                            .clicked()
+                        {
+                            self.reopen_request = Some(i);
+                        }
+
+                        if ui.button("X").on_hover_text("Delete this fit").clicked() {
+                            to_remove = Some(i);
+                        }
+
+                        ui.end_row();
+                    }
+                });
+        });
+
+        if let Some(index) = renamed {
+            self.finish_rename(index);
+        }
+
+        if let Some(index) = to_remove {
+            self.remove_stored_fit(index);
+        }
+    }
+
+    // A name that is empty or used by another stored fit goes back to the name before the edit
+    fn finish_rename(&mut self, index: usize) {
+        let Some((renaming_index, old)) = self.renaming.take() else {
+            return;
+        };
+        if renaming_index != index || index >= self.stored_fits.len() {
+            return;
+        }
+
+        let name = self.stored_fits[index].name.clone();
+        let taken = self
+            .stored_fits
+            .iter()
+            .enumerate()
+            .any(|(i, fit)| i != index && fit.name == name);
+
+        if name.is_empty() || taken {
+            log::error!("Fit name '{}' is already used or empty", name);
+            self.stored_fits[index].name = old;
+        } else {
+            self.stored_fits[index].rename(name);
+        }
+    }
+
+    pub fn result_rows(&self, histogram: &str) -> Vec<FitResultRow> {
+        self.stored_fits
+            .iter()
+            .enumerate()
+            .flat_map(|(i, fit)| fit.result_rows(histogram, i))
+            .collect()
+    }
+
+    // Returns true if a temp fit or the selected stored fit has residuals to show
+    pub fn has_residuals(&self) -> bool {
+        let temp = self
+            .temp_fit
+            .iter()
+            .chain(self.temp_cluster_fits.iter())
+            .any(|fit| fit.goodness_of_fit().is_some());
+        let selected = self
+            .selected_fit
+            .and_then(|i| self.stored_fits.get(i))
+            .map_or(false, |fit| fit.goodness_of_fit().is_some());
+
+        temp || selected
+    }
+
+    // Residual plot drawn under the histogram, x axis linked to the histogram plot
+    pub fn residual_plot_ui(&self, ui: &mut egui::Ui, name: &str, link_id: egui::Id) {
+        let pulls = self.settings.residuals_as_pulls;
+
+        let plot = egui_plot::Plot::new(format!("{} Residuals", name))
+            .link_axis(link_id, true, false)
+            .link_cursor(link_id, true, false)
+            .y_axis_label(if pulls { "Pull" } else { "Residual" })
+            .include_y(0.0)
+            .allow_boxed_zoom(false);
+
+        plot.show(ui, |plot_ui| {
+            plot_ui.hline(egui_plot::HLine::new(0.0).color(egui::Color32::GRAY));
+
+            for temp_fit in self.temp_fit.iter().chain(self.temp_cluster_fits.iter()) {
+                temp_fit.draw_residuals(plot_ui, pulls);
+            }
+
+            if let Some(fit) = self.selected_fit.and_then(|i| self.stored_fits.get(i)) {
+                fit.draw_residuals(plot_ui, pulls);
+            }
+        });
+    }
+
     pub fn fit_stats_ui(&mut self, ui: &mut egui::Ui) {
         if self.settings.show_fit_stats {
             egui::ScrollArea::vertical()
@@ -525,6 +1320,10 @@ impl Fits {
                     temp_fit.lines_ui(ui);
                 }
 
+                for fit in &mut self.temp_cluster_fits {
+                    fit.lines_ui(ui);
+                }
+
                 for fit in &mut self.stored_fits {
                     fit.lines_ui(ui);
                 }
@@ -542,6 +1341,10 @@ impl Fits {
 
             ui.separator();
 
+            self.stored_fits_ui(ui);
+
+            ui.separator();
+
             egui::ScrollArea::vertical()
                 .max_height(300.0)
                 .id_source("Context menu fit stats grid")
@@ -551,6 +1354,8 @@ impl Fits {
 
             ui.separator();
 
+            self.method_comparison_ui(ui);
+
             egui::ScrollArea::vertical()
                 .max_height(300.0)
                 .show(ui, |ui| {
@@ -558,4 +1363,128 @@ impl Fits {
                 });
         });
     }
+
+    pub fn method_comparison_ui(&self, ui: &mut egui::Ui) {
+        let temp_fit = self.temp_fit.iter().map(|fit| ("Current".to_string(), fit));
+        let stored_fits = self.stored_fits.iter().map(|fit| (fit.name.clone(), fit));
+
+        let fits: Vec<(String, &Fitter)> = temp_fit
+            .chain(stored_fits)
+            .filter(|(_, fit)| fit.has_method_comparison())
+            .collect();
+
+        if fits.is_empty() {
+            return;
+        }
+
+        ui.collapsing("Least Squares vs Poisson Likelihood", |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .id_source("Context menu method comparison")
+                .show(ui, |ui| {
+                    // the temp fit and a stored fit can have the same line name
+                    for (index, (label, fit)) in fits.into_iter().enumerate() {
+                        ui.push_id(index, |ui| {
+                            ui.label(label);
+                            fit.method_comparison_ui(ui);
+                        });
+                        ui.separator();
+                    }
+                });
+        });
+
+        ui.separator();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(name: &str) -> Fitter {
+        let mut fit = Fitter::new(FitModel::Gaussian(vec![1.0]), None);
+        fit.rename(name.to_string());
+        fit
+    }
+
+    #[test]
+    fn test_remove_and_reopen_stored_fits() {
+        let mut fits = Fits::new();
+        fits.stored_fits = vec![stored("Fit 0"), stored("Fit 1"), stored("Fit 2")];
+        fits.selected_fit = Some(2);
+
+        fits.remove_stored_fit(0);
+        assert_eq!(fits.selected_fit, Some(1));
+        assert_eq!(fits.stored_fits[1].name, "Fit 2");
+
+        // the reopened fit stays stored while it is refit
+        fits.reopen_stored_fit(1);
+        assert_eq!(fits.stored_fits.len(), 2);
+
+        fits.store_temp_fit();
+        assert_eq!(fits.stored_fits.len(), 2);
+        assert_eq!(fits.stored_fits[1].name, "Fit 2");
+        assert_eq!(
+            fits.stored_fits[1].composition_line.name,
+            "Fit 2-Composition"
+        );
+
+        // a discarded refit leaves the stored fit alone
+        fits.reopen_stored_fit(0);
+        fits.discard_temp_fits();
+        fits.temp_fit = Some(Fitter::new(FitModel::Gaussian(vec![2.0]), None));
+        fits.store_temp_fit();
+        assert_eq!(fits.stored_fits[0].name, "Fit 1");
+        assert_eq!(fits.stored_fits.len(), 3);
+    }
+
+    #[test]
+    fn test_reopen_refit_store_keeps_identity() {
+        let mut fits = Fits::new();
+        let mut fit = stored("Fit");
+        fit.note = "2+ -> 0+".to_string();
+        fit.visible = false;
+        fit.method = FitMethod::PoissonLikelihood;
+        fits.stored_fits = vec![stored("Other"), fit];
+
+        // the refit starts with the method of the fit, not of the histogram
+        fits.reopen_stored_fit(1);
+        assert!(fits.temp_fit.as_ref().unwrap().visible);
+        assert_eq!(
+            fits.reopened.as_ref().map(|reopened| reopened.method),
+            Some(FitMethod::PoissonLikelihood)
+        );
+
+        // refitting replaces the temp fit with a new fitter
+        fits.temp_fit = Some(Fitter::new(FitModel::Gaussian(vec![2.0]), None));
+        fits.store_temp_fit();
+
+        let refit = &fits.stored_fits[1];
+        assert_eq!(refit.name, "Fit");
+        assert_eq!(refit.note, "2+ -> 0+");
+        assert!(!refit.visible);
+        assert_eq!(fits.stored_fits.len(), 2);
+
+        // a new fit gets a numbered name again
+        fits.temp_fit = Some(Fitter::new(FitModel::Gaussian(vec![3.0]), None));
+        fits.store_temp_fit();
+        assert_eq!(fits.stored_fits[2].name, "Fit 2");
+    }
+
+    #[test]
+    fn test_stored_fit_names_are_unique() {
+        let mut fits = Fits::new();
+        fits.stored_fits = vec![stored("Fit 0"), stored("Fit 1"), stored("Fit 2")];
+        fits.remove_stored_fit(1);
+
+        fits.temp_fit = Some(Fitter::new(FitModel::Gaussian(vec![1.0]), None));
+        fits.store_temp_fit();
+        assert_eq!(fits.stored_fits[2].name, "Fit 3");
+
+        // a rename to a taken name is undone
+        fits.renaming = Some((0, "Fit 0".to_string()));
+        fits.stored_fits[0].name = "Fit 2".to_string();
+        fits.finish_rename(0);
+        assert_eq!(fits.stored_fits[0].name, "Fit 0");
+    }
 }
@@ -0,0 +1,369 @@
+use rfd::FileDialog;
+
+use std::fs::File;
+use std::io::Write;
+
+// Version of the exported JSON layout, bump when the row fields change
+const FIT_RESULTS_SCHEMA_VERSION: u32 = 3;
+
+/// One fitted peak of a stored fit, flattened for tables and export.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FitResultRow {
+    pub histogram: String,
+    pub fit: usize,
+    pub peak: usize,
+    pub centroid: f64,
+    pub centroid_uncertainty: f64,
+    pub fwhm: f64,
+    pub fwhm_uncertainty: f64,
+    pub area: f64,
+    pub area_uncertainty: f64,
+    pub chi_squared: Option<f64>,
+    pub degrees_of_freedom: Option<usize>,
+    pub reduced_chi_squared: Option<f64>,
+    pub method: String,
+    pub weighting: String,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub identification: String,
+}
+
+impl FitResultRow {
+    const CSV_HEADER: &'static str = "histogram,fit,peak,centroid,centroid_uncertainty,fwhm,fwhm_uncertainty,area,area_uncertainty,chi_squared,degrees_of_freedom,reduced_chi_squared,method,weighting,note,identification";
+
+    fn to_csv_line(&self) -> String {
+        let optional = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_escape(&self.histogram),
+            self.fit,
+            self.peak,
+            self.centroid,
+            self.centroid_uncertainty,
+            self.fwhm,
+            self.fwhm_uncertainty,
+            self.area,
+            self.area_uncertainty,
+            optional(self.chi_squared),
+            self.degrees_of_freedom
+                .map_or(String::new(), |v| v.to_string()),
+            optional(self.reduced_chi_squared),
+            csv_escape(&self.method),
+            csv_escape(&self.weighting),
+            csv_escape(&self.note),
+            csv_escape(&self.identification),
+        )
+    }
+}
+
+// Quote fields containing separators so histogram names like "X2 v X1, gated" survive
+pub fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FitResultsExport<'a> {
+    schema_version: u32,
+    fits: Vec<&'a FitResultRow>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum FitResultColumn {
+    #[default]
+    Histogram,
+    Fit,
+    Peak,
+    Centroid,
+    Fwhm,
+    Area,
+    ReducedChiSquared,
+    Note,
+    Identification,
+}
+
+impl FitResultColumn {
+    const ALL: [FitResultColumn; 9] = [
+        FitResultColumn::Histogram,
+        FitResultColumn::Fit,
+        FitResultColumn::Peak,
+        FitResultColumn::Centroid,
+        FitResultColumn::Fwhm,
+        FitResultColumn::Area,
+        FitResultColumn::ReducedChiSquared,
+        FitResultColumn::Note,
+        FitResultColumn::Identification,
+    ];
+
+    fn name(&self) -> &str {
+        match self {
+            FitResultColumn::Histogram => "Histogram",
+            FitResultColumn::Fit => "Fit",
+            FitResultColumn::Peak => "Peak",
+            FitResultColumn::Centroid => "Centroid",
+            FitResultColumn::Fwhm => "FWHM",
+            FitResultColumn::Area => "Area",
+            FitResultColumn::ReducedChiSquared => "χ²/dof",
+            FitResultColumn::Note => "Note",
+            FitResultColumn::Identification => "ID",
+        }
+    }
+
+    fn compare(&self, a: &FitResultRow, b: &FitResultRow) -> std::cmp::Ordering {
+        let by_f64 = |x: f64, y: f64| x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal);
+        match self {
+            FitResultColumn::Histogram => a
+                .histogram
+                .cmp(&b.histogram)
+                .then(a.fit.cmp(&b.fit))
+                .then(a.peak.cmp(&b.peak)),
+            FitResultColumn::Fit => a.fit.cmp(&b.fit),
+            FitResultColumn::Peak => a.peak.cmp(&b.peak),
+            FitResultColumn::Centroid => by_f64(a.centroid, b.centroid),
+            FitResultColumn::Fwhm => by_f64(a.fwhm, b.fwhm),
+            FitResultColumn::Area => by_f64(a.area, b.area),
+            FitResultColumn::ReducedChiSquared => by_f64(
+                a.reduced_chi_squared.unwrap_or(f64::INFINITY),
+                b.reduced_chi_squared.unwrap_or(f64::INFINITY),
+            ),
+            FitResultColumn::Note => a.note.cmp(&b.note),
+            FitResultColumn::Identification => a.identification.cmp(&b.identification),
+        }
+    }
+}
+
+/// Table of the stored fits of every histogram. The rows are collected from the
+/// histogram panes by the app each frame.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FitResultsTable {
+    #[serde(skip)]
+    pub rows: Vec<FitResultRow>,
+    pub sort_column: FitResultColumn,
+    pub sort_ascending: bool,
+    pub filter: String,
+}
+
+impl FitResultsTable {
+    pub fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            sort_column: FitResultColumn::Histogram,
+            sort_ascending: true,
+            filter: String::new(),
+        }
+    }
+
+    // Rows matching the filter (case insensitive on the histogram name), in the current sort order
+    pub fn visible_rows(&self) -> Vec<&FitResultRow> {
+        let filter = self.filter.to_lowercase();
+        let mut rows: Vec<&FitResultRow> = self
+            .rows
+            .iter()
+            .filter(|row| filter.is_empty() || row.histogram.to_lowercase().contains(&filter))
+            .collect();
+
+        rows.sort_by(|a, b| {
+            let ordering = self.sort_column.compare(a, b);
+            if self.sort_ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+
+        rows
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(FitResultRow::CSV_HEADER);
+        csv.push('\n');
+        for row in self.visible_rows() {
+            csv.push_str(&row.to_csv_line());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let export = FitResultsExport {
+            schema_version: FIT_RESULTS_SCHEMA_VERSION,
+            fits: self.visible_rows(),
+        };
+        serde_json::to_string_pretty(&export)
+    }
+
+    fn export_csv(&self) {
+        if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).save_file() {
+            match File::create(path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.write_all(self.to_csv().as_bytes()) {
+                        log::error!("Error writing fit results: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error creating file: {:?}", e);
+                }
+            }
+        }
+    }
+
+    fn export_json(&self) {
+        if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).save_file() {
+            let json = match self.to_json() {
+                Ok(json) => json,
+                Err(e) => {
+                    log::error!("Failed to serialize fit results: {:?}", e);
+                    return;
+                }
+            };
+
+            match File::create(path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.write_all(json.as_bytes()) {
+                        log::error!("Error writing fit results: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error creating file: {:?}", e);
+                }
+            }
+        }
+    }
+
+    fn header_ui(&mut self, ui: &mut egui::Ui) {
+        for column in FitResultColumn::ALL {
+            let selected = self.sort_column == column;
+            let arrow = match (selected, self.sort_ascending) {
+                (true, true) => " ⏶",
+                (true, false) => " ⏷",
+                _ => "",
+            };
+
+            if ui
+                .selectable_label(selected, format!("{}{}", column.name(), arrow))
+                .on_hover_text("Sort by this column")
+                .clicked()
+            {
+                if selected {
+                    self.sort_ascending = !self.sort_ascending;
+                } else {
+                    self.sort_column = column;
+                    self.sort_ascending = true;
+                }
+            }
+        }
+        ui.end_row();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Filter: ");
+            ui.text_edit_singleline(&mut self.filter)
+                .on_hover_text("Only show fits from histograms containing this text");
+
+            ui.separator();
+
+            if ui.button("Export CSV").clicked() {
+                self.export_csv();
+            }
+
+            if ui.button("Export JSON").clicked() {
+                self.export_json();
+            }
+        });
+
+        ui.separator();
+
+        if self.rows.is_empty() {
+            ui.label("No stored fits. Store a fit with S in a histogram pane.");
+            return;
+        }
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("fit_results_table")
+                .striped(true)
+                .show(ui, |ui| {
+                    self.header_ui(ui);
+
+                    for row in self.visible_rows() {
+                        ui.label(&row.histogram)
+                            .on_hover_text(format!("{}, {}", row.method, row.weighting));
+                        ui.label(format!("{}", row.fit));
+                        ui.label(format!("{}", row.peak));
+                        ui.label(format!(
+                            "{:.2} ± {:.2}",
+                            row.centroid, row.centroid_uncertainty
+                        ));
+                        ui.label(format!("{:.2} ± {:.2}", row.fwhm, row.fwhm_uncertainty));
+                        ui.label(format!("{:.2} ± {:.2}", row.area, row.area_uncertainty));
+                        ui.label(
+                            row.reduced_chi_squared
+                                .map_or(String::new(), |chi| format!("{:.2}", chi)),
+                        );
+                        ui.label(&row.note);
+                        ui.label(&row.identification);
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(histogram: &str, centroid: f64) -> FitResultRow {
+        FitResultRow {
+            histogram: histogram.to_string(),
+            fit: 0,
+            peak: 0,
+            centroid,
+            centroid_uncertainty: 0.1,
+            fwhm: 2.0,
+            fwhm_uncertainty: 0.2,
+            area: 100.0,
+            area_uncertainty: 10.0,
+            chi_squared: Some(12.0),
+            degrees_of_freedom: Some(10),
+            reduced_chi_squared: Some(1.2),
+            method: "Least Squares".to_string(),
+            weighting: "Poisson".to_string(),
+            note: String::new(),
+            identification: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let mut table = FitResultsTable::new();
+        table.rows = vec![
+            row("Cebra0Energy", 511.0),
+            row("X1", 10.0),
+            row("Cebra1Energy", 1332.0),
+        ];
+        table.filter = "cebra".to_string();
+        table.sort_column = FitResultColumn::Centroid;
+        table.sort_ascending = false;
+
+        let centroids: Vec<f64> = table.visible_rows().iter().map(|r| r.centroid).collect();
+        assert_eq!(centroids, vec![1332.0, 511.0]);
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let mut table = FitResultsTable::new();
+        table.rows = vec![row("X2 v X1, gated", 1.5)];
+
+        let csv = table.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], FitResultRow::CSV_HEADER);
+        assert!(lines[1].starts_with("\"X2 v X1, gated\",0,0,1.5,"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub enum FitWorkerStatus<T> {
+    Running,
+    Finished(Box<T>),
+    Failed,
+}
+
+/// Runs a fit on a separate thread so the UI keeps drawing.
+/// The solvers cannot be interrupted, so a cancelled fit runs to the end in the
+/// background and its result is dropped.
+pub struct FitWorker<T> {
+    receiver: Mutex<Receiver<T>>,
+    cancelled: Arc<AtomicBool>,
+    started: Instant,
+}
+
+impl<T> std::fmt::Debug for FitWorker<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FitWorker")
+            .field("cancelled", &self.is_cancelled())
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl<T: Send + 'static> FitWorker<T> {
+    // Runs `fit` on the worker thread, its return value is the result
+    pub fn spawn_with(fit: impl FnOnce() -> T + Send + 'static, ctx: egui::Context) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+
+        let spawned = std::thread::Builder::new()
+            .name("fit worker".to_string())
+            .spawn(move || {
+                let result = fit();
+
+                if !thread_cancelled.load(Ordering::Relaxed) {
+                    // the receiver is gone if the pane was closed, nothing to do then
+                    let _ = sender.send(result);
+                    ctx.request_repaint();
+                }
+            });
+
+        if let Err(e) = spawned {
+            log::error!("Failed to start the fit thread: {:?}", e);
+        }
+
+        Self {
+            receiver: Mutex::new(receiver),
+            cancelled,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl<T> FitWorker<T> {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.started.elapsed().as_secs_f32()
+    }
+
+    pub fn status(&self) -> FitWorkerStatus<T> {
+        let receiver = match self.receiver.lock() {
+            Ok(receiver) => receiver,
+            Err(_) => return FitWorkerStatus::Failed,
+        };
+
+        match receiver.try_recv() {
+            Ok(result) => FitWorkerStatus::Finished(Box::new(result)),
+            Err(TryRecvError::Empty) => FitWorkerStatus::Running,
+            // the thread panicked or could not be started
+            Err(TryRecvError::Disconnected) => FitWorkerStatus::Failed,
+        }
+    }
+
+    /// Takes the result of a finished fit and empties the slot. A thread that stopped
+    /// without a result is logged.
+    pub fn poll(worker: &mut Option<Arc<Self>>, name: &str) -> Option<T> {
+        let status = worker.as_ref()?.status();
+        match status {
+            FitWorkerStatus::Running => None,
+            FitWorkerStatus::Finished(result) => {
+                *worker = None;
+                Some(*result)
+            }
+            FitWorkerStatus::Failed => {
+                *worker = None;
+                log::error!("{}: the fit thread stopped without a result", name);
+                None
+            }
+        }
+    }
+
+    pub fn cancel_slot(worker: &mut Option<Arc<Self>>, name: &str) {
+        if let Some(worker) = worker.take() {
+            worker.cancel();
+            log::info!("{}: fit cancelled", name);
+        }
+    }
+
+    /// Spinner, elapsed time and a cancel button while a fit runs.
+    pub fn ui(worker: &mut Option<Arc<Self>>, ui: &mut egui::Ui, label: &str, name: &str) {
+        let Some(running) = worker.as_ref() else {
+            return;
+        };
+
+        let elapsed = running.elapsed_seconds();
+        let mut cancel = false;
+
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(format!("{} {:.1} s", label, elapsed));
+            cancel = ui.button("Cancel").clicked();
+        });
+
+        // keep the elapsed time ticking, the worker repaints when it is done
+        ui.ctx()
+            .request_repaint_after(std::time::Duration::from_millis(100));
+
+        if cancel {
+            Self::cancel_slot(worker, name);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use super::goodness_of_fit::GoodnessOfFit;
+use super::likelihood::{LikelihoodResult, PoissonLikelihood};
 use nalgebra::DVector;
 use varpro::model::builder::SeparableModelBuilder;
 use varpro::solvers::levmar::{LevMarProblemBuilder, LevMarSolver};
@@ -15,11 +17,23 @@ pub struct GaussianParams {
     pub sigma: Value,
     pub fwhm: Value,
     pub area: Value,
+    #[serde(default)]
+    pub identification: Option<String>, // matched peak library line
 }
 
 impl GaussianParams {
     // Constructor that also calculates FWHM and area
     pub fn new(amplitude: Value, mean: Value, sigma: Value) -> Result<Self, String> {
+        Self::new_with_covariance(amplitude, mean, sigma, 0.0)
+    }
+
+    // Same as new, with the amplitude-sigma covariance included in the area uncertainty
+    pub fn new_with_covariance(
+        amplitude: Value,
+        mean: Value,
+        sigma: Value,
+        amplitude_sigma_covariance: f64,
+    ) -> Result<Self, String> {
         if sigma.value <= 0.0 {
             let error_message = "Sigma must be positive.".to_string();
             // log::error!("{}", error_message);
@@ -35,7 +49,8 @@ impl GaussianParams {
             // log::error!("{}", error_message);
             return Err(error_message);
         }
-        let area_uncertainty = Self::area_uncertainty(amplitude.clone(), sigma.clone());
+        let area_uncertainty =
+            Self::area_uncertainty(amplitude.clone(), sigma.clone(), amplitude_sigma_covariance);
 
         Ok(GaussianParams {
             amplitude,
@@ -49,6 +64,7 @@ impl GaussianParams {
                 value: area,
                 uncertainty: area_uncertainty,
             },
+            identification: None,
         })
     }
 
@@ -67,19 +83,26 @@ impl GaussianParams {
         amplitude * sigma * (2.0 * std::f64::consts::PI).sqrt()
     }
 
-    // Method to calculate area uncertainty
-    fn area_uncertainty(amplitude: Value, sigma: Value) -> f64 {
+    // Method to calculate area uncertainty, area = sqrt(2π) a σ
+    fn area_uncertainty(amplitude: Value, sigma: Value, amplitude_sigma_covariance: f64) -> f64 {
         let two_pi_sqrt = (2.0 * std::f64::consts::PI).sqrt();
-        ((sigma.value * two_pi_sqrt * amplitude.uncertainty).powi(2)
-            + (amplitude.value * two_pi_sqrt * sigma.uncertainty).powi(2))
-        .sqrt()
+        let variance = (sigma.value * two_pi_sqrt * amplitude.uncertainty).powi(2)
+            + (amplitude.value * two_pi_sqrt * sigma.uncertainty).powi(2)
+            + 2.0
+                * (sigma.value * two_pi_sqrt)
+                * (amplitude.value * two_pi_sqrt)
+                * amplitude_sigma_covariance;
+        variance.max(0.0).sqrt()
     }
 
     pub fn params_ui(&self, ui: &mut egui::Ui) {
-        ui.label(format!(
+        let mean = ui.label(format!(
             "{:.2} ± {:.2}",
             self.mean.value, self.mean.uncertainty
         ));
+        if let Some(identification) = &self.identification {
+            mean.on_hover_text(identification);
+        }
         ui.label(format!(
             "{:.2} ± {:.2}",
             self.fwhm.value, self.fwhm.uncertainty
@@ -111,9 +134,20 @@ impl GaussianParams {
 pub struct GaussianFitter {
     x: Vec<f64>,
     y: Vec<f64>,
+    #[serde(default)]
+    y_err: Option<Vec<f64>>,
     pub peak_markers: Vec<f64>,
     pub fit_params: Option<Vec<GaussianParams>>,
     pub fit_lines: Option<Vec<Vec<[f64; 2]>>>,
+    #[serde(default)]
+    pub goodness_of_fit: Option<GoodnessOfFit>,
+    #[serde(default)]
+    pub least_squares_params: Option<Vec<GaussianParams>>,
+    #[serde(default)]
+    pub likelihood_result: Option<LikelihoodResult>,
+    // full covariance ordered as amplitudes, means, sigma (same as the likelihood model)
+    #[serde(default)]
+    pub covariance: Option<Vec<Vec<f64>>>,
 }
 
 impl GaussianFitter {
@@ -121,12 +155,22 @@ impl GaussianFitter {
         Self {
             x,
             y,
+            y_err: None,
             peak_markers,
             fit_params: None,
             fit_lines: None,
+            goodness_of_fit: None,
+            least_squares_params: None,
+            likelihood_result: None,
+            covariance: None,
         }
     }
 
+    // Uncertainties used to weight the least squares fit (weight = 1/σ)
+    pub fn set_uncertainties(&mut self, y_err: Vec<f64>) {
+        self.y_err = Some(y_err);
+    }
+
     fn gaussian(x: &DVector<f64>, mean: f64, sigma: f64) -> DVector<f64> {
         x.map(|x_val| (-((x_val - mean).powi(2)) / (2.0 * sigma.powi(2))).exp())
     }
@@ -192,6 +236,10 @@ impl GaussianFitter {
     pub fn multi_gauss_fit(&mut self) {
         self.fit_params = None;
         self.fit_lines = None;
+        self.goodness_of_fit = None;
+        self.least_squares_params = None;
+        self.likelihood_result = None;
+        self.covariance = None;
 
         // Ensure x and y data have the same length
         if self.x.len() != self.y.len() {
@@ -232,11 +280,25 @@ impl GaussianFitter {
             }
         };
 
+        let mut problem_builder = LevMarProblemBuilder::new(model).observations(y_data);
+
+        // Weight each bin by 1/σ, bins without an uncertainty do not contribute
+        if let Some(y_err) = &self.y_err {
+            if y_err.len() == self.y.len() {
+                let weights = DVector::from_iterator(
+                    y_err.len(),
+                    y_err
+                        .iter()
+                        .map(|&err| if err > 0.0 { 1.0 / err } else { 0.0 }),
+                );
+                problem_builder = problem_builder.weights(weights);
+            } else {
+                log::error!("y_err must have the same length as the data, fitting unweighted");
+            }
+        }
+
         // Extract the parameters
-        let problem = match LevMarProblemBuilder::new(model)
-            .observations(y_data)
-            .build()
-        {
+        let problem = match problem_builder.build() {
             Ok(problem) => problem,
             Err(e) => {
                 log::error!("Failed to build problem: {:?}", e);
@@ -277,6 +339,15 @@ impl GaussianFitter {
 
                 let linear_variances = fit_statistics.linear_coefficients_variance();
 
+                // varpro orders the covariance as the linear coefficients (amplitudes) then the
+                // nonlinear parameters (means, sigma), which matches the likelihood model order
+                let covariance_matrix = fit_statistics.covariance_matrix();
+                let covariance: Vec<Vec<f64>> = covariance_matrix
+                    .row_iter()
+                    .map(|row| row.iter().cloned().collect())
+                    .collect();
+                let sigma_index = covariance.len() - 1;
+
                 let mut params: Vec<GaussianParams> = Vec::new();
 
                 let sigma = nonlinear_parameters[nonlinear_parameters.len() - 1];
@@ -295,7 +366,7 @@ impl GaussianFitter {
                     let amplitude_variance = linear_variances[i];
 
                     // Create a GaussianParams instance which now includes FWHM and area calculations
-                    match GaussianParams::new(
+                    match GaussianParams::new_with_covariance(
                         Value {
                             value: amplitude,
                             uncertainty: amplitude_variance.sqrt(),
@@ -308,6 +379,7 @@ impl GaussianFitter {
                             value: sigma,
                             uncertainty: sigma_variance.sqrt(),
                         },
+                        covariance[i][sigma_index],
                     ) {
                         Ok(gaussian_params) => {
                             // Log the Gaussian component parameters including FWHM and area
@@ -325,6 +397,7 @@ impl GaussianFitter {
                 }
 
                 self.fit_params = Some(params);
+                self.covariance = Some(covariance);
                 self.get_fit_lines();
             }
             Err(e) => {
@@ -333,6 +406,98 @@ impl GaussianFitter {
         }
     }
 
+    // Gaussian model used for the likelihood fit. Parameters are the amplitudes, then the means, then the shared sigma.
+    fn likelihood_model(x: f64, parameters: &[f64]) -> f64 {
+        let n_peaks = (parameters.len() - 1) / 2;
+        let sigma = parameters[parameters.len() - 1];
+
+        (0..n_peaks).fold(0.0, |sum, i| {
+            let amplitude = parameters[i];
+            let mean = parameters[n_peaks + i];
+            sum + amplitude * (-((x - mean).powi(2)) / (2.0 * sigma.powi(2))).exp()
+        })
+    }
+
+    /// Refines the least squares result with a Poisson maximum-likelihood fit to the raw counts.
+    /// The least squares parameters are kept in `least_squares_params` for comparison.
+    pub fn poisson_likelihood_fit(&mut self, y_counts: &[f64], background: &[f64]) {
+        let least_squares_params = match &self.fit_params {
+            Some(params) if !params.is_empty() => params.clone(),
+            _ => {
+                log::error!("Poisson likelihood fit needs a least squares fit as a starting point");
+                return;
+            }
+        };
+
+        let n_peaks = least_squares_params.len();
+        let mut initial_guess: Vec<f64> = least_squares_params
+            .iter()
+            .map(|p| p.amplitude.value)
+            .collect();
+        initial_guess.extend(least_squares_params.iter().map(|p| p.mean.value));
+        initial_guess.push(least_squares_params[0].sigma.value);
+
+        let likelihood =
+            PoissonLikelihood::new(&self.x, y_counts, background, Self::likelihood_model);
+
+        let result = match likelihood.minimize(initial_guess) {
+            Ok(result) => result,
+            Err(e) => {
+                log::error!(
+                    "Poisson likelihood fit failed, keeping least squares result: {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        let value = |index: usize| Value {
+            value: result.parameters[index],
+            uncertainty: result.uncertainties[index],
+        };
+
+        let sigma_index = 2 * n_peaks;
+        let covariance = |i: usize, j: usize| {
+            result
+                .covariance
+                .get(i)
+                .and_then(|row| row.get(j))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let mut params = Vec::new();
+        for i in 0..n_peaks {
+            match GaussianParams::new_with_covariance(
+                value(i),
+                value(n_peaks + i),
+                value(sigma_index),
+                covariance(i, sigma_index),
+            ) {
+                Ok(gaussian_params) => params.push(gaussian_params),
+                Err(e) => {
+                    log::error!("Poisson likelihood fit failed for peak {}: {}", i, e);
+                    return;
+                }
+            }
+        }
+
+        log::info!(
+            "Poisson likelihood fit: Cash statistic {:.2} after {} iterations",
+            result.cash_statistic,
+            result.iterations
+        );
+
+        self.peak_markers = params.iter().map(|p| p.mean.value).collect();
+        self.fit_params = Some(params);
+        self.least_squares_params = Some(least_squares_params);
+        if !result.covariance.is_empty() {
+            self.covariance = Some(result.covariance.clone());
+        }
+        self.likelihood_result = Some(result);
+        self.get_fit_lines();
+    }
+
     pub fn get_fit_lines(&mut self) {
         if let Some(fit_params) = &self.fit_params {
             let mut fit_lines = Vec::new();
@@ -348,30 +513,104 @@ impl GaussianFitter {
         }
     }
 
+    // Sum of all fitted gaussians at x (background not included)
+    pub fn evaluate(&self, x: f64) -> f64 {
+        self.fit_params.as_ref().map_or(0.0, |params| {
+            params.iter().fold(0.0, |sum, param| {
+                sum + param.amplitude.value
+                    * (-((x - param.mean.value).powi(2)) / (2.0 * param.sigma.value.powi(2))).exp()
+            })
+        })
+    }
+
+    /// One standard deviation of the fitted gaussians at x, propagated through the full covariance.
+    pub fn evaluate_uncertainty(&self, x: f64) -> f64 {
+        let (Some(params), Some(covariance)) = (&self.fit_params, &self.covariance) else {
+            return 0.0;
+        };
+
+        let n_peaks = params.len();
+        if covariance.len() != 2 * n_peaks + 1 {
+            return 0.0;
+        }
+
+        // gradient of the model with respect to (amplitudes, means, sigma)
+        let mut gradient = vec![0.0; 2 * n_peaks + 1];
+        for (i, param) in params.iter().enumerate() {
+            let sigma = param.sigma.value;
+            let dx = x - param.mean.value;
+            let g = (-(dx * dx) / (2.0 * sigma * sigma)).exp();
+
+            gradient[i] = g;
+            gradient[n_peaks + i] = param.amplitude.value * g * dx / (sigma * sigma);
+            gradient[2 * n_peaks] += param.amplitude.value * g * dx * dx / sigma.powi(3);
+        }
+
+        let variance: f64 = gradient
+            .iter()
+            .enumerate()
+            .map(|(i, gi)| {
+                gradient
+                    .iter()
+                    .enumerate()
+                    .map(|(j, gj)| gi * covariance[i][j] * gj)
+                    .sum::<f64>()
+            })
+            .sum();
+
+        variance.max(0.0).sqrt()
+    }
+
+    // amplitude and mean for every peak plus the shared sigma
+    pub fn number_of_parameters(&self) -> usize {
+        self.fit_params
+            .as_ref()
+            .map_or(0, |params| 2 * params.len() + 1)
+    }
+
     pub fn composition_fit_points_linear_bg(&self, slope: f64, intercept: f64) -> Vec<[f64; 2]> {
+        let x_points = self.composition_x_points();
+        let background: Vec<f64> = x_points.iter().map(|x| slope * x + intercept).collect();
+        self.composition_fit_points(&x_points, &background)
+    }
+
+    // Evenly spaced x values over the fit region for drawing the composition line
+    pub fn composition_x_points(&self) -> Vec<f64> {
         let num_points = 3000;
         let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
         let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
         let step = (max_x - min_x) / num_points as f64;
 
-        (0..=num_points)
-            .map(|i| {
-                let x = min_x + step * i as f64;
-                let y_gauss = self.fit_params.as_ref().map_or(0.0, |params| {
-                    params.iter().fold(0.0, |sum, param| {
-                        sum + param.amplitude.value
-                            * (-((x - param.mean.value).powi(2))
-                                / (2.0 * param.sigma.value.powi(2)))
-                            .exp()
-                    })
-                });
-                let y_background = slope * x + intercept;
-                let y_total = y_gauss + y_background;
-                [x, y_total]
-            })
+        (0..=num_points).map(|i| min_x + step * i as f64).collect()
+    }
+
+    // Sum of the gaussians and the background at each x value
+    pub fn composition_fit_points(&self, x_points: &[f64], background: &[f64]) -> Vec<[f64; 2]> {
+        x_points
+            .iter()
+            .zip(background.iter())
+            .map(|(&x, &y_background)| [x, self.evaluate(x) + y_background])
             .collect()
     }
 
+    // Compare the least squares and the likelihood results peak by peak
+    pub fn method_comparison_ui(&self, ui: &mut egui::Ui) {
+        if let (Some(least_squares), Some(likelihood)) =
+            (&self.least_squares_params, &self.fit_params)
+        {
+            for (i, (ls, ml)) in least_squares.iter().zip(likelihood.iter()).enumerate() {
+                ui.label(format!("{}", i));
+                ui.label(format!("{:.2} ± {:.2}", ls.mean.value, ls.mean.uncertainty));
+                ui.label(format!("{:.2} ± {:.2}", ml.mean.value, ml.mean.uncertainty));
+                ui.label(format!("{:.2} ± {:.2}", ls.fwhm.value, ls.fwhm.uncertainty));
+                ui.label(format!("{:.2} ± {:.2}", ml.fwhm.value, ml.fwhm.uncertainty));
+                ui.label(format!("{:.2} ± {:.2}", ls.area.value, ls.area.uncertainty));
+                ui.label(format!("{:.2} ± {:.2}", ml.area.value, ml.area.uncertainty));
+                ui.end_row();
+            }
+        }
+    }
+
     pub fn fit_params_ui(&self, ui: &mut egui::Ui) {
         if let Some(fit_params) = &self.fit_params {
             for (i, params) in fit_params.iter().enumerate() {
@@ -381,8 +620,64 @@ impl GaussianFitter {
 
                 ui.label(format!("{}", i));
                 params.params_ui(ui);
+
+                if i == 0 {
+                    if let Some(goodness_of_fit) = &self.goodness_of_fit {
+                        goodness_of_fit.ui(ui);
+                    }
+                }
+
                 ui.end_row();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(value: f64, uncertainty: f64) -> Value {
+        Value { value, uncertainty }
+    }
+
+    #[test]
+    fn test_area_uncertainty_includes_covariance() {
+        let uncorrelated =
+            GaussianParams::new(value(100.0, 5.0), value(0.0, 0.1), value(2.0, 0.1)).unwrap();
+        let anticorrelated = GaussianParams::new_with_covariance(
+            value(100.0, 5.0),
+            value(0.0, 0.1),
+            value(2.0, 0.1),
+            -0.4,
+        )
+        .unwrap();
+
+        // area = sqrt(2π) a σ, so var = 2π (σ² σa² + a² σσ² + 2 a σ cov)
+        let two_pi = 2.0 * std::f64::consts::PI;
+        let expected = (two_pi * (4.0 * 25.0 + 10000.0 * 0.01 - 2.0 * 200.0 * 0.4)).sqrt();
+        assert!((anticorrelated.area.uncertainty - expected).abs() < 1e-9);
+        assert!(anticorrelated.area.uncertainty < uncorrelated.area.uncertainty);
+    }
+
+    #[test]
+    fn test_evaluate_uncertainty() {
+        let mut fitter = GaussianFitter::new(vec![], vec![], vec![]);
+        fitter.fit_params = Some(vec![GaussianParams::new(
+            value(100.0, 5.0),
+            value(10.0, 0.1),
+            value(2.0, 0.1),
+        )
+        .unwrap()]);
+        // only the amplitude is uncertain
+        fitter.covariance = Some(vec![
+            vec![25.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+        ]);
+
+        assert!((fitter.evaluate_uncertainty(10.0) - 5.0).abs() < 1e-12);
+        let g = (-0.5_f64).exp(); // one sigma away from the mean
+        assert!((fitter.evaluate_uncertainty(12.0) - 5.0 * g).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,497 @@
+use nalgebra::{DMatrix, DVector};
+
+use super::gaussian::Value;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Background2D {
+    None,
+    #[default]
+    Constant,
+    Planar,
+}
+
+impl Background2D {
+    pub fn name(&self) -> &str {
+        match self {
+            Background2D::None => "None",
+            Background2D::Constant => "Constant",
+            Background2D::Planar => "Planar",
+        }
+    }
+}
+
+// Order of the parameters in the solver
+const AMPLITUDE: usize = 0;
+const MEAN_X: usize = 1;
+const MEAN_Y: usize = 2;
+const SIGMA_X: usize = 3;
+const SIGMA_Y: usize = 4;
+const RHO: usize = 5;
+const OFFSET: usize = 6;
+const SLOPE_X: usize = 7;
+const SLOPE_Y: usize = 8;
+const NUMBER_OF_PARAMETERS: usize = 9;
+
+const MAX_RHO: f64 = 0.99;
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Gaussian2DParams {
+    pub amplitude: Value,
+    pub mean_x: Value,
+    pub mean_y: Value,
+    pub sigma_x: Value,
+    pub sigma_y: Value,
+    pub rho: Value,
+    pub background: Vec<Value>, // offset, then the x and y slopes for a planar background
+    pub volume: Value,          // counts under the peak
+    pub reduced_chi_squared: f64,
+}
+
+impl Gaussian2DParams {
+    // Points of the ellipse at k standard deviations
+    pub fn ellipse(&self, k: f64, number_of_points: usize) -> Vec<[f64; 2]> {
+        let (sx, sy, rho) = (self.sigma_x.value, self.sigma_y.value, self.rho.value);
+
+        // cholesky factor of the covariance matrix maps the unit circle onto the ellipse
+        (0..=number_of_points)
+            .map(|i| {
+                let theta = 2.0 * std::f64::consts::PI * i as f64 / number_of_points as f64;
+                let (u, v) = (k * theta.cos(), k * theta.sin());
+                [
+                    self.mean_x.value + sx * u,
+                    self.mean_y.value + sy * (rho * u + (1.0 - rho * rho).sqrt() * v),
+                ]
+            })
+            .collect()
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui) {
+        let value = |v: &Value| format!("{:.3} ± {:.3}", v.value, v.uncertainty);
+
+        egui::Grid::new("gaussian_2d_params")
+            .striped(true)
+            .show(ui, |ui| {
+                for (label, v) in [
+                    ("Volume (counts)", &self.volume),
+                    ("Mean X", &self.mean_x),
+                    ("Mean Y", &self.mean_y),
+                    ("Sigma X", &self.sigma_x),
+                    ("Sigma Y", &self.sigma_y),
+                    ("ρ", &self.rho),
+                    ("Amplitude", &self.amplitude),
+                ] {
+                    ui.label(label);
+                    ui.label(value(v));
+                    ui.end_row();
+                }
+
+                for (label, v) in ["Background", "Slope X", "Slope Y"]
+                    .iter()
+                    .zip(&self.background)
+                {
+                    ui.label(*label);
+                    ui.label(value(v));
+                    ui.end_row();
+                }
+
+                ui.label("χ²/dof");
+                ui.label(format!("{:.3}", self.reduced_chi_squared));
+                ui.end_row();
+            });
+    }
+}
+
+/// Fits A·exp(-Q/2) plus a constant or planar background to the bins of a 2D
+/// histogram region, where Q is the (optionally correlated) bivariate normal form.
+/// Bins are weighted with their Poisson variance.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Gaussian2DFitter {
+    pub region: [f64; 4], // x min, x max, y min, y max
+    pub correlated: bool,
+    pub background: Background2D,
+    pub show_region: bool,
+    pub show_contours: bool,
+    pub params: Option<Gaussian2DParams>,
+}
+
+impl Default for Gaussian2DFitter {
+    fn default() -> Self {
+        Self {
+            region: [0.0, 0.0, 0.0, 0.0],
+            correlated: true,
+            background: Background2D::Constant,
+            show_region: true,
+            show_contours: true,
+            params: None,
+        }
+    }
+}
+
+impl Gaussian2DFitter {
+    fn is_free(&self, parameter: usize) -> bool {
+        match parameter {
+            RHO => self.correlated,
+            OFFSET => self.background != Background2D::None,
+            SLOPE_X | SLOPE_Y => self.background == Background2D::Planar,
+            _ => true,
+        }
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            0.5 * (self.region[0] + self.region[1]),
+            0.5 * (self.region[2] + self.region[3]),
+        )
+    }
+
+    fn evaluate(&self, p: &[f64], x: f64, y: f64) -> f64 {
+        let dx = (x - p[MEAN_X]) / p[SIGMA_X];
+        let dy = (y - p[MEAN_Y]) / p[SIGMA_Y];
+        let rho = p[RHO];
+        let q = (dx * dx - 2.0 * rho * dx * dy + dy * dy) / (1.0 - rho * rho);
+
+        // the plane is taken relative to the region center to decouple it from the offset
+        let (cx, cy) = self.center();
+        p[AMPLITUDE] * (-0.5 * q).exp() + p[OFFSET] + p[SLOPE_X] * (x - cx) + p[SLOPE_Y] * (y - cy)
+    }
+
+    // Moments of the counts above the smallest count as the starting point
+    fn initial_guess(&self, data: &[[f64; 3]]) -> [f64; NUMBER_OF_PARAMETERS] {
+        let min = data.iter().map(|d| d[2]).fold(f64::INFINITY, f64::min);
+        let max = data.iter().map(|d| d[2]).fold(f64::NEG_INFINITY, f64::max);
+        let floor = if self.background == Background2D::None {
+            0.0
+        } else {
+            min
+        };
+
+        let weights: Vec<f64> = data.iter().map(|d| (d[2] - floor).max(0.0)).collect();
+        let total: f64 = weights.iter().sum::<f64>().max(f64::EPSILON);
+
+        let moment = |f: &dyn Fn(&[f64; 3]) -> f64| {
+            data.iter()
+                .zip(&weights)
+                .map(|(d, w)| w * f(d))
+                .sum::<f64>()
+                / total
+        };
+
+        let mean_x = moment(&|d| d[0]);
+        let mean_y = moment(&|d| d[1]);
+        let var_x = moment(&|d| (d[0] - mean_x).powi(2));
+        let var_y = moment(&|d| (d[1] - mean_y).powi(2));
+        let cov = moment(&|d| (d[0] - mean_x) * (d[1] - mean_y));
+
+        let min_width = 0.25
+            * (self.region[1] - self.region[0]).min(self.region[3] - self.region[2])
+            / (data.len() as f64).sqrt().max(1.0);
+        let sigma_x = var_x.sqrt().max(min_width);
+        let sigma_y = var_y.sqrt().max(min_width);
+
+        let mut p = [0.0; NUMBER_OF_PARAMETERS];
+        p[AMPLITUDE] = max - floor;
+        p[MEAN_X] = mean_x;
+        p[MEAN_Y] = mean_y;
+        p[SIGMA_X] = sigma_x;
+        p[SIGMA_Y] = sigma_y;
+        if self.correlated {
+            p[RHO] = (cov / (sigma_x * sigma_y)).clamp(-0.9, 0.9);
+        }
+        p[OFFSET] = floor;
+        p
+    }
+
+    fn chi_squared(&self, p: &[f64], data: &[[f64; 3]]) -> f64 {
+        data.iter()
+            .map(|d| (d[2] - self.evaluate(p, d[0], d[1])).powi(2) / d[2].max(1.0))
+            .sum()
+    }
+
+    fn constrain(p: &mut [f64]) {
+        p[SIGMA_X] = p[SIGMA_X].abs().max(f64::EPSILON);
+        p[SIGMA_Y] = p[SIGMA_Y].abs().max(f64::EPSILON);
+        p[RHO] = p[RHO].clamp(-MAX_RHO, MAX_RHO);
+    }
+
+    // Weighted jacobian (rows scaled by 1/σ) of the free parameters by central differences
+    fn jacobian(&self, p: &[f64], free: &[usize], data: &[[f64; 3]]) -> DMatrix<f64> {
+        DMatrix::from_fn(data.len(), free.len(), |i, j| {
+            let parameter = free[j];
+            let step = 1e-6 * p[parameter].abs().max(1e-3);
+
+            let mut up = p.to_vec();
+            let mut down = p.to_vec();
+            up[parameter] += step;
+            down[parameter] -= step;
+
+            let d = &data[i];
+            let derivative =
+                (self.evaluate(&up, d[0], d[1]) - self.evaluate(&down, d[0], d[1])) / (2.0 * step);
+            derivative / d[2].max(1.0).sqrt()
+        })
+    }
+
+    /// Fit the (x, y, counts) points of the bins in the region. The bin area turns the
+    /// integral of the counts per bin into the number of counts.
+    pub fn fit(&mut self, data: &[[f64; 3]], bin_area: f64) {
+        self.params = None;
+
+        let free: Vec<usize> = (0..NUMBER_OF_PARAMETERS)
+            .filter(|&i| self.is_free(i))
+            .collect();
+
+        if data.len() <= free.len() {
+            log::error!(
+                "2D gaussian fit needs more than {} bins in the region",
+                free.len()
+            );
+            return;
+        }
+
+        let mut p = self.initial_guess(data);
+        let mut chi_squared = self.chi_squared(&p, data);
+        let mut lambda = 1e-3;
+
+        // Levenberg-Marquardt
+        for _ in 0..500 {
+            let jacobian = self.jacobian(&p, &free, data);
+            let residuals = DVector::from_iterator(
+                data.len(),
+                data.iter()
+                    .map(|d| (d[2] - self.evaluate(&p, d[0], d[1])) / d[2].max(1.0).sqrt()),
+            );
+
+            let jtj = jacobian.transpose() * &jacobian;
+            let gradient = jacobian.transpose() * residuals;
+
+            let mut damped = jtj.clone();
+            for i in 0..free.len() {
+                damped[(i, i)] += lambda * jtj[(i, i)].max(f64::EPSILON);
+            }
+
+            let Some(step) = damped.lu().solve(&gradient) else {
+                lambda *= 10.0;
+                continue;
+            };
+
+            let mut trial = p;
+            for (j, &parameter) in free.iter().enumerate() {
+                trial[parameter] += step[j];
+            }
+            Self::constrain(&mut trial);
+
+            let trial_chi_squared = self.chi_squared(&trial, data);
+            if trial_chi_squared < chi_squared {
+                let converged = (chi_squared - trial_chi_squared) <= 1e-10 * chi_squared.max(1.0);
+                p = trial;
+                chi_squared = trial_chi_squared;
+                lambda = (lambda / 10.0).max(1e-12);
+                if converged {
+                    break;
+                }
+            } else {
+                lambda *= 10.0;
+                if lambda > 1e12 {
+                    break;
+                }
+            }
+        }
+
+        let jacobian = self.jacobian(&p, &free, data);
+        let Some(covariance) = (jacobian.transpose() * &jacobian).try_inverse() else {
+            log::error!("2D gaussian fit failed: singular covariance matrix");
+            return;
+        };
+
+        let uncertainty = |parameter: usize| {
+            free.iter()
+                .position(|&f| f == parameter)
+                .map_or(0.0, |j| covariance[(j, j)].max(0.0).sqrt())
+        };
+        let value = |parameter: usize| Value {
+            value: p[parameter],
+            uncertainty: uncertainty(parameter),
+        };
+
+        // V = 2π A σx σy sqrt(1 - ρ²) / bin area, propagated with the full covariance
+        let sqrt_one_minus_rho = (1.0 - p[RHO] * p[RHO]).sqrt();
+        let volume = 2.0
+            * std::f64::consts::PI
+            * p[AMPLITUDE]
+            * p[SIGMA_X]
+            * p[SIGMA_Y]
+            * sqrt_one_minus_rho
+            / bin_area;
+        let gradient: Vec<f64> = free
+            .iter()
+            .map(|&parameter| match parameter {
+                AMPLITUDE => volume / p[AMPLITUDE],
+                SIGMA_X => volume / p[SIGMA_X],
+                SIGMA_Y => volume / p[SIGMA_Y],
+                RHO => -volume * p[RHO] / (1.0 - p[RHO] * p[RHO]),
+                _ => 0.0,
+            })
+            .collect();
+        let mut volume_variance = 0.0;
+        for i in 0..free.len() {
+            for j in 0..free.len() {
+                volume_variance += gradient[i] * covariance[(i, j)] * gradient[j];
+            }
+        }
+
+        let background = match self.background {
+            Background2D::None => Vec::new(),
+            Background2D::Constant => vec![value(OFFSET)],
+            Background2D::Planar => vec![value(OFFSET), value(SLOPE_X), value(SLOPE_Y)],
+        };
+
+        self.params = Some(Gaussian2DParams {
+            amplitude: value(AMPLITUDE),
+            mean_x: value(MEAN_X),
+            mean_y: value(MEAN_Y),
+            sigma_x: value(SIGMA_X),
+            sigma_y: value(SIGMA_Y),
+            rho: value(RHO),
+            background,
+            volume: Value {
+                value: volume,
+                uncertainty: volume_variance.max(0.0).sqrt(),
+            },
+            reduced_chi_squared: chi_squared / (data.len() - free.len()) as f64,
+        });
+    }
+
+    pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let color = egui::Color32::WHITE;
+
+        if self.show_region && self.region[0] < self.region[1] && self.region[2] < self.region[3] {
+            let [x_min, x_max, y_min, y_max] = self.region;
+            plot_ui.line(
+                egui_plot::Line::new(vec![
+                    [x_min, y_min],
+                    [x_max, y_min],
+                    [x_max, y_max],
+                    [x_min, y_max],
+                    [x_min, y_min],
+                ])
+                .color(color)
+                .style(egui_plot::LineStyle::dashed_loose())
+                .name("2D Fit Region"),
+            );
+        }
+
+        if let (true, Some(params)) = (self.show_contours, &self.params) {
+            for k in [1.0, 2.0, 3.0] {
+                plot_ui.line(
+                    egui_plot::Line::new(params.ellipse(k, 100))
+                        .color(color)
+                        .name(format!("{}σ", k)),
+                );
+            }
+        }
+    }
+
+    // Settings only, the histogram adds the fit buttons since it owns the bins
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for (i, prefix) in ["X min: ", "X max: ", "Y min: ", "Y max: "]
+                .iter()
+                .enumerate()
+            {
+                ui.add(
+                    egui::DragValue::new(&mut self.region[i])
+                        .speed(1.0)
+                        .prefix(*prefix),
+                );
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.correlated, "Correlated")
+                .on_hover_text("Fit the correlation ρ between x and y (tilted ellipse)");
+
+            ui.label("Background: ");
+            for background in [
+                Background2D::None,
+                Background2D::Constant,
+                Background2D::Planar,
+            ] {
+                ui.radio_value(&mut self.background, background, background.name());
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_region, "Show Region");
+            ui.checkbox(&mut self.show_contours, "Show 1/2/3σ Contours");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlated_fit() {
+        let truth = Gaussian2DFitter {
+            region: [-10.0, 10.0, -10.0, 10.0],
+            ..Default::default()
+        };
+        let mut p = [0.0; NUMBER_OF_PARAMETERS];
+        p[AMPLITUDE] = 200.0;
+        p[MEAN_X] = 1.0;
+        p[MEAN_Y] = -2.0;
+        p[SIGMA_X] = 2.0;
+        p[SIGMA_Y] = 1.5;
+        p[RHO] = 0.5;
+        p[OFFSET] = 5.0;
+
+        let data: Vec<[f64; 3]> = (0..40)
+            .flat_map(|i| (0..40).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let x = -10.0 + 0.5 * (i as f64 + 0.5);
+                let y = -10.0 + 0.5 * (j as f64 + 0.5);
+                [x, y, truth.evaluate(&p, x, y)]
+            })
+            .collect();
+
+        let mut fitter = truth.clone();
+        fitter.fit(&data, 0.25);
+        let params = fitter.params.unwrap();
+
+        assert!((params.mean_x.value - 1.0).abs() < 1e-3);
+        assert!((params.mean_y.value + 2.0).abs() < 1e-3);
+        assert!((params.sigma_x.value - 2.0).abs() < 1e-3);
+        assert!((params.sigma_y.value - 1.5).abs() < 1e-3);
+        assert!((params.rho.value - 0.5).abs() < 1e-3);
+        assert!((params.background[0].value - 5.0).abs() < 1e-2);
+
+        // in counts, the bins are 0.5 by 0.5
+        let volume = 2.0 * std::f64::consts::PI * 200.0 * 2.0 * 1.5 * 0.75_f64.sqrt() / 0.25;
+        assert!((params.volume.value - volume).abs() < 1e-3 * volume);
+        assert!(params.volume.uncertainty > 0.0);
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let params = Gaussian2DParams {
+            mean_x: Value {
+                value: 1.0,
+                uncertainty: 0.0,
+            },
+            sigma_x: Value {
+                value: 2.0,
+                uncertainty: 0.0,
+            },
+            sigma_y: Value {
+                value: 3.0,
+                uncertainty: 0.0,
+            },
+            ..Default::default()
+        };
+
+        let points = params.ellipse(1.0, 4);
+        assert!((points[0][0] - 3.0).abs() < 1e-12);
+        assert!((points[1][1] - 3.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,100 @@
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GoodnessOfFit {
+    pub chi_squared: f64,
+    pub degrees_of_freedom: usize,
+    pub reduced_chi_squared: f64,
+    pub residuals: Vec<[f64; 2]>, // (x, y - model)
+    pub pulls: Vec<[f64; 2]>,     // (x, (y - model) / uncertainty)
+}
+
+impl GoodnessOfFit {
+    /// Compares the data to the model evaluated at the same x values.
+    /// Bins with a non-positive uncertainty are skipped in the chi² sum.
+    pub fn new(
+        x_data: &[f64],
+        y_data: &[f64],
+        y_model: &[f64],
+        y_err: &[f64],
+        number_of_parameters: usize,
+    ) -> Option<Self> {
+        if x_data.len() != y_data.len()
+            || x_data.len() != y_model.len()
+            || x_data.len() != y_err.len()
+        {
+            log::error!("Goodness of fit: data, model and uncertainties must have the same length");
+            return None;
+        }
+
+        let mut chi_squared = 0.0;
+        let mut used_bins: usize = 0;
+        let mut residuals = Vec::with_capacity(x_data.len());
+        let mut pulls = Vec::with_capacity(x_data.len());
+
+        for i in 0..x_data.len() {
+            let residual = y_data[i] - y_model[i];
+            residuals.push([x_data[i], residual]);
+
+            if y_err[i] > 0.0 {
+                let pull = residual / y_err[i];
+                chi_squared += pull * pull;
+                used_bins += 1;
+                pulls.push([x_data[i], pull]);
+            }
+        }
+
+        let degrees_of_freedom = used_bins.saturating_sub(number_of_parameters);
+        let reduced_chi_squared = if degrees_of_freedom > 0 {
+            chi_squared / degrees_of_freedom as f64
+        } else {
+            f64::NAN
+        };
+
+        Some(GoodnessOfFit {
+            chi_squared,
+            degrees_of_freedom,
+            reduced_chi_squared,
+            residuals,
+            pulls,
+        })
+    }
+
+    /// Poisson uncertainties for raw bin counts, treating empty bins as one count.
+    pub fn poisson_uncertainties(y_data: &[f64]) -> Vec<f64> {
+        y_data.iter().map(|&y| y.max(1.0).sqrt()).collect()
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "{:.2} / {} = {:.2}",
+            self.chi_squared, self.degrees_of_freedom, self.reduced_chi_squared
+        ))
+        .on_hover_text("χ² / degrees of freedom = reduced χ²");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chi_squared_and_dof() {
+        let x = vec![1.0, 2.0, 3.0, 4.0];
+        let y = vec![4.0, 9.0, 16.0, 25.0];
+        let model = vec![2.0, 9.0, 20.0, 25.0];
+        let err = GoodnessOfFit::poisson_uncertainties(&y);
+
+        let gof = GoodnessOfFit::new(&x, &y, &model, &err, 2).unwrap();
+
+        // (2/2)^2 + 0 + (-4/4)^2 + 0 = 2
+        assert!((gof.chi_squared - 2.0).abs() < 1e-12);
+        assert_eq!(gof.degrees_of_freedom, 2);
+        assert!((gof.reduced_chi_squared - 1.0).abs() < 1e-12);
+        assert_eq!(gof.residuals[2], [3.0, -4.0]);
+        assert_eq!(gof.pulls[0], [1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_mismatched_lengths() {
+        assert!(GoodnessOfFit::new(&[1.0], &[1.0, 2.0], &[1.0], &[1.0], 0).is_none());
+    }
+}
@@ -0,0 +1,282 @@
+use nalgebra::{DMatrix, DVector};
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct LikelihoodResult {
+    pub parameters: Vec<f64>,
+    pub uncertainties: Vec<f64>,
+    pub cash_statistic: f64,
+    pub iterations: usize,
+    #[serde(default)]
+    pub covariance: Vec<Vec<f64>>, // rows of the full covariance matrix, same order as the parameters
+}
+
+/// Poisson maximum-likelihood fit of `model(x, parameters) + background` to raw bin counts.
+/// Minimizes the Cash statistic C = 2 Σ (μ - n + n ln(n/μ)) with a Levenberg-Marquardt
+/// style Fisher scoring step. Uncertainties come from the inverse Hessian of -ln L.
+pub struct PoissonLikelihood<'a, F: Fn(f64, &[f64]) -> f64> {
+    x: &'a [f64],
+    y: &'a [f64],
+    background: &'a [f64],
+    model: F,
+    pub max_iterations: usize,
+    pub tolerance: f64,
+}
+
+impl<'a, F: Fn(f64, &[f64]) -> f64> PoissonLikelihood<'a, F> {
+    pub fn new(x: &'a [f64], y: &'a [f64], background: &'a [f64], model: F) -> Self {
+        Self {
+            x,
+            y,
+            background,
+            model,
+            max_iterations: 200,
+            tolerance: 1e-9,
+        }
+    }
+
+    // Expected counts in bin i
+    fn expected(&self, i: usize, parameters: &[f64]) -> f64 {
+        (self.model)(self.x[i], parameters) + self.background.get(i).copied().unwrap_or(0.0)
+    }
+
+    // Cash statistic, infinite if the model is not positive where there are counts
+    pub fn cash(&self, parameters: &[f64]) -> f64 {
+        let mut cash = 0.0;
+        for (i, &n) in self.y.iter().enumerate() {
+            let mu = self.expected(i, parameters);
+            if mu <= 0.0 || !mu.is_finite() {
+                if n > 0.0 || mu < 0.0 || !mu.is_finite() {
+                    return f64::INFINITY;
+                }
+                continue;
+            }
+
+            cash += if n > 0.0 {
+                mu - n + n * (n / mu).ln()
+            } else {
+                mu
+            };
+        }
+        2.0 * cash
+    }
+
+    fn step_size(value: f64, relative: f64) -> f64 {
+        relative * value.abs().max(1e-2)
+    }
+
+    // Central difference derivatives of the model with respect to every parameter
+    fn model_jacobian(&self, parameters: &[f64]) -> DMatrix<f64> {
+        let mut jacobian = DMatrix::zeros(self.x.len(), parameters.len());
+        let mut shifted = parameters.to_vec();
+
+        for j in 0..parameters.len() {
+            let h = Self::step_size(parameters[j], 1e-6);
+            for (i, &x) in self.x.iter().enumerate() {
+                shifted[j] = parameters[j] + h;
+                let up = (self.model)(x, &shifted);
+                shifted[j] = parameters[j] - h;
+                let down = (self.model)(x, &shifted);
+                jacobian[(i, j)] = (up - down) / (2.0 * h);
+            }
+            shifted[j] = parameters[j];
+        }
+
+        jacobian
+    }
+
+    // Gradient of -ln L and the Fisher information matrix
+    fn gradient_and_fisher(&self, parameters: &[f64]) -> (DVector<f64>, DMatrix<f64>) {
+        let jacobian = self.model_jacobian(parameters);
+        let n_params = parameters.len();
+
+        let mut gradient = DVector::zeros(n_params);
+        let mut fisher = DMatrix::zeros(n_params, n_params);
+
+        for (i, &n) in self.y.iter().enumerate() {
+            let mu = self.expected(i, parameters).max(f64::MIN_POSITIVE);
+            let row = jacobian.row(i);
+
+            gradient += row.transpose() * (1.0 - n / mu);
+            fisher += row.transpose() * row / mu;
+        }
+
+        (gradient, fisher)
+    }
+
+    // Numerical Hessian of -ln L = C / 2
+    fn hessian(&self, parameters: &[f64]) -> DMatrix<f64> {
+        let n_params = parameters.len();
+        let mut hessian = DMatrix::zeros(n_params, n_params);
+        let mut p = parameters.to_vec();
+        let f = |p: &[f64]| self.cash(p) / 2.0;
+
+        for j in 0..n_params {
+            let hj = Self::step_size(parameters[j], 1e-4);
+            for k in j..n_params {
+                let hk = Self::step_size(parameters[k], 1e-4);
+
+                let mut corner = |sj: f64, sk: f64| {
+                    p.copy_from_slice(parameters);
+                    p[j] += sj * hj;
+                    p[k] += sk * hk;
+                    f(&p)
+                };
+
+                let value = (corner(1.0, 1.0) - corner(1.0, -1.0) - corner(-1.0, 1.0)
+                    + corner(-1.0, -1.0))
+                    / (4.0 * hj * hk);
+
+                hessian[(j, k)] = value;
+                hessian[(k, j)] = value;
+            }
+        }
+
+        hessian
+    }
+
+    // Inverse Hessian, rejected unless it is finite and positive definite so the
+    // uncertainties are real
+    fn covariance(&self, parameters: &[f64]) -> Option<DMatrix<f64>> {
+        let valid = |covariance: &DMatrix<f64>| {
+            covariance.iter().all(|v| v.is_finite()) && covariance.clone().cholesky().is_some()
+        };
+
+        if let Some(covariance) = self.hessian(parameters).try_inverse() {
+            if valid(&covariance) {
+                return Some(covariance);
+            }
+        }
+
+        // fall back to the expected (Fisher) information
+        log::warn!("Likelihood Hessian is not positive definite, using the Fisher information");
+        let (_, fisher) = self.gradient_and_fisher(parameters);
+        fisher.try_inverse().filter(valid)
+    }
+
+    pub fn minimize(&self, initial_parameters: Vec<f64>) -> Result<LikelihoodResult, String> {
+        let mut parameters = initial_parameters;
+        let mut cash = self.cash(&parameters);
+        if !cash.is_finite() {
+            return Err(
+                "Initial parameters give a non-positive model where there are counts".to_string(),
+            );
+        }
+
+        let mut lambda = 1e-3;
+        let mut iterations = 0;
+        let mut converged = false;
+
+        while iterations < self.max_iterations {
+            iterations += 1;
+
+            let (gradient, fisher) = self.gradient_and_fisher(&parameters);
+
+            let mut damped = fisher.clone();
+            for j in 0..parameters.len() {
+                damped[(j, j)] += lambda * fisher[(j, j)].max(1e-12);
+            }
+
+            let step = match damped.lu().solve(&(-&gradient)) {
+                Some(step) => step,
+                None => return Err("Singular Fisher information matrix".to_string()),
+            };
+
+            let trial: Vec<f64> = parameters
+                .iter()
+                .zip(step.iter())
+                .map(|(p, s)| p + s)
+                .collect();
+            let trial_cash = self.cash(&trial);
+
+            if trial_cash.is_finite() && trial_cash <= cash {
+                let improvement = cash - trial_cash;
+                parameters = trial;
+                cash = trial_cash;
+                lambda = (lambda / 10.0).max(1e-12);
+
+                if improvement <= self.tolerance * cash.max(1.0) {
+                    converged = true;
+                    break;
+                }
+            } else {
+                lambda *= 10.0;
+                if lambda > 1e12 {
+                    return Err(format!(
+                        "No step lowers the Cash statistic after {} iterations",
+                        iterations
+                    ));
+                }
+            }
+        }
+
+        if !converged {
+            return Err(format!("Not converged after {} iterations", iterations));
+        }
+
+        let covariance = match self.covariance(&parameters) {
+            Some(covariance) => covariance,
+            None => return Err("The likelihood Hessian is not positive definite".to_string()),
+        };
+
+        let uncertainties = covariance.diagonal().iter().map(|v| v.sqrt()).collect();
+
+        Ok(LikelihoodResult {
+            parameters,
+            uncertainties,
+            cash_statistic: cash,
+            iterations,
+            covariance: covariance
+                .row_iter()
+                .map(|row| row.iter().cloned().collect())
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_model() {
+        // the maximum likelihood estimate of a constant is the mean, with variance mean / n
+        let x = vec![0.0, 1.0, 2.0, 3.0, 4.0];
+        let y = vec![3.0, 5.0, 4.0, 6.0, 2.0];
+        let background = vec![0.0; x.len()];
+        let likelihood = PoissonLikelihood::new(&x, &y, &background, |_, p: &[f64]| p[0]);
+
+        let result = likelihood.minimize(vec![1.0]).unwrap();
+
+        assert!((result.parameters[0] - 4.0).abs() < 1e-4);
+        assert!((result.uncertainties[0] - (4.0_f64 / 5.0).sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_gaussian_model() {
+        let model = |x: f64, p: &[f64]| p[0] * (-((x - p[1]).powi(2)) / (2.0 * p[2].powi(2))).exp();
+        let x: Vec<f64> = (0..41).map(|i| i as f64).collect();
+        let background = vec![2.0; x.len()];
+        let y: Vec<f64> = x
+            .iter()
+            .map(|&x| (model(x, &[50.0, 20.0, 3.0]) + 2.0).round())
+            .collect();
+
+        let likelihood = PoissonLikelihood::new(&x, &y, &background, model);
+        let result = likelihood.minimize(vec![40.0, 18.0, 4.0]).unwrap();
+
+        assert!((result.parameters[1] - 20.0).abs() < 0.05);
+        assert!((result.parameters[2] - 3.0).abs() < 0.05);
+        assert!(result.uncertainties.iter().all(|u| *u > 0.0));
+    }
+
+    #[test]
+    fn test_not_converged_is_an_error() {
+        let x = vec![0.0, 1.0, 2.0, 3.0, 4.0];
+        let y = vec![3.0, 5.0, 4.0, 6.0, 2.0];
+        let background = vec![0.0; x.len()];
+        let mut likelihood = PoissonLikelihood::new(&x, &y, &background, |_, p: &[f64]| p[0]);
+        likelihood.max_iterations = 1;
+
+        assert!(likelihood.minimize(vec![1.0]).is_err());
+    }
+}
@@ -1,17 +1,35 @@
 use log::info;
 use serde::{Deserialize, Serialize};
 
+use super::goodness_of_fit::GoodnessOfFit;
+use super::likelihood::{LikelihoodResult, PoissonLikelihood};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LinearParameters {
     pub slope: f64,
     pub intercept: f64,
+    #[serde(default)]
+    pub slope_uncertainty: f64,
+    #[serde(default)]
+    pub intercept_uncertainty: f64,
 }
 
 impl LinearParameters {
     pub fn params_ui(&self, ui: &mut egui::Ui) {
         // just display the value with 4 decimal places
-        ui.label(format!("Slope: {:.4}", self.slope));
-        ui.label(format!("Intercept: {:.4}", self.intercept));
+        if self.slope_uncertainty > 0.0 || self.intercept_uncertainty > 0.0 {
+            ui.label(format!(
+                "Slope: {:.4} ± {:.4}",
+                self.slope, self.slope_uncertainty
+            ));
+            ui.label(format!(
+                "Intercept: {:.4} ± {:.4}",
+                self.intercept, self.intercept_uncertainty
+            ));
+        } else {
+            ui.label(format!("Slope: {:.4}", self.slope));
+            ui.label(format!("Intercept: {:.4}", self.intercept));
+        }
     }
 }
 
@@ -19,8 +37,16 @@ impl LinearParameters {
 pub struct LinearFitter {
     pub x_data: Vec<f64>,
     pub y_data: Vec<f64>,
+    #[serde(default)]
+    pub y_err: Option<Vec<f64>>,
     pub fit_params: Option<LinearParameters>,
     pub fit_points: Option<Vec<[f64; 2]>>,
+    #[serde(default)]
+    pub goodness_of_fit: Option<GoodnessOfFit>,
+    #[serde(default)]
+    pub least_squares_params: Option<LinearParameters>,
+    #[serde(default)]
+    pub likelihood_result: Option<LikelihoodResult>,
 }
 
 impl LinearFitter {
@@ -29,8 +55,12 @@ impl LinearFitter {
         LinearFitter {
             x_data,
             y_data,
+            y_err: None,
             fit_params: None,
             fit_points: None,
+            goodness_of_fit: None,
+            least_squares_params: None,
+            likelihood_result: None,
         }
     }
 
@@ -45,25 +75,55 @@ impl LinearFitter {
 
         let mut covariance = 0.0;
         let mut std_dev_sqr_x = 0.0;
-        let mut std_dev_sqr_y = 0.0;
 
         for (x, y) in data_points {
             covariance += (x - mean_x) * (y - mean_y);
             std_dev_sqr_x += (x - mean_x).powi(2);
-            std_dev_sqr_y += (y - mean_y).powi(2);
         }
 
-        let std_dev_x = std_dev_sqr_x.sqrt();
-        let std_dev_y = std_dev_sqr_y.sqrt();
-        let std_dev_prod = std_dev_x * std_dev_y;
+        // all points at one x have no slope
+        if std_dev_sqr_x <= 0.0 {
+            return None;
+        }
 
-        let pcc = covariance / std_dev_prod; // Pearson's correlation constant
-        let slope = pcc * (std_dev_y / std_dev_x); // Slope of the line
+        // without the correlation coefficient, which is 0/0 for a flat line
+        let slope = covariance / std_dev_sqr_x; // Slope of the line
         let intercept = mean_y - slope * mean_x; // Y-Intercept of the line
 
         Some((slope, intercept))
     }
 
+    /// Weighted linear regression with weights 1/σ². Returns (slope, intercept, slope_err, intercept_err).
+    /// Points with a non-positive uncertainty are ignored.
+    pub fn weighted_linear_regression(
+        data_points: Vec<(f64, f64)>,
+        y_err: &[f64],
+    ) -> Option<(f64, f64, f64, f64)> {
+        let (mut s, mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+
+        for ((x, y), &err) in data_points.iter().zip(y_err) {
+            if err <= 0.0 {
+                continue;
+            }
+            let w = 1.0 / (err * err);
+            s += w;
+            sx += w * x;
+            sy += w * y;
+            sxx += w * x * x;
+            sxy += w * x * y;
+        }
+
+        let delta = s * sxx - sx * sx;
+        if delta <= 0.0 {
+            return None;
+        }
+
+        let slope = (s * sxy - sx * sy) / delta;
+        let intercept = (sxx * sy - sx * sxy) / delta;
+
+        Some((slope, intercept, (s / delta).sqrt(), (sxx / delta).sqrt()))
+    }
+
     /// Performs a linear fit on the provided data.
     pub fn perform_linear_fit(&mut self) {
         assert!(
@@ -71,6 +131,36 @@ impl LinearFitter {
             "Insufficient data for linear regression."
         );
 
+        if let Some(y_err) = &self.y_err {
+            if y_err.len() == self.y_data.len() {
+                let data_points: Vec<(f64, f64)> = self
+                    .x_data
+                    .iter()
+                    .zip(&self.y_data)
+                    .map(|(&x, &y)| (x, y))
+                    .collect();
+
+                if let Some((slope, intercept, slope_uncertainty, intercept_uncertainty)) =
+                    Self::weighted_linear_regression(data_points, y_err)
+                {
+                    self.fit_params = Some(LinearParameters {
+                        slope,
+                        intercept,
+                        slope_uncertainty,
+                        intercept_uncertainty,
+                    });
+                    self.compute_fit_line();
+                    info!(
+                        "Weighted linear fit: slope: {} ± {}, intercept: {} ± {}",
+                        slope, slope_uncertainty, intercept, intercept_uncertainty
+                    );
+                } else {
+                    self.fit_params = None;
+                }
+                return;
+            }
+        }
+
         let data_points: Vec<(f64, f64)> = self
             .x_data
             .iter()
@@ -78,7 +168,12 @@ impl LinearFitter {
             .map(|(&x, &y)| (x, y))
             .collect();
         if let Some((slope, intercept)) = Self::linear_regression(data_points) {
-            self.fit_params = Some(LinearParameters { slope, intercept });
+            self.fit_params = Some(LinearParameters {
+                slope,
+                intercept,
+                slope_uncertainty: 0.0,
+                intercept_uncertainty: 0.0,
+            });
             self.compute_fit_line();
             info!(
                 "Background Fit (linear): slope: {}, intercept: {}",
@@ -89,6 +184,48 @@ impl LinearFitter {
         }
     }
 
+    /// Refines the least squares line with a Poisson maximum-likelihood fit to the raw counts.
+    pub fn poisson_likelihood_fit(&mut self, y_counts: &[f64], background: &[f64]) {
+        let least_squares_params = match &self.fit_params {
+            Some(params) => params.clone(),
+            None => {
+                log::error!("Poisson likelihood fit needs a least squares fit as a starting point");
+                return;
+            }
+        };
+
+        let model = |x: f64, p: &[f64]| p[0] * x + p[1];
+        let likelihood = PoissonLikelihood::new(&self.x_data, y_counts, background, model);
+
+        match likelihood.minimize(vec![
+            least_squares_params.slope,
+            least_squares_params.intercept,
+        ]) {
+            Ok(result) => {
+                info!(
+                    "Poisson likelihood fit (linear): slope: {}, intercept: {}, Cash statistic: {}",
+                    result.parameters[0], result.parameters[1], result.cash_statistic
+                );
+
+                self.fit_params = Some(LinearParameters {
+                    slope: result.parameters[0],
+                    intercept: result.parameters[1],
+                    slope_uncertainty: result.uncertainties[0],
+                    intercept_uncertainty: result.uncertainties[1],
+                });
+                self.least_squares_params = Some(least_squares_params);
+                self.likelihood_result = Some(result);
+                self.compute_fit_line();
+            }
+            Err(e) => {
+                log::error!(
+                    "Poisson likelihood fit failed, keeping least squares result: {}",
+                    e
+                );
+            }
+        }
+    }
+
     /// Computes the fit line based on the fit parameters.
     fn compute_fit_line(&mut self) {
         if let Some(params) = &self.fit_params {
@@ -120,10 +257,71 @@ impl LinearFitter {
         }
     }
 
+    /// Uncertainty of the fitted line at each x, propagating the Poisson
+    /// uncertainty of the (unweighted) points used in the fit.
+    pub fn calculate_background_uncertainty(&self, x_data: &[f64]) -> Vec<f64> {
+        let count = self.x_data.len() as f64;
+        if count == 0.0 {
+            return vec![0.0; x_data.len()];
+        }
+
+        let mean_x = self.x_data.iter().sum::<f64>() / count;
+        let sxx: f64 = self.x_data.iter().map(|x| (x - mean_x).powi(2)).sum();
+
+        x_data
+            .iter()
+            .map(|&x| {
+                // the line at x is a linear combination of the fitted points
+                self.x_data
+                    .iter()
+                    .zip(&self.y_data)
+                    .map(|(&xk, &yk)| {
+                        let coefficient = if sxx > 0.0 {
+                            1.0 / count + (x - mean_x) * (xk - mean_x) / sxx
+                        } else {
+                            1.0 / count
+                        };
+                        coefficient.powi(2) * yk.max(1.0)
+                    })
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .collect()
+    }
+
+    // Evaluates the fitted line at x
+    pub fn evaluate(&self, x: f64) -> f64 {
+        self.fit_params
+            .as_ref()
+            .map_or(0.0, |params| params.slope * x + params.intercept)
+    }
+
+    // Compare the least squares and the likelihood results
+    pub fn method_comparison_ui(&self, ui: &mut egui::Ui) {
+        if let (Some(ls), Some(ml)) = (&self.least_squares_params, &self.fit_params) {
+            ui.label("Slope");
+            ui.label(format!("{:.4}", ls.slope));
+            ui.label(format!("{:.4} ± {:.4}", ml.slope, ml.slope_uncertainty));
+            ui.end_row();
+
+            ui.label("Intercept");
+            ui.label(format!("{:.4}", ls.intercept));
+            ui.label(format!(
+                "{:.4} ± {:.4}",
+                ml.intercept, ml.intercept_uncertainty
+            ));
+            ui.end_row();
+        }
+    }
+
     pub fn fit_params_ui(&self, ui: &mut egui::Ui) {
         if let Some(params) = &self.fit_params {
             params.params_ui(ui);
         }
+
+        if let Some(goodness_of_fit) = &self.goodness_of_fit {
+            goodness_of_fit.ui(ui);
+        }
     }
 }
 
@@ -140,6 +338,17 @@ mod tests {
         assert!((intercept - 2.2).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_weighted_linear_regression() {
+        // equal weights reproduce the unweighted result
+        let data_points = vec![(1.0, 2.0), (2.0, 4.0), (3.0, 5.0), (4.0, 4.0), (5.0, 5.0)];
+        let (slope, intercept, slope_err, _) =
+            LinearFitter::weighted_linear_regression(data_points, &[1.0; 5]).unwrap();
+        assert!((slope - 0.6).abs() < 1e-6);
+        assert!((intercept - 2.2).abs() < 1e-6);
+        assert!((slope_err - (1.0_f64 / 10.0).sqrt()).abs() < 1e-6);
+    }
+
     #[test]
     fn test_perform_linear_fit() {
         let x_data = vec![1.0, 2.0, 3.0, 4.0, 5.0];
@@ -150,4 +359,13 @@ mod tests {
         assert!((params.slope - 0.6).abs() < 1e-6);
         assert!((params.intercept - 2.2).abs() < 1e-6);
     }
+
+    #[test]
+    fn test_linear_regression_flat_and_vertical() {
+        let (slope, intercept) =
+            LinearFitter::linear_regression(vec![(40.5, 5.0), (60.5, 5.0)]).unwrap();
+        assert_eq!((slope, intercept), (0.0, 5.0));
+
+        assert!(LinearFitter::linear_regression(vec![(1.0, 2.0), (1.0, 3.0)]).is_none());
+    }
 }
@@ -1,5 +1,16 @@
 pub mod background_fitter;
+pub mod batch_fit;
 pub mod fit_handler;
 pub mod fit_markers;
+pub mod fit_results;
+pub mod fit_worker;
 pub mod gaussian;
+pub mod gaussian2d;
+pub mod goodness_of_fit;
+pub mod likelihood;
 pub mod linear;
+pub mod peak_finder;
+pub mod peak_library;
+pub mod polynomial;
+pub mod spectrum_background;
+pub mod trend_plot;
@@ -0,0 +1,237 @@
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PeakFinderSettings {
+    pub min_fwhm: f64,    // in bins, narrower structures are not treated as peaks
+    pub sensitivity: f64, // minimum significance of the smoothed second derivative
+    pub cluster_gap: f64, // peaks closer than this many FWHM are fit together
+    pub fit_window: f64,  // region extends this many FWHM past the outer peaks of a cluster
+}
+
+impl Default for PeakFinderSettings {
+    fn default() -> Self {
+        Self {
+            min_fwhm: 4.0,
+            sensitivity: 3.0,
+            cluster_gap: 3.0,
+            fit_window: 2.0,
+        }
+    }
+}
+
+impl PeakFinderSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("peak_finder_settings")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Min FWHM (bins)");
+                ui.add(
+                    egui::DragValue::new(&mut self.min_fwhm)
+                        .speed(0.1)
+                        .clamp_range(1.0..=f64::INFINITY),
+                )
+                .on_hover_text("Width of the narrowest peak to look for");
+                ui.end_row();
+
+                ui.label("Sensitivity (σ)");
+                ui.add(
+                    egui::DragValue::new(&mut self.sensitivity)
+                        .speed(0.1)
+                        .clamp_range(0.5..=f64::INFINITY),
+                )
+                .on_hover_text("Lower values find smaller peaks but more false positives");
+                ui.end_row();
+
+                ui.label("Cluster gap (FWHM)");
+                ui.add(
+                    egui::DragValue::new(&mut self.cluster_gap)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f64::INFINITY),
+                )
+                .on_hover_text("Peaks closer than this are fit together as one multiplet");
+                ui.end_row();
+
+                ui.label("Fit window (FWHM)");
+                ui.add(
+                    egui::DragValue::new(&mut self.fit_window)
+                        .speed(0.1)
+                        .clamp_range(0.5..=f64::INFINITY),
+                )
+                .on_hover_text("Distance from the outer peaks to the edge of the fit region");
+                ui.end_row();
+            });
+    }
+}
+
+/// Finds peaks with a smoothed second derivative (Mariscotti) filter.
+/// The kernel has zero sum so a linear background does not contribute, and the
+/// response of each bin is compared to its Poisson uncertainty.
+pub struct PeakFinder<'a> {
+    pub counts: &'a [f64],
+    pub settings: &'a PeakFinderSettings,
+}
+
+impl<'a> PeakFinder<'a> {
+    pub fn new(counts: &'a [f64], settings: &'a PeakFinderSettings) -> Self {
+        Self { counts, settings }
+    }
+
+    // Negative second derivative of a gaussian matched to the minimum width, shifted to zero sum
+    fn kernel(&self) -> Vec<f64> {
+        let sigma = (self.settings.min_fwhm / 2.355).max(0.5);
+        let half_width = (3.0 * sigma).ceil() as i64;
+
+        let mut kernel: Vec<f64> = (-half_width..=half_width)
+            .map(|i| {
+                let z2 = (i * i) as f64 / (sigma * sigma);
+                (1.0 - z2) * (-0.5 * z2).exp()
+            })
+            .collect();
+
+        let mean = kernel.iter().sum::<f64>() / kernel.len() as f64;
+        kernel.iter_mut().for_each(|k| *k -= mean);
+
+        kernel
+    }
+
+    /// Filter response and its significance for every bin. Bins too close to the
+    /// edges for the full kernel get zero.
+    pub fn significance(&self) -> (Vec<f64>, Vec<f64>) {
+        let kernel = self.kernel();
+        let half_width = kernel.len() / 2;
+        let n = self.counts.len();
+
+        let mut response = vec![0.0; n];
+        let mut significance = vec![0.0; n];
+
+        if n < kernel.len() {
+            return (response, significance);
+        }
+
+        for i in half_width..n - half_width {
+            let mut sum = 0.0;
+            let mut variance = 0.0;
+            for (j, k) in kernel.iter().enumerate() {
+                let y = self.counts[i + j - half_width];
+                sum += k * y;
+                variance += k * k * y.max(1.0);
+            }
+            response[i] = sum;
+            significance[i] = sum / variance.sqrt();
+        }
+
+        (response, significance)
+    }
+
+    /// Peak positions in fractional bin index, sorted ascending.
+    pub fn find_peaks(&self) -> Vec<f64> {
+        let (response, significance) = self.significance();
+        let n = self.counts.len();
+        if n < 3 {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<(usize, f64)> = (1..n - 1)
+            .filter(|&i| {
+                significance[i] > self.settings.sensitivity
+                    && response[i] >= response[i - 1]
+                    && response[i] > response[i + 1]
+            })
+            .map(|i| (i, significance[i]))
+            .collect();
+
+        // keep the most significant peak when two are closer than the minimum width
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut accepted: Vec<usize> = Vec::new();
+        for (i, _) in candidates {
+            if accepted
+                .iter()
+                .all(|&j| (i as f64 - j as f64).abs() >= self.settings.min_fwhm)
+            {
+                accepted.push(i);
+            }
+        }
+
+        let mut peaks: Vec<f64> = accepted
+            .into_iter()
+            .map(|i| {
+                // parabolic interpolation of the filter response for a sub-bin position
+                let (left, center, right) = (response[i - 1], response[i], response[i + 1]);
+                let denominator = left - 2.0 * center + right;
+                let offset = if denominator.abs() > f64::EPSILON {
+                    0.5 * (left - right) / denominator
+                } else {
+                    0.0
+                };
+                i as f64 + offset.clamp(-0.5, 0.5)
+            })
+            .collect();
+
+        peaks.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        peaks
+    }
+
+    /// Groups sorted peak positions (in bins) whose neighbours are closer than the cluster gap.
+    pub fn clusters(&self, peaks: &[f64]) -> Vec<Vec<f64>> {
+        let max_gap = self.settings.cluster_gap * self.settings.min_fwhm;
+        let mut clusters: Vec<Vec<f64>> = Vec::new();
+
+        for &peak in peaks {
+            match clusters.last_mut() {
+                Some(cluster) if peak - cluster.last().copied().unwrap_or(peak) <= max_gap => {
+                    cluster.push(peak)
+                }
+                _ => clusters.push(vec![peak]),
+            }
+        }
+
+        clusters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum() -> Vec<f64> {
+        // two gaussian peaks on a sloped background
+        (0..200)
+            .map(|i| {
+                let x = i as f64;
+                let background = 20.0 + 0.05 * x;
+                let peak1 = 200.0 * (-0.5 * ((x - 60.0) / 2.5).powi(2)).exp();
+                let peak2 = 80.0 * (-0.5 * ((x - 140.3) / 2.5).powi(2)).exp();
+                background + peak1 + peak2
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_peaks() {
+        let counts = spectrum();
+        let settings = PeakFinderSettings::default();
+        let peaks = PeakFinder::new(&counts, &settings).find_peaks();
+
+        assert_eq!(peaks.len(), 2);
+        assert!((peaks[0] - 60.0).abs() < 0.5);
+        assert!((peaks[1] - 140.3).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_flat_spectrum_has_no_peaks() {
+        let counts = vec![50.0; 100];
+        let settings = PeakFinderSettings::default();
+        assert!(PeakFinder::new(&counts, &settings).find_peaks().is_empty());
+    }
+
+    #[test]
+    fn test_clusters() {
+        let counts = vec![];
+        let settings = PeakFinderSettings::default();
+        let finder = PeakFinder::new(&counts, &settings);
+
+        let clusters = finder.clusters(&[10.0, 15.0, 50.0, 58.0, 100.0]);
+        assert_eq!(
+            clusters,
+            vec![vec![10.0, 15.0], vec![50.0, 58.0], vec![100.0]]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use rfd::FileDialog;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// One gamma line of a nucleus, e.g. exported from ENSDF.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LibraryLine {
+    pub nucleus: String,
+    pub energy: f64,
+    #[serde(default)]
+    pub energy_uncertainty: f64,
+    #[serde(default)]
+    pub intensity: Option<f64>,
+    #[serde(default)]
+    pub transition: String, // e.g. "2+ → 0+"
+}
+
+impl LibraryLine {
+    pub fn label(&self) -> String {
+        if self.transition.is_empty() {
+            format!("{} {:.1}", self.nucleus, self.energy)
+        } else {
+            format!("{} {:.1} ({})", self.nucleus, self.energy, self.transition)
+        }
+    }
+}
+
+// Split a CSV line on commas outside of double quotes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Parses a table with a header row. Only the nucleus and energy columns are required,
+/// lines starting with # are skipped.
+pub fn parse_csv(contents: &str) -> Result<Vec<LibraryLine>, String> {
+    let mut rows = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let header: Vec<String> = rows
+        .next()
+        .map(|line| {
+            split_csv_line(line)
+                .into_iter()
+                .map(|h| h.to_lowercase())
+                .collect()
+        })
+        .ok_or("Empty peak library")?;
+
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+
+    let nucleus = column(&["nucleus", "isotope", "nuclide"]).ok_or("Missing nucleus column")?;
+    let energy = column(&["energy", "e_gamma", "egamma"]).ok_or("Missing energy column")?;
+    let energy_uncertainty = column(&["energy_uncertainty", "denergy", "de_gamma"]);
+    let intensity = column(&["intensity", "i_gamma", "igamma"]);
+    let transition = column(&["transition", "assignment", "label"]);
+
+    rows.enumerate()
+        .map(|(i, line)| {
+            let fields = split_csv_line(line);
+            let get = |index: Option<usize>| {
+                index
+                    .and_then(|index| fields.get(index))
+                    .filter(|f| !f.is_empty())
+            };
+
+            let energy_value = get(Some(energy))
+                .and_then(|e| e.parse::<f64>().ok())
+                .ok_or(format!("Invalid energy in row {}", i + 1))?;
+
+            Ok(LibraryLine {
+                nucleus: get(Some(nucleus)).cloned().unwrap_or_default(),
+                energy: energy_value,
+                energy_uncertainty: get(energy_uncertainty)
+                    .and_then(|e| e.parse().ok())
+                    .unwrap_or(0.0),
+                intensity: get(intensity).and_then(|e| e.parse().ok()),
+                transition: get(transition).cloned().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Known gamma lines drawn over the spectrum and matched to fitted centroids.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PeakLibrary {
+    pub lines: Vec<LibraryLine>,
+    pub source: String,          // file the lines were loaded from
+    pub nucleus: Option<String>, // None uses the lines of every nucleus
+    pub tolerance: f64,
+    pub show_overlay: bool,
+    pub show_labels: bool,
+}
+
+impl Default for PeakLibrary {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            source: String::new(),
+            nucleus: None,
+            tolerance: 2.0,
+            show_overlay: true,
+            show_labels: true,
+        }
+    }
+}
+
+impl PeakLibrary {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Error reading {}: {:?}", path.display(), e))?;
+
+        let is_json = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+
+        let lines = if is_json {
+            serde_json::from_str::<Vec<LibraryLine>>(&contents).map_err(|e| e.to_string())?
+        } else {
+            parse_csv(&contents)?
+        };
+
+        self.lines = lines;
+        self.source = path.display().to_string();
+        self.nucleus = None;
+
+        Ok(())
+    }
+
+    fn load_from_file(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .add_filter("Peak Library", &["csv", "json"])
+            .pick_file()
+        {
+            if let Err(e) = self.load(&path) {
+                log::error!("Failed to load peak library: {}", e);
+            }
+        }
+    }
+
+    pub fn nuclei(&self) -> Vec<String> {
+        let mut nuclei: Vec<String> = self.lines.iter().map(|l| l.nucleus.clone()).collect();
+        nuclei.sort();
+        nuclei.dedup();
+        nuclei
+    }
+
+    pub fn selected_lines(&self) -> impl Iterator<Item = &LibraryLine> {
+        self.lines
+            .iter()
+            .filter(|line| self.nucleus.as_ref().map_or(true, |n| *n == line.nucleus))
+    }
+
+    // Closest library line within the tolerance of the centroid
+    pub fn identify(&self, centroid: f64) -> Option<&LibraryLine> {
+        self.selected_lines()
+            .filter(|line| (line.energy - centroid).abs() <= self.tolerance)
+            .min_by(|a, b| {
+                (a.energy - centroid)
+                    .abs()
+                    .total_cmp(&(b.energy - centroid).abs())
+            })
+    }
+
+    pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi, log_x: bool) {
+        if !self.show_overlay {
+            return;
+        }
+
+        let color = egui::Color32::from_rgb(0, 150, 150);
+        let bounds = plot_ui.plot_bounds();
+        let top = bounds.max()[1];
+
+        for line in self.selected_lines() {
+            let x = if log_x {
+                if line.energy <= 0.0 {
+                    continue;
+                }
+                line.energy.log10()
+            } else {
+                line.energy
+            };
+
+            if x < bounds.min()[0] || x > bounds.max()[0] {
+                continue;
+            }
+
+            plot_ui.vline(
+                egui_plot::VLine::new(x)
+                    .color(color)
+                    .style(egui_plot::LineStyle::dashed_loose()),
+            );
+
+            if self.show_labels {
+                plot_ui.text(
+                    egui_plot::Text::new(egui_plot::PlotPoint::new(x, top), line.label())
+                        .color(color)
+                        .anchor(egui::Align2::LEFT_TOP),
+                );
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Load Library").clicked() {
+                self.load_from_file();
+            }
+
+            if !self.is_empty() && ui.button("Clear").clicked() {
+                *self = Self::default();
+            }
+        });
+
+        if self.is_empty() {
+            ui.label("CSV with nucleus and energy columns, or JSON");
+            return;
+        }
+
+        ui.label(format!("{} lines from {}", self.lines.len(), self.source));
+
+        egui::ComboBox::from_label("Nucleus")
+            .selected_text(self.nucleus.clone().unwrap_or("All".to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.nucleus, None, "All");
+                for nucleus in self.nuclei() {
+                    ui.selectable_value(&mut self.nucleus, Some(nucleus.clone()), nucleus);
+                }
+            });
+
+        ui.add(
+            egui::DragValue::new(&mut self.tolerance)
+                .speed(0.1)
+                .clamp_range(0.0..=f64::INFINITY)
+                .prefix("Tolerance: "),
+        )
+        .on_hover_text("Largest distance between a centroid and a library line to identify it");
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_overlay, "Show Lines");
+            ui.checkbox(&mut self.show_labels, "Show Labels");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "# 60Co lines\nNucleus,Energy,Intensity,Transition\n60Ni,1173.228,99.85,4+ → 2+\n60Ni,1332.492,99.98,\"2+ → 0+\"\n";
+        let lines = parse_csv(csv).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].nucleus, "60Ni");
+        assert_eq!(lines[1].energy, 1332.492);
+        assert_eq!(lines[1].intensity, Some(99.98));
+        assert_eq!(lines[1].transition, "2+ → 0+");
+
+        assert!(parse_csv("Nucleus,Intensity\n60Ni,1.0").is_err());
+    }
+
+    #[test]
+    fn test_identify() {
+        let mut library = PeakLibrary {
+            lines: parse_csv("nucleus,energy\n60Ni,1173.2\n60Ni,1332.5\n22Na,1274.5").unwrap(),
+            ..Default::default()
+        };
+
+        assert_eq!(library.identify(1331.0).map(|l| l.energy), Some(1332.5));
+        assert!(library.identify(1300.0).is_none());
+
+        library.nucleus = Some("22Na".to_string());
+        assert!(library.identify(1173.0).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use nalgebra::{DMatrix, DVector};
+
+use super::gaussian::Value;
+
+/// Least squares polynomial c0 + c1 x + c2 x² + ..., weighted by 1/σ² when uncertainties are given.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct PolynomialFitter {
+    pub x_data: Vec<f64>,
+    pub y_data: Vec<f64>,
+    pub y_err: Option<Vec<f64>>,
+    pub degree: usize,
+    pub coefficients: Option<Vec<Value>>,
+}
+
+impl PolynomialFitter {
+    pub fn new(x_data: Vec<f64>, y_data: Vec<f64>, degree: usize) -> Self {
+        Self {
+            x_data,
+            y_data,
+            y_err: None,
+            degree,
+            coefficients: None,
+        }
+    }
+
+    pub fn fit(&mut self) {
+        self.coefficients = None;
+
+        let n = self.x_data.len();
+        let number_of_parameters = self.degree + 1;
+        if n != self.y_data.len() || n < number_of_parameters {
+            log::error!(
+                "Polynomial fit of degree {} needs at least {} points",
+                self.degree,
+                number_of_parameters
+            );
+            return;
+        }
+
+        let weights: Vec<f64> = match &self.y_err {
+            Some(y_err) if y_err.len() == n => y_err
+                .iter()
+                .map(|&e| if e > 0.0 { 1.0 / (e * e) } else { 0.0 })
+                .collect(),
+            _ => vec![1.0; n],
+        };
+
+        let design = DMatrix::from_fn(n, number_of_parameters, |i, j| {
+            self.x_data[i].powi(j as i32)
+        });
+        let weighted_design =
+            DMatrix::from_fn(n, number_of_parameters, |i, j| design[(i, j)] * weights[i]);
+        let y = DVector::from_column_slice(&self.y_data);
+
+        let normal = design.transpose() * &weighted_design;
+        let Some(covariance) = normal.try_inverse() else {
+            log::error!("Polynomial fit failed: singular normal matrix");
+            return;
+        };
+        let coefficients = &covariance * weighted_design.transpose() * &y;
+
+        // without uncertainties the scatter of the points sets the scale of the errors
+        let scale = if self.y_err.is_some() || n == number_of_parameters {
+            1.0
+        } else {
+            let residuals = &y - &design * &coefficients;
+            residuals.norm_squared() / (n - number_of_parameters) as f64
+        };
+
+        self.coefficients = Some(
+            (0..number_of_parameters)
+                .map(|j| Value {
+                    value: coefficients[j],
+                    uncertainty: (covariance[(j, j)] * scale).max(0.0).sqrt(),
+                })
+                .collect(),
+        );
+    }
+
+    pub fn evaluate(&self, x: f64) -> f64 {
+        self.coefficients.as_ref().map_or(0.0, |coefficients| {
+            coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |sum, c| sum * x + c.value)
+        })
+    }
+
+    pub fn fit_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
+        let min_x = self.x_data.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self
+            .x_data
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let step = (max_x - min_x) / num_points.max(1) as f64;
+
+        (0..=num_points)
+            .map(|i| {
+                let x = min_x + step * i as f64;
+                [x, self.evaluate(x)]
+            })
+            .collect()
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui) {
+        if let Some(coefficients) = &self.coefficients {
+            for (i, c) in coefficients.iter().enumerate() {
+                ui.label(format!("c{}: {:.4e} ± {:.4e}", i, c.value, c.uncertainty));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadratic_fit() {
+        let x: Vec<f64> = (0..10).map(|i| i as f64).collect();
+        let y: Vec<f64> = x.iter().map(|x| 1.0 - 2.0 * x + 0.5 * x * x).collect();
+
+        let mut fitter = PolynomialFitter::new(x, y, 2);
+        fitter.y_err = Some(vec![1.0; 10]);
+        fitter.fit();
+
+        let coefficients = fitter.coefficients.unwrap();
+        assert!((coefficients[0].value - 1.0).abs() < 1e-9);
+        assert!((coefficients[1].value + 2.0).abs() < 1e-9);
+        assert!((coefficients[2].value - 0.5).abs() < 1e-9);
+        assert!(coefficients[2].uncertainty > 0.0);
+    }
+
+    #[test]
+    fn test_too_few_points() {
+        let mut fitter = PolynomialFitter::new(vec![1.0, 2.0], vec![1.0, 2.0], 2);
+        fitter.fit();
+        assert!(fitter.coefficients.is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::egui_plot_stuff::egui_line::EguiLine;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum BackgroundAlgorithm {
+    #[default]
+    Snip,
+    RollingBall,
+}
+
+impl BackgroundAlgorithm {
+    pub fn name(&self) -> &str {
+        match self {
+            BackgroundAlgorithm::Snip => "SNIP",
+            BackgroundAlgorithm::RollingBall => "Rolling Ball",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SpectrumBackgroundSettings {
+    pub algorithm: BackgroundAlgorithm,
+    pub window: usize,     // SNIP clipping window or rolling ball radius, in bins
+    pub iterations: usize, // number of SNIP passes over the full window
+    pub show: bool,
+    pub use_in_fits: bool,
+}
+
+impl Default for SpectrumBackgroundSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: BackgroundAlgorithm::Snip,
+            window: 20,
+            iterations: 1,
+            show: true,
+            use_in_fits: false,
+        }
+    }
+}
+
+/// Background estimate over the whole spectrum, one value per bin.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SpectrumBackground {
+    pub settings: SpectrumBackgroundSettings,
+    pub values: Vec<f64>,
+    pub line: EguiLine,
+}
+
+impl Default for SpectrumBackground {
+    fn default() -> Self {
+        let mut line = EguiLine::new(egui::Color32::from_rgb(255, 140, 0));
+        line.name = "Spectrum Background".to_string();
+        line.name_in_legend = true;
+
+        Self {
+            settings: SpectrumBackgroundSettings::default(),
+            values: Vec::new(),
+            line,
+        }
+    }
+}
+
+impl SpectrumBackground {
+    pub fn is_calculated(&self) -> bool {
+        !self.values.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.line.points.clear();
+    }
+
+    // Estimate the background of the counts, the line is drawn as steps over the bin edges like the histogram
+    pub fn calculate(&mut self, counts: &[f64], range_min: f64, bin_width: f64) {
+        self.values = match self.settings.algorithm {
+            BackgroundAlgorithm::Snip => {
+                snip(counts, self.settings.window, self.settings.iterations)
+            }
+            BackgroundAlgorithm::RollingBall => rolling_ball(counts, self.settings.window),
+        };
+
+        self.line.points = self
+            .values
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &y)| {
+                let start = range_min + i as f64 * bin_width;
+                vec![[start, y], [start + bin_width, y]]
+            })
+            .collect();
+    }
+
+    // Points (bin center, background) for a fixed background in a fitter
+    pub fn points(&self, range_min: f64, bin_width: f64) -> Vec<[f64; 2]> {
+        self.values
+            .iter()
+            .enumerate()
+            .map(|(i, &y)| [range_min + (i as f64 + 0.5) * bin_width, y])
+            .collect()
+    }
+
+    pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
+        if self.settings.show {
+            self.line.draw(plot_ui);
+        }
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Algorithm: ");
+            for algorithm in [BackgroundAlgorithm::Snip, BackgroundAlgorithm::RollingBall] {
+                ui.radio_value(&mut self.settings.algorithm, algorithm, algorithm.name());
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let label = match self.settings.algorithm {
+                BackgroundAlgorithm::Snip => "Window: ",
+                BackgroundAlgorithm::RollingBall => "Radius: ",
+            };
+            ui.add(
+                egui::DragValue::new(&mut self.settings.window)
+                    .clamp_range(1..=10000)
+                    .prefix(label)
+                    .suffix(" bins"),
+            )
+            .on_hover_text(
+                "Should be wider than the peaks but narrower than background structures",
+            );
+
+            if self.settings.algorithm == BackgroundAlgorithm::Snip {
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.iterations)
+                        .clamp_range(1..=100)
+                        .prefix("Iterations: "),
+                );
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.show, "Show");
+            ui.checkbox(&mut self.settings.use_in_fits, "Use in fits")
+                .on_hover_text(
+                    "Use this background instead of the linear background when fitting gaussians",
+                );
+        });
+    }
+}
+
+// Log-log-square root transform used by SNIP to compress the dynamic range
+fn lls(y: f64) -> f64 {
+    ((y.max(0.0) + 1.0).sqrt() + 1.0).ln().ln_1p()
+}
+
+fn lls_inverse(v: f64) -> f64 {
+    let inner = v.exp_m1().exp() - 1.0;
+    (inner * inner - 1.0).max(0.0)
+}
+
+/// Statistics-sensitive non-linear iterative peak clipping (Ryan et al. 1988).
+/// Each pass clips every bin to the mean of its neighbours at distance p, for p
+/// increasing up to the window, which removes peaks narrower than the window.
+pub fn snip(counts: &[f64], window: usize, iterations: usize) -> Vec<f64> {
+    let n = counts.len();
+    let mut v: Vec<f64> = counts.iter().map(|&y| lls(y)).collect();
+    let mut clipped = v.clone();
+
+    for _ in 0..iterations.max(1) {
+        for p in 1..=window {
+            if 2 * p >= n {
+                break;
+            }
+            for i in p..n - p {
+                let mean = 0.5 * (v[i - p] + v[i + p]);
+                clipped[i] = v[i].min(mean);
+            }
+            v[p..n - p].copy_from_slice(&clipped[p..n - p]);
+        }
+    }
+
+    v.into_iter().map(lls_inverse).collect()
+}
+
+/// Morphological opening (minimum then maximum filter) followed by a moving
+/// average over the same radius, which rolls a flat ball under the spectrum.
+/// The result never exceeds the counts.
+pub fn rolling_ball(counts: &[f64], radius: usize) -> Vec<f64> {
+    let window = |i: usize| (i.saturating_sub(radius), (i + radius + 1).min(counts.len()));
+
+    let eroded: Vec<f64> = (0..counts.len())
+        .map(|i| {
+            let (start, end) = window(i);
+            counts[start..end]
+                .iter()
+                .cloned()
+                .fold(f64::INFINITY, f64::min)
+        })
+        .collect();
+
+    let opened: Vec<f64> = (0..counts.len())
+        .map(|i| {
+            let (start, end) = window(i);
+            eroded[start..end]
+                .iter()
+                .cloned()
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect();
+
+    (0..counts.len())
+        .map(|i| {
+            let (start, end) = window(i);
+            let smoothed = opened[start..end].iter().sum::<f64>() / (end - start) as f64;
+            // smoothing can lift the estimate over a sloped spectrum, keep it under the data
+            smoothed.min(counts[i])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak_on_background() -> Vec<f64> {
+        (0..200)
+            .map(|i| {
+                let x = i as f64;
+                100.0 + 0.2 * x + 500.0 * (-0.5 * ((x - 100.0) / 3.0).powi(2)).exp()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lls_round_trip() {
+        for y in [0.0, 1.0, 10.0, 1234.5] {
+            assert!((lls_inverse(lls(y)) - y).abs() < 1e-6 * y.max(1.0));
+        }
+    }
+
+    #[test]
+    fn test_snip_removes_peak() {
+        let counts = peak_on_background();
+        let background = snip(&counts, 20, 1);
+
+        // the peak is clipped down to roughly the linear background
+        assert!((background[100] - 120.0).abs() < 5.0);
+        // far from the peak the background follows the data
+        assert!((background[30] - counts[30]).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_rolling_ball_removes_peak() {
+        let counts = peak_on_background();
+        let background = rolling_ball(&counts, 15);
+
+        assert!((background[100] - 120.0).abs() < 5.0);
+        assert!(background.iter().zip(counts.iter()).all(|(b, c)| b <= c));
+    }
+}
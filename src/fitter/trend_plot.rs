@@ -0,0 +1,312 @@
+use super::fit_results::FitResultRow;
+use super::linear::LinearFitter;
+use super::polynomial::PolynomialFitter;
+use crate::egui_plot_stuff::egui_line::EguiLine;
+use crate::workspacer::labelled_run_number;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum TrendQuantity {
+    #[default]
+    Centroid,
+    Fwhm,
+    Area,
+    ReducedChiSquared,
+    HistogramIndex,
+    RunNumber,
+}
+
+impl TrendQuantity {
+    const ALL: [TrendQuantity; 6] = [
+        TrendQuantity::Centroid,
+        TrendQuantity::Fwhm,
+        TrendQuantity::Area,
+        TrendQuantity::ReducedChiSquared,
+        TrendQuantity::HistogramIndex,
+        TrendQuantity::RunNumber,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            TrendQuantity::Centroid => "Centroid",
+            TrendQuantity::Fwhm => "FWHM",
+            TrendQuantity::Area => "Area",
+            TrendQuantity::ReducedChiSquared => "χ²/dof",
+            TrendQuantity::HistogramIndex => "Histogram",
+            TrendQuantity::RunNumber => "Run",
+        }
+    }
+
+    // Value and uncertainty of the quantity for a row, histogram_index is the position of its histogram
+    fn value(&self, row: &FitResultRow, histogram_index: usize) -> Option<(f64, f64)> {
+        match self {
+            TrendQuantity::Centroid => Some((row.centroid, row.centroid_uncertainty)),
+            TrendQuantity::Fwhm => Some((row.fwhm, row.fwhm_uncertainty)),
+            TrendQuantity::Area => Some((row.area, row.area_uncertainty)),
+            TrendQuantity::ReducedChiSquared => row
+                .reduced_chi_squared
+                .filter(|chi| chi.is_finite())
+                .map(|chi| (chi, 0.0)),
+            TrendQuantity::HistogramIndex => Some((histogram_index as f64, 0.0)),
+            // digits elsewhere in the name are detector numbers, e.g. Cebra3Energy
+            TrendQuantity::RunNumber => {
+                labelled_run_number(&row.histogram).map(|run| (run as f64, 0.0))
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum TrendFitModel {
+    #[default]
+    None,
+    Linear,
+    Polynomial,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum TrendFit {
+    Linear(Box<LinearFitter>),
+    Polynomial(PolynomialFitter),
+}
+
+/// Plots one quantity of the stored fits against another, e.g. FWHM vs centroid.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrendPlot {
+    pub x_quantity: TrendQuantity,
+    pub y_quantity: TrendQuantity,
+    pub histogram_filter: String,
+    pub peak: Option<usize>,
+    pub fit_model: TrendFitModel,
+    pub polynomial_degree: usize,
+    pub fit: Option<TrendFit>,
+    pub fit_line: EguiLine,
+
+    #[serde(skip)]
+    pub rows: Vec<FitResultRow>,
+}
+
+impl Default for TrendPlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrendPlot {
+    pub fn new() -> Self {
+        let mut fit_line = EguiLine::new(egui::Color32::BLUE);
+        fit_line.name = "Trend Fit".to_string();
+
+        Self {
+            x_quantity: TrendQuantity::Centroid,
+            y_quantity: TrendQuantity::Fwhm,
+            histogram_filter: String::new(),
+            peak: None,
+            fit_model: TrendFitModel::None,
+            polynomial_degree: 2,
+            fit: None,
+            fit_line,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Points as (x, y, x uncertainty, y uncertainty) for the rows passing the filters.
+    pub fn points(&self) -> Vec<[f64; 4]> {
+        let filter = self.histogram_filter.to_lowercase();
+
+        // histograms are numbered in the order they first appear
+        let mut histograms: Vec<&str> = Vec::new();
+
+        self.rows
+            .iter()
+            .filter(|row| filter.is_empty() || row.histogram.to_lowercase().contains(&filter))
+            .filter(|row| self.peak.map_or(true, |peak| row.peak == peak))
+            .filter_map(|row| {
+                let index = match histograms.iter().position(|h| *h == row.histogram) {
+                    Some(index) => index,
+                    None => {
+                        histograms.push(&row.histogram);
+                        histograms.len() - 1
+                    }
+                };
+
+                let (x, x_err) = self.x_quantity.value(row, index)?;
+                let (y, y_err) = self.y_quantity.value(row, index)?;
+                Some([x, y, x_err, y_err])
+            })
+            .collect()
+    }
+
+    fn fit_points(&mut self) {
+        self.fit = None;
+        self.fit_line.points.clear();
+
+        let points = self.points();
+        let x_data: Vec<f64> = points.iter().map(|p| p[0]).collect();
+        let y_data: Vec<f64> = points.iter().map(|p| p[1]).collect();
+
+        // weight by the y uncertainties only if every point has one
+        let y_err: Option<Vec<f64>> = if points.iter().all(|p| p[3] > 0.0) {
+            Some(points.iter().map(|p| p[3]).collect())
+        } else {
+            None
+        };
+
+        match self.fit_model {
+            TrendFitModel::None => {}
+            TrendFitModel::Linear => {
+                if x_data.len() < 2 {
+                    log::error!("Need at least two points to fit a line");
+                    return;
+                }
+
+                let mut fitter = LinearFitter::new(x_data, y_data);
+                fitter.y_err = y_err;
+                fitter.perform_linear_fit();
+
+                if let Some(points) = &fitter.fit_points {
+                    self.fit_line.points.clone_from(points);
+                }
+                self.fit = Some(TrendFit::Linear(Box::new(fitter)));
+            }
+            TrendFitModel::Polynomial => {
+                let mut fitter = PolynomialFitter::new(x_data, y_data, self.polynomial_degree);
+                fitter.y_err = y_err;
+                fitter.fit();
+
+                if fitter.coefficients.is_some() {
+                    self.fit_line.points = fitter.fit_line_points(500);
+                }
+                self.fit = Some(TrendFit::Polynomial(fitter));
+            }
+        }
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for (label, quantity) in [("X", &mut self.x_quantity), ("Y", &mut self.y_quantity)] {
+                egui::ComboBox::from_label(label)
+                    .selected_text(quantity.name())
+                    .show_ui(ui, |ui| {
+                        for option in TrendQuantity::ALL {
+                            ui.selectable_value(quantity, option, option.name());
+                        }
+                    });
+            }
+
+            ui.separator();
+
+            ui.label("Histograms: ");
+            ui.text_edit_singleline(&mut self.histogram_filter)
+                .on_hover_text("Only use fits from histograms containing this text");
+
+            ui.separator();
+
+            let mut filter_peak = self.peak.is_some();
+            ui.checkbox(&mut filter_peak, "Peak");
+            if filter_peak {
+                let mut peak = self.peak.unwrap_or(0);
+                ui.add(egui::DragValue::new(&mut peak).clamp_range(0..=100));
+                self.peak = Some(peak);
+            } else {
+                self.peak = None;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Fit: ");
+            ui.radio_value(&mut self.fit_model, TrendFitModel::None, "None");
+            ui.radio_value(&mut self.fit_model, TrendFitModel::Linear, "Linear");
+            ui.radio_value(&mut self.fit_model, TrendFitModel::Polynomial, "Polynomial");
+
+            if self.fit_model == TrendFitModel::Polynomial {
+                ui.add(
+                    egui::DragValue::new(&mut self.polynomial_degree)
+                        .clamp_range(1..=6)
+                        .prefix("Degree: "),
+                );
+            }
+
+            if ui.button("Fit").clicked() {
+                self.fit_points();
+            }
+
+            ui.separator();
+
+            match &self.fit {
+                Some(TrendFit::Linear(fitter)) => {
+                    if let Some(params) = &fitter.fit_params {
+                        ui.label(format!(
+                            "Slope: {:.4e} ± {:.4e}, Intercept: {:.4e} ± {:.4e}",
+                            params.slope,
+                            params.slope_uncertainty,
+                            params.intercept,
+                            params.intercept_uncertainty
+                        ));
+                    }
+                }
+                Some(TrendFit::Polynomial(fitter)) => fitter.params_ui(ui),
+                None => {}
+            }
+        });
+    }
+
+    fn draw_error_bars(plot_ui: &mut egui_plot::PlotUi, points: &[[f64; 4]], color: egui::Color32) {
+        for &[x, y, x_err, y_err] in points {
+            if y_err > 0.0 {
+                plot_ui
+                    .line(egui_plot::Line::new(vec![[x, y - y_err], [x, y + y_err]]).color(color));
+            }
+            if x_err > 0.0 {
+                plot_ui
+                    .line(egui_plot::Line::new(vec![[x - x_err, y], [x + x_err, y]]).color(color));
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.controls_ui(ui);
+
+        ui.separator();
+
+        let points = self.points();
+        let color = egui::Color32::from_rgb(120, 47, 64);
+
+        egui_plot::Plot::new("Trend Plot")
+            .x_axis_label(self.x_quantity.name())
+            .y_axis_label(self.y_quantity.name())
+            .legend(egui_plot::Legend::default())
+            .show(ui, |plot_ui| {
+                Self::draw_error_bars(plot_ui, &points, color);
+
+                let xy: Vec<[f64; 2]> = points.iter().map(|p| [p[0], p[1]]).collect();
+                plot_ui.points(
+                    egui_plot::Points::new(xy)
+                        .radius(3.0)
+                        .color(color)
+                        .name("Fits"),
+                );
+
+                if self.fit.is_some() {
+                    self.fit_line.draw(plot_ui);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_number_quantity() {
+        let row = |histogram: &str| FitResultRow {
+            histogram: histogram.to_string(),
+            ..Default::default()
+        };
+
+        let run = TrendQuantity::RunNumber;
+        assert_eq!(run.value(&row("Xavg_run_0042"), 0), Some((42.0, 0.0)));
+        assert_eq!(run.value(&row("Cebra3Energy"), 0), None);
+        assert_eq!(run.value(&row("X1"), 0), None);
+    }
+}
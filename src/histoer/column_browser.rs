@@ -0,0 +1,448 @@
+use super::histogram1d::Histogram;
+use super::histogram2d::Histogram2D;
+use super::histogram_script::add_columns;
+use super::histogrammer::Histogrammer;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use polars::prelude::*;
+
+/// Summary of a column, computed when asked for since it reads the whole column.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnStats {
+    pub nulls: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub dtype: DataType,
+    pub stats: Option<ColumnStats>,
+}
+
+/// Lists the raw and calculated columns of the selected files and makes quick histograms of them.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ColumnBrowser {
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+    #[serde(skip)]
+    pub columns: Vec<ColumnInfo>,
+    #[serde(skip)]
+    schema_loaded: bool, // also set when reading failed, so it is not retried every frame
+    pub filter: String,
+    pub bins_1d: usize,
+    pub bins_2d: usize,
+    #[serde(skip)]
+    x_column: Option<String>,
+    #[serde(skip)]
+    y_column: Option<String>,
+    #[serde(skip)]
+    pub new_histograms: Vec<Histogram>, // picked up by the app and added to the tree
+    #[serde(skip)]
+    pub new_histograms2d: Vec<Histogram2D>,
+}
+
+impl Default for ColumnBrowser {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            columns: Vec::new(),
+            schema_loaded: false,
+            filter: String::new(),
+            bins_1d: 512,
+            bins_2d: 256,
+            x_column: None,
+            y_column: None,
+            new_histograms: Vec::new(),
+            new_histograms2d: Vec::new(),
+        }
+    }
+}
+
+// Range holding every value, the histograms leave out the upper edge
+fn auto_range(min: f64, max: f64, bins: usize) -> (f64, f64) {
+    if max > min {
+        (min, max + (max - min) / bins.max(1) as f64)
+    } else {
+        (min - 0.5, max + 0.5)
+    }
+}
+
+/// Null count, min, max and mean of each column in one pass. The -1e6 placeholder for
+/// missing values is left out of min, max and mean like when filling histograms.
+pub fn compute_stats(
+    lf: &LazyFrame,
+    columns: &[(String, bool)], // name and whether the column is numeric
+) -> Result<Vec<ColumnStats>, PolarsError> {
+    let mut exprs = Vec::new();
+    for (index, (name, numeric)) in columns.iter().enumerate() {
+        exprs.push(
+            col(name)
+                .null_count()
+                .cast(DataType::UInt64)
+                .alias(&format!("nulls {}", index)),
+        );
+
+        if *numeric {
+            let values = col(name)
+                .cast(DataType::Float64)
+                .filter(col(name).cast(DataType::Float64).neq(lit(-1e6)));
+            exprs.push(values.clone().min().alias(&format!("min {}", index)));
+            exprs.push(values.clone().max().alias(&format!("max {}", index)));
+            exprs.push(values.mean().alias(&format!("mean {}", index)));
+        }
+    }
+
+    let df = lf.clone().select(exprs).collect()?;
+
+    let value = |column: &str| -> Option<f64> {
+        df.column(column)
+            .ok()?
+            .cast(&DataType::Float64)
+            .ok()?
+            .f64()
+            .ok()?
+            .get(0)
+    };
+
+    Ok(columns
+        .iter()
+        .enumerate()
+        .map(|(index, _)| ColumnStats {
+            nulls: value(&format!("nulls {}", index)).unwrap_or(0.0) as u64,
+            min: value(&format!("min {}", index)),
+            max: value(&format!("max {}", index)),
+            mean: value(&format!("mean {}", index)),
+        })
+        .collect())
+}
+
+/// 1D histogram over the full range of a column.
+pub fn quick_histogram(lf: &LazyFrame, column: &str, bins: usize) -> Result<Histogram, String> {
+    let stats = compute_stats(lf, &[(column.to_string(), true)]).map_err(|e| e.to_string())?;
+    let (Some(min), Some(max)) = (stats[0].min, stats[0].max) else {
+        return Err(format!("{} has no values", column));
+    };
+
+    let mut histogrammer = Histogrammer::new();
+    histogrammer.add_fill_hist1d(column, lf, column, bins, auto_range(min, max, bins));
+    histogrammer
+        .histograms1d
+        .pop()
+        .ok_or_else(|| format!("Failed to fill {}", column))
+}
+
+/// 2D histogram over the full ranges of two columns.
+pub fn quick_histogram2d(
+    lf: &LazyFrame,
+    x_column: &str,
+    y_column: &str,
+    bins: usize,
+) -> Result<Histogram2D, String> {
+    let stats = compute_stats(
+        lf,
+        &[(x_column.to_string(), true), (y_column.to_string(), true)],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let (Some(x_min), Some(x_max), Some(y_min), Some(y_max)) =
+        (stats[0].min, stats[0].max, stats[1].min, stats[1].max)
+    else {
+        return Err(format!("{} or {} has no values", x_column, y_column));
+    };
+
+    let name = format!("{} v {}", y_column, x_column);
+    let mut histogrammer = Histogrammer::new();
+    histogrammer.add_fill_hist2d(
+        &name,
+        lf,
+        x_column,
+        y_column,
+        (bins, bins),
+        (
+            auto_range(x_min, x_max, bins),
+            auto_range(y_min, y_max, bins),
+        ),
+    );
+    histogrammer
+        .histograms2d
+        .pop()
+        .ok_or_else(|| format!("Failed to fill {}", name))
+}
+
+impl ColumnBrowser {
+    pub fn set_files(&mut self, files: &[PathBuf]) {
+        if self.files != files {
+            self.files = files.to_vec();
+            self.columns.clear();
+            self.schema_loaded = false;
+        }
+    }
+
+    // The selected files with the calculated columns of the histogram script
+    fn lazyframe(&self) -> Result<LazyFrame, PolarsError> {
+        let files: Arc<[PathBuf]> = Arc::from(self.files.clone());
+        let lf = LazyFrame::scan_parquet_files(files, ScanArgsParquet::default())?;
+        Ok(add_columns(lf))
+    }
+
+    // Names and types come from the schema, no data is read
+    pub fn load_columns(&mut self) {
+        self.schema_loaded = true;
+        match self.lazyframe().and_then(|lf| lf.schema()) {
+            Ok(schema) => {
+                self.columns = schema
+                    .iter()
+                    .map(|(name, dtype)| ColumnInfo {
+                        name: name.to_string(),
+                        dtype: dtype.clone(),
+                        stats: None,
+                    })
+                    .collect();
+            }
+            Err(e) => log::error!("Failed to read the columns: {}", e),
+        }
+    }
+
+    fn compute_stats_for(&mut self, indices: &[usize]) {
+        let columns: Vec<(String, bool)> = indices
+            .iter()
+            .map(|&i| {
+                (
+                    self.columns[i].name.clone(),
+                    self.columns[i].dtype.is_numeric(),
+                )
+            })
+            .collect();
+
+        match self.lazyframe().and_then(|lf| compute_stats(&lf, &columns)) {
+            Ok(stats) => {
+                for (&index, stats) in indices.iter().zip(stats) {
+                    self.columns[index].stats = Some(stats);
+                }
+            }
+            Err(e) => log::error!("Failed to compute the column statistics: {}", e),
+        }
+    }
+
+    fn add_quick_histogram(&mut self, column: &str) {
+        match self
+            .lazyframe()
+            .map_err(|e| e.to_string())
+            .and_then(|lf| quick_histogram(&lf, column, self.bins_1d))
+        {
+            Ok(hist) => self.new_histograms.push(hist),
+            Err(e) => log::error!("Failed to create a histogram of {}: {}", column, e),
+        }
+    }
+
+    fn add_quick_histogram2d(&mut self, x_column: &str, y_column: &str) {
+        match self
+            .lazyframe()
+            .map_err(|e| e.to_string())
+            .and_then(|lf| quick_histogram2d(&lf, x_column, y_column, self.bins_2d))
+        {
+            Ok(hist) => self.new_histograms2d.push(hist),
+            Err(e) => log::error!(
+                "Failed to create a histogram of {} v {}: {}",
+                y_column,
+                x_column,
+                e
+            ),
+        }
+    }
+
+    fn histogram2d_ui(&mut self, ui: &mut egui::Ui) {
+        let numeric: Vec<String> = self
+            .columns
+            .iter()
+            .filter(|c| c.dtype.is_numeric())
+            .map(|c| c.name.clone())
+            .collect();
+
+        ui.horizontal(|ui| {
+            for (label, selected) in [("X", &mut self.x_column), ("Y", &mut self.y_column)] {
+                egui::ComboBox::from_label(label)
+                    .selected_text(selected.clone().unwrap_or_default())
+                    .show_ui(ui, |ui| {
+                        for name in &numeric {
+                            ui.selectable_value(selected, Some(name.clone()), name);
+                        }
+                    });
+            }
+
+            ui.add(
+                egui::DragValue::new(&mut self.bins_2d)
+                    .clamp_range(1..=4096)
+                    .prefix("Bins: "),
+            );
+
+            if let (Some(x), Some(y)) = (self.x_column.clone(), self.y_column.clone()) {
+                if ui.button("2D Histogram").clicked() {
+                    self.add_quick_histogram2d(&x, &y);
+                }
+            }
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.files.is_empty() {
+            ui.label("Select files in the workspace to browse their columns");
+            return;
+        }
+
+        if !self.schema_loaded {
+            self.load_columns();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} columns in {} files",
+                self.columns.len(),
+                self.files.len()
+            ));
+
+            if ui.button("↻").on_hover_text("Reload the schema").clicked() {
+                self.load_columns();
+            }
+
+            ui.separator();
+
+            ui.label("Filter:");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).desired_width(120.0));
+
+            ui.separator();
+
+            ui.add(
+                egui::DragValue::new(&mut self.bins_1d)
+                    .clamp_range(1..=65536)
+                    .prefix("Bins: "),
+            );
+        });
+
+        self.histogram2d_ui(ui);
+
+        ui.separator();
+
+        let filter = self.filter.to_lowercase();
+        let visible: Vec<usize> = (0..self.columns.len())
+            .filter(|&i| self.columns[i].name.to_lowercase().contains(&filter))
+            .collect();
+
+        let mut stats_request = None;
+        let mut histogram_request = None;
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("column_browser")
+                .striped(true)
+                .num_columns(8)
+                .show(ui, |ui| {
+                    ui.label("Column");
+                    ui.label("Type");
+                    ui.label("Nulls");
+                    ui.label("Min");
+                    ui.label("Max");
+                    ui.label("Mean");
+                    if ui
+                        .button("Stats")
+                        .on_hover_text("Compute the statistics of the listed columns")
+                        .clicked()
+                    {
+                        stats_request = Some(visible.clone());
+                    }
+                    ui.label("");
+                    ui.end_row();
+
+                    let format_value =
+                        |value: Option<f64>| value.map(|v| format!("{:.4}", v)).unwrap_or_default();
+
+                    for &index in &visible {
+                        let column = &self.columns[index];
+                        ui.label(&column.name);
+                        ui.label(column.dtype.to_string());
+
+                        match &column.stats {
+                            Some(stats) => {
+                                ui.label(stats.nulls.to_string());
+                                ui.label(format_value(stats.min));
+                                ui.label(format_value(stats.max));
+                                ui.label(format_value(stats.mean));
+                            }
+                            None => {
+                                for _ in 0..4 {
+                                    ui.label("");
+                                }
+                            }
+                        }
+
+                        if ui.button("Stats").clicked() {
+                            stats_request = Some(vec![index]);
+                        }
+
+                        if ui
+                            .add_enabled(column.dtype.is_numeric(), egui::Button::new("Histogram"))
+                            .clicked()
+                        {
+                            histogram_request = Some(column.name.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+        if let Some(indices) = stats_request {
+            self.compute_stats_for(&indices);
+        }
+
+        if let Some(column) = histogram_request {
+            self.add_quick_histogram(&column);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_and_quick_histogram() {
+        let lf = df!(
+            "x" => [1.0, 2.0, 3.0, -1e6],
+            "y" => [Some(4.0), None, Some(8.0), Some(6.0)],
+            "name" => ["a", "b", "c", "d"]
+        )
+        .unwrap()
+        .lazy();
+
+        let stats = compute_stats(
+            &lf,
+            &[
+                ("x".to_string(), true),
+                ("y".to_string(), true),
+                ("name".to_string(), false),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            stats[0],
+            ColumnStats {
+                nulls: 0,
+                min: Some(1.0),
+                max: Some(3.0),
+                mean: Some(2.0),
+            }
+        );
+        assert_eq!(stats[1].nulls, 1);
+        assert_eq!(stats[1].max, Some(8.0));
+        assert_eq!(stats[2].min, None);
+
+        // the maximum lands in the last bin instead of past the range
+        let hist = quick_histogram(&lf, "x", 4).unwrap();
+        assert_eq!(hist.range, (1.0, 3.5));
+        assert_eq!(hist.bins, vec![1, 1, 0, 1]);
+    }
+}
@@ -1,27 +1,42 @@
+use crate::cutter::cuts::{unique_cut_name, RangeCut};
 use crate::egui_plot_stuff::egui_line::EguiLine;
 use crate::fitter::background_fitter::BackgroundFitter;
+use crate::fitter::batch_fit::FitTemplate;
 use crate::fitter::fit_handler::{FitModel, Fits, Fitter};
 use crate::fitter::fit_markers::EguiFitMarkers;
+use crate::fitter::fit_results::FitResultRow;
+use crate::fitter::fit_worker::FitWorker;
+use crate::fitter::peak_finder::{PeakFinder, PeakFinderSettings};
+use crate::fitter::peak_library::PeakLibrary;
+use crate::fitter::spectrum_background::SpectrumBackground;
 
 use super::plot_settings::EguiPlotSettings;
 
+use std::sync::Arc;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlotSettings {
     #[serde(skip)]
     cursor_position: Option<egui_plot::PlotPoint>,
+    #[serde(skip)]
+    view_range: Option<(f64, f64)>,
     egui_settings: EguiPlotSettings,
     stats_info: bool,
     markers: EguiFitMarkers,
     rebin_factor: usize,
+    #[serde(default)]
+    peak_finder: PeakFinderSettings,
 }
 impl Default for PlotSettings {
     fn default() -> Self {
         PlotSettings {
             cursor_position: None,
+            view_range: None,
             egui_settings: EguiPlotSettings::default(),
             stats_info: false,
             markers: EguiFitMarkers::new(),
             rebin_factor: 1,
+            peak_finder: PeakFinderSettings::default(),
         }
     }
 }
@@ -37,6 +52,12 @@ impl PlotSettings {
     }
 }
 
+// Fits run on the worker thread, picked up by poll_fit_worker
+enum WorkerFit {
+    Gaussian(Box<Fitter>),
+    Clusters(Vec<Fitter>),
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Histogram {
     pub name: String,
@@ -47,6 +68,24 @@ pub struct Histogram {
     pub plot_settings: PlotSettings,
     pub fits: Fits,
     pub original_bins: Vec<u32>,
+    #[serde(default)]
+    pub spectrum_background: SpectrumBackground,
+    #[serde(default)]
+    pub peak_library: PeakLibrary,
+    #[serde(default)]
+    pub column: String, // column the histogram was filled from
+    #[serde(default)]
+    pub gate: Option<String>, // only events passing this gate were filled
+    #[serde(skip)]
+    pub used_names: Vec<String>, // names of the gates and other histograms' cuts, set by the app
+    #[serde(skip)]
+    pub renamed_cuts: Vec<(String, String)>, // old and new names, picked up by the app
+    #[serde(default)]
+    pub cuts: Vec<RangeCut>,
+    #[serde(skip)]
+    pub new_histograms: Vec<Histogram>, // created from this histogram, added as panes by the app
+    #[serde(skip)]
+    fit_worker: Option<Arc<FitWorker<WorkerFit>>>,
 }
 impl Histogram {
     // Create a new Histogram with specified min, max, and number of bins
@@ -63,9 +102,25 @@ impl Histogram {
             plot_settings: PlotSettings::default(),
             fits: Fits::new(),
             original_bins: vec![0; number_of_bins],
+            spectrum_background: SpectrumBackground::default(),
+            peak_library: PeakLibrary::default(),
+            column: String::new(),
+            gate: None,
+            used_names: Vec::new(),
+            renamed_cuts: Vec::new(),
+            cuts: Vec::new(),
+            new_histograms: Vec::new(),
+            fit_worker: None,
         }
     }
 
+    // Empty histogram with the same binning and column
+    pub fn empty_copy(&self, name: &str) -> Self {
+        let mut hist = Histogram::new(name, self.bins.len(), self.range);
+        hist.column.clone_from(&self.column);
+        hist
+    }
+
     // Add a value to the histogram
     pub fn fill(&mut self, value: f64) {
         if value >= self.range.0 && value < self.range.1 {
@@ -91,6 +146,11 @@ impl Histogram {
         self.bins = new_bins;
         self.bin_width = (self.range.1 - self.range.0) / new_bin_count as f64;
         self.update_line_points();
+
+        // the background is per bin, so it has to follow the binning
+        if self.spectrum_background.is_calculated() {
+            self.calculate_spectrum_background();
+        }
     }
 
     // Compute the possible rebin factors based on the initial number of bins
@@ -127,13 +187,14 @@ impl Histogram {
 
     // Get the bin number for a given x position.
     fn get_bin(&self, x: f64) -> Option<usize> {
-        if x < self.range.0 || x > self.range.1 {
+        // the upper edge belongs to no bin, like when filling
+        if x < self.range.0 || x >= self.range.1 || self.bins.is_empty() {
             return None;
         }
 
         let bin_index: usize = ((x - self.range.0) / self.bin_width).floor() as usize;
 
-        Some(bin_index)
+        Some(bin_index.min(self.bins.len() - 1))
     }
 
     // Get the bin centers for the histogram
@@ -275,11 +336,146 @@ impl Histogram {
         self.fits.temp_background_fit = Some(background_fitter);
     }
 
+    fn calculate_spectrum_background(&mut self) {
+        let counts: Vec<f64> = self.bins.iter().map(|&count| count as f64).collect();
+        self.spectrum_background
+            .calculate(&counts, self.range.0, self.bin_width);
+    }
+
+    // The spectrum background between start_x and end_x as a fixed background for a fit
+    fn fixed_spectrum_background(&self, start_x: f64, end_x: f64) -> Option<BackgroundFitter> {
+        if !self.spectrum_background.settings.use_in_fits
+            || !self.spectrum_background.is_calculated()
+        {
+            return None;
+        }
+
+        let points: Vec<[f64; 2]> = self
+            .spectrum_background
+            .points(self.range.0, self.bin_width)
+            .into_iter()
+            .filter(|p| p[0] >= start_x - self.bin_width && p[0] <= end_x + self.bin_width)
+            .collect();
+
+        Some(BackgroundFitter::fixed(points))
+    }
+
+    // New histogram with the spectrum background subtracted, negative bins are set to zero
+    fn subtract_spectrum_background(&mut self) {
+        if !self.spectrum_background.is_calculated() {
+            log::error!("Calculate the spectrum background before subtracting it");
+            return;
+        }
+
+        let mut histogram = Histogram::new(
+            &format!("{} - Background", self.name),
+            self.bins.len(),
+            self.range,
+        );
+
+        histogram.bins = self
+            .bins
+            .iter()
+            .zip(self.spectrum_background.values.iter())
+            .map(|(&count, &bg)| (count as f64 - bg).round().max(0.0) as u32)
+            .collect();
+        histogram.original_bins.clone_from(&histogram.bins);
+
+        self.new_histograms.push(histogram);
+    }
+
+    fn spectrum_background_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Spectrum Background", |ui| {
+            self.spectrum_background.settings_ui(ui);
+
+            ui.horizontal(|ui| {
+                if ui.button("Calculate").clicked() {
+                    self.calculate_spectrum_background();
+                }
+
+                if ui.button("Clear").clicked() {
+                    self.spectrum_background.clear();
+                }
+
+                ui.separator();
+
+                if ui
+                    .button("Subtract")
+                    .on_hover_text("Create a new background subtracted histogram")
+                    .clicked()
+                {
+                    self.subtract_spectrum_background();
+                }
+            });
+        });
+    }
+
+    // Gaussian fitter with the data and settings of this histogram, ready to fit
+    fn unfitted_gaussian_fitter(
+        &self,
+        start_x: f64,
+        end_x: f64,
+        peak_positions: Vec<f64>,
+        background: Option<BackgroundFitter>,
+    ) -> Fitter {
+        let mut fitter = Fitter::new(FitModel::Gaussian(peak_positions), background);
+
+        // a reopened fit is refit with the method and weighting chosen for it
+        (fitter.method, fitter.weighting) = match &self.fits.reopened {
+            Some(reopened) => (reopened.method, reopened.weighting),
+            None => (self.fits.settings.fit_method, self.fits.settings.weighting),
+        };
+
+        fitter.region = Some((start_x, end_x));
+        fitter.x_data = self.get_bin_centers_between(start_x, end_x);
+        fitter.y_data = self.get_bin_counts_between(start_x, end_x);
+
+        fitter
+    }
+
     fn fit_gaussians(&mut self) {
+        if let Some(mut fitter) = self.prepare_gaussian_fit() {
+            fitter.fit();
+            self.finish_gaussian_fit(fitter);
+        }
+    }
+
+    // Fit on a worker thread, the result is picked up by poll_fit_worker
+    fn fit_gaussians_in_background(&mut self, ctx: &egui::Context) {
+        if self.fit_worker.is_some() {
+            log::info!("{}: a fit is already running", self.name);
+            return;
+        }
+
+        if let Some(mut fitter) = self.prepare_gaussian_fit() {
+            self.fit_worker = Some(Arc::new(FitWorker::spawn_with(
+                move || {
+                    fitter.fit();
+                    WorkerFit::Gaussian(Box::new(fitter))
+                },
+                ctx.clone(),
+            )));
+        }
+    }
+
+    fn poll_fit_worker(&mut self) {
+        match FitWorker::poll(&mut self.fit_worker, &self.name) {
+            Some(WorkerFit::Gaussian(fitter)) => self.finish_gaussian_fit(*fitter),
+            Some(WorkerFit::Clusters(fitters)) => self.fits.temp_cluster_fits = fitters,
+            None => {}
+        }
+    }
+
+    fn cancel_fit(&mut self) {
+        FitWorker::cancel_slot(&mut self.fit_worker, &self.name);
+    }
+
+    // Check the markers, fit the background and set up the gaussian fitter for the region
+    fn prepare_gaussian_fit(&mut self) -> Option<Fitter> {
         let region_marker_positions = self.plot_settings.markers.get_region_marker_positions();
         if region_marker_positions.len() != 2 {
             log::error!("Need to set two region markers to fit the histogram");
-            return;
+            return None;
         }
 
         self.plot_settings
@@ -287,7 +483,11 @@ impl Histogram {
             .remove_peak_markers_outside_region();
         let peak_positions = self.plot_settings.markers.get_peak_marker_positions();
 
-        if self.fits.temp_background_fit.is_none() {
+        let (start_x, end_x) = (region_marker_positions[0], region_marker_positions[1]);
+
+        if let Some(background) = self.fixed_spectrum_background(start_x, end_x) {
+            self.fits.temp_background_fit = Some(background);
+        } else if self.fits.temp_background_fit.is_none() {
             if self.plot_settings.markers.background_markers.len() <= 1 {
                 for position in region_marker_positions.iter() {
                     self.plot_settings.markers.add_background_marker(*position);
@@ -296,18 +496,15 @@ impl Histogram {
             self.fit_background();
         }
 
-        let mut fitter = Fitter::new(
-            FitModel::Gaussian(peak_positions),
+        Some(self.unfitted_gaussian_fitter(
+            start_x,
+            end_x,
+            peak_positions,
             self.fits.temp_background_fit.clone(),
-        );
-
-        let (start_x, end_x) = (region_marker_positions[0], region_marker_positions[1]);
-
-        fitter.x_data = self.get_bin_centers_between(start_x, end_x);
-        fitter.y_data = self.get_bin_counts_between(start_x, end_x);
-
-        fitter.fit();
+        ))
+    }
 
+    fn finish_gaussian_fit(&mut self, mut fitter: Fitter) {
         fitter.set_name(self.name.clone());
 
         // clear peak markers and add the new peak markers
@@ -318,9 +515,391 @@ impl Histogram {
             self.plot_settings.markers.add_peak_marker(peak);
         }
 
+        if !self.peak_library.is_empty() {
+            fitter.identify_peaks(&self.peak_library);
+        }
+
+        // a failed refit is not stored over the reopened fit
+        if fitter.result_rows(&self.name, 0).is_empty() {
+            self.fits.reopened = None;
+        }
+
         self.fits.temp_fit = Some(fitter);
     }
 
+    // Move a stored fit back to the temp fit and put its markers back so it can be refit
+    fn reopen_stored_fit(&mut self, index: usize) {
+        self.cancel_fit();
+
+        let Some(fit) = self.fits.reopen_stored_fit(index) else {
+            return;
+        };
+
+        let Some(template) = FitTemplate::from_fitter(&fit.name, fit) else {
+            return;
+        };
+
+        self.set_template_markers(&template);
+    }
+
+    fn set_template_markers(&mut self, template: &FitTemplate) {
+        let markers = &mut self.plot_settings.markers;
+        markers.clear_region_markers();
+        markers.clear_peak_markers();
+        markers.clear_background_markers();
+
+        markers.add_region_marker(template.region.0);
+        markers.add_region_marker(template.region.1);
+        for &peak in &template.peak_markers {
+            markers.add_peak_marker(peak);
+        }
+        for &background in &template.background_markers {
+            markers.add_background_marker(background);
+        }
+    }
+
+    // Repeat a fit with the markers and settings of the template and store it, returns the fitted peaks.
+    // The markers and temp fits of the histogram are put back afterwards.
+    pub fn apply_fit_template(&mut self, template: &FitTemplate) -> Vec<FitResultRow> {
+        if self.fit_worker.is_some() {
+            log::error!(
+                "{}: a fit is running, fit template '{}' was not applied",
+                self.name,
+                template.name
+            );
+            return Vec::new();
+        }
+
+        let markers = self.plot_settings.markers.clone();
+        let temp_fit = self.fits.temp_fit.take();
+        let temp_background_fit = self.fits.temp_background_fit.take();
+        let temp_cluster_fits = std::mem::take(&mut self.fits.temp_cluster_fits);
+        let reopened = self.fits.reopened.take();
+
+        self.set_template_markers(template);
+
+        if self.plot_settings.markers.background_markers.len() >= 2 {
+            self.fit_background();
+        }
+
+        // fit with the template settings without changing the settings of this histogram
+        let (fit_method, weighting) = (self.fits.settings.fit_method, self.fits.settings.weighting);
+        self.fits.settings.fit_method = template.method;
+        self.fits.settings.weighting = template.weighting;
+
+        self.fit_gaussians();
+
+        self.fits.settings.fit_method = fit_method;
+        self.fits.settings.weighting = weighting;
+
+        let rows = self.fits.temp_fit.as_ref().map_or(Vec::new(), |fit| {
+            fit.result_rows(&self.name, self.fits.stored_fits.len())
+        });
+
+        if rows.is_empty() {
+            log::error!("{}: fit template '{}' failed", self.name, template.name);
+            self.fits.remove_temp_fits();
+        } else {
+            self.fits.store_temp_fit();
+        }
+
+        self.plot_settings.markers = markers;
+        self.fits.temp_fit = temp_fit;
+        self.fits.temp_background_fit = temp_background_fit;
+        self.fits.temp_cluster_fits = temp_cluster_fits;
+        self.fits.reopened = reopened;
+
+        rows
+    }
+
+    // The region markers if set, otherwise the visible part of the histogram
+    fn peak_search_range(&self) -> (f64, f64) {
+        let region = self.plot_settings.markers.get_region_marker_positions();
+        if region.len() == 2 {
+            return (region[0], region[1]);
+        }
+
+        match self.plot_settings.view_range {
+            Some((min, max)) => (min.max(self.range.0), max.min(self.range.1)),
+            None => self.range,
+        }
+    }
+
+    // Run the peak finder over the search range and return the peak positions in x
+    fn search_peaks(&self) -> Vec<f64> {
+        let (start_x, end_x) = self.peak_search_range();
+        let x_data = self.get_bin_centers_between(start_x, end_x);
+        let y_data = self.get_bin_counts_between(start_x, end_x);
+
+        let peaks = PeakFinder::new(&y_data, &self.plot_settings.peak_finder).find_peaks();
+
+        // convert the fractional bin index back to x
+        let first_center = x_data.first().copied().unwrap_or(start_x);
+        peaks
+            .into_iter()
+            .map(|bin| first_center + bin * self.bin_width)
+            .collect()
+    }
+
+    // Replace the peak markers with the peaks found by the peak finder
+    fn find_peaks(&mut self) {
+        let peaks = self.search_peaks();
+        log::info!("{}: found {} peaks", self.name, peaks.len());
+
+        self.plot_settings.markers.clear_peak_markers();
+        for peak in peaks {
+            self.plot_settings.markers.add_peak_marker(peak);
+        }
+    }
+
+    // Find the peaks and fit each cluster of nearby peaks on the worker thread
+    fn find_and_fit_peaks(&mut self, ctx: &egui::Context) {
+        if self.fit_worker.is_some() {
+            log::info!("{}: a fit is already running", self.name);
+            return;
+        }
+
+        self.fits.discard_temp_fits();
+
+        let name = self.name.clone();
+        let mut fitters = self.cluster_fitters();
+        if fitters.is_empty() {
+            return;
+        }
+
+        self.fit_worker = Some(Arc::new(FitWorker::spawn_with(
+            move || {
+                for fitter in fitters.iter_mut() {
+                    fitter.fit();
+                    fitter.set_name(name.clone());
+                }
+                WorkerFit::Clusters(fitters)
+            },
+            ctx.clone(),
+        )));
+    }
+
+    // Place the found peaks as markers and set up a fitter for each cluster of nearby peaks,
+    // with its own region and linear background
+    fn cluster_fitters(&mut self) -> Vec<Fitter> {
+        let peaks = self.search_peaks();
+        if peaks.is_empty() {
+            log::info!("{}: no peaks found", self.name);
+            return Vec::new();
+        }
+
+        let settings = &self.plot_settings.peak_finder;
+        let fwhm = settings.min_fwhm * self.bin_width;
+        let window = settings.fit_window * fwhm;
+
+        let bins: Vec<f64> = peaks.iter().map(|x| x / self.bin_width).collect();
+        let clusters: Vec<Vec<f64>> = PeakFinder::new(&[], settings)
+            .clusters(&bins)
+            .into_iter()
+            .map(|cluster| cluster.iter().map(|bin| bin * self.bin_width).collect())
+            .collect();
+
+        self.plot_settings.markers.clear_peak_markers();
+        for peak in &peaks {
+            self.plot_settings.markers.add_peak_marker(*peak);
+        }
+
+        let mut fitters = Vec::new();
+        for cluster in clusters {
+            let start_x = (cluster[0] - window).max(self.range.0);
+            let end_x = (cluster[cluster.len() - 1] + window).min(self.range.1);
+
+            // spectrum background if enabled, otherwise a line through the edges of the cluster region
+            let background = match self.fixed_spectrum_background(start_x, end_x) {
+                Some(background) => background,
+                None => match self.edge_background(start_x, end_x) {
+                    Some(background) => background,
+                    None => {
+                        log::error!(
+                            "{}: no bins at the edges of the cluster at {:.2}, skipping it",
+                            self.name,
+                            cluster[0]
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            fitters.push(self.unfitted_gaussian_fitter(start_x, end_x, cluster, Some(background)));
+        }
+
+        fitters
+    }
+
+    // Line through the bins at the edges of a region. The line needs two edge bins with
+    // different counts, otherwise the background is flat at their mean.
+    fn edge_background(&self, start_x: f64, end_x: f64) -> Option<BackgroundFitter> {
+        let mut edges: Vec<(f64, f64)> = [start_x, end_x]
+            .iter()
+            .filter_map(|&x| self.get_bin_count_and_center(x))
+            .collect();
+        edges.dedup_by(|a, b| a.0 == b.0);
+
+        match edges.as_slice() {
+            [] => None,
+            [(x0, y0), (x1, y1)] if y0 != y1 => {
+                let mut background =
+                    BackgroundFitter::new(vec![*x0, *x1], vec![*y0, *y1], FitModel::Linear);
+                background.fit();
+                Some(background)
+            }
+            _ => {
+                let mean = edges.iter().map(|(_, y)| y).sum::<f64>() / edges.len() as f64;
+                let mut background = BackgroundFitter::fixed(vec![[start_x, mean], [end_x, mean]]);
+                background.fit();
+                Some(background)
+            }
+        }
+    }
+
+    fn peak_finder_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Peak Finder", |ui| {
+            self.plot_settings.peak_finder.ui(ui);
+
+            ui.label(if self.plot_settings.markers.region_markers.len() == 2 {
+                "Searching between the region markers"
+            } else {
+                "Searching the visible range"
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Find Peaks").clicked() {
+                    self.find_peaks();
+                }
+
+                ui.separator();
+
+                if ui
+                    .button("Find and Fit All")
+                    .on_hover_text("Fit each cluster of peaks as a separate temp fit")
+                    .clicked()
+                {
+                    self.find_and_fit_peaks(ui.ctx());
+                }
+            });
+        });
+    }
+
+    fn peak_library_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Peak Library", |ui| {
+            self.peak_library.ui(ui);
+
+            if self.peak_library.is_empty() {
+                return;
+            }
+
+            if ui
+                .button("Identify Fit Peaks")
+                .on_hover_text(
+                    "Tag the peaks of the current and stored fits with the closest library line",
+                )
+                .clicked()
+            {
+                self.fits.identify_peaks(&self.peak_library);
+            }
+        });
+    }
+
+    // A cut is used as a gate by name, so a rename to an empty or taken name is undone
+    fn finish_cut_rename(&mut self, index: usize, old: String, new: String) {
+        let taken = self.used_names.contains(&new)
+            || self
+                .cuts
+                .iter()
+                .enumerate()
+                .any(|(i, cut)| i != index && cut.name == new);
+        if new.is_empty() || taken {
+            log::error!(
+                "Cut name '{}' is empty or already used, keeping '{}'",
+                new,
+                old
+            );
+            self.cuts[index].set_name(&old);
+        } else {
+            self.renamed_cuts.push((old, new));
+        }
+    }
+
+    // Names in use, including the cuts added since the app last collected them
+    fn taken_cut_names(&self) -> Vec<String> {
+        let mut taken = self.used_names.clone();
+        taken.extend(self.cuts.iter().map(|cut| cut.name.clone()));
+        taken
+    }
+
+    // Range cut between the region markers, bound to the column of the histogram
+    fn add_cut_from_region_markers(&mut self) {
+        let positions = self.plot_settings.markers.get_region_marker_positions();
+        if positions.len() != 2 {
+            log::error!("Need to set two region markers to add a cut");
+            return;
+        }
+
+        if self.column.is_empty() {
+            log::error!("Recalculate the histograms to bind cuts to the column");
+            return;
+        }
+
+        let name = unique_cut_name(&self.name, &self.taken_cut_names());
+        let mut cut = RangeCut::new(&name, &self.column, positions[0], positions[1]);
+        cut.histogram.clone_from(&self.name);
+        self.cuts.push(cut);
+    }
+
+    // Takes the cuts of this histogram from a saved or imported set. Cuts without a histogram
+    // only go to ungated histograms, the gated copies share their columns.
+    pub fn place_cuts(&mut self, cuts: &[RangeCut]) {
+        self.cuts = cuts
+            .iter()
+            .filter(|cut| {
+                cut.belongs_to(&self.name, &self.column)
+                    && (self.gate.is_none() || !cut.histogram.is_empty())
+            })
+            .cloned()
+            .collect();
+    }
+
+    fn cuts_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Cuts", |ui| {
+            if ui
+                .button("Add Cut from Region Markers")
+                .on_hover_text("The lines can be moved with the middle mouse button")
+                .clicked()
+            {
+                self.add_cut_from_region_markers();
+            }
+
+            let mut index_to_remove = None;
+            let mut renamed = None;
+            for (index, cut) in self.cuts.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.button("🗙").clicked() {
+                        index_to_remove = Some(index);
+                    }
+
+                    ui.separator();
+
+                    if let Some(names) = cut.menu_button(ui) {
+                        renamed = Some((index, names));
+                    }
+                });
+            }
+
+            if let Some((index, (old, new))) = renamed {
+                self.finish_cut_rename(index, old, new);
+            }
+
+            if let Some(index) = index_to_remove {
+                self.cuts.remove(index);
+            }
+        });
+    }
+
     // Handles the interactive elements of the histogram
     fn interactive(&mut self, ui: &mut egui::Ui) {
         self.plot_settings.markers.cursor_position = self.plot_settings.cursor_position;
@@ -329,7 +908,8 @@ impl Histogram {
             self.plot_settings.markers.interactive_markers(ui);
 
             if ui.input(|i| i.key_pressed(egui::Key::Minus) || i.key_pressed(egui::Key::Delete)) {
-                self.fits.remove_temp_fits();
+                self.cancel_fit();
+                self.fits.discard_temp_fits();
             }
 
             if ui.input(|i| i.key_pressed(egui::Key::G)) {
@@ -337,11 +917,24 @@ impl Histogram {
             }
 
             if ui.input(|i| i.key_pressed(egui::Key::F)) {
-                self.fit_gaussians();
+                self.fit_gaussians_in_background(ui.ctx());
+            }
+
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.cancel_fit();
+            }
+
+            if ui.input(|i| i.key_pressed(egui::Key::K)) {
+                self.find_peaks();
             }
 
             if ui.input(|i| i.key_pressed(egui::Key::S)) {
-                self.fits.store_temp_fit();
+                // the running fit replaces the temp fit when it is done
+                if self.fit_worker.is_some() {
+                    log::info!("{}: wait for the running fit or cancel it", self.name);
+                } else {
+                    self.fits.store_temp_fit();
+                }
             }
 
             if ui.input(|i| i.key_pressed(egui::Key::I)) {
@@ -356,6 +949,10 @@ impl Histogram {
             if ui.input(|i| i.key_pressed(egui::Key::L)) {
                 self.plot_settings.egui_settings.log_y = !self.plot_settings.egui_settings.log_y;
             }
+
+            if ui.input(|i| i.key_pressed(egui::Key::C)) {
+                self.add_cut_from_region_markers();
+            }
         }
     }
 
@@ -378,12 +975,17 @@ impl Histogram {
                 ui.separator();
                 ui.label("Fitting");
                 ui.label("G: Fit Background").on_hover_text("Fit a linear background using the background markers");
-                ui.label("F: Fit Gaussians").on_hover_text("Fit gaussians at the peak markers give some region with a linear background");
+                ui.label("F: Fit Gaussians").on_hover_text("Fit gaussians at the peak markers give some region with a linear background. The fit runs in the background.");
+                ui.label("Escape: Cancel Running Fit");
+                ui.label("K: Find Peaks").on_hover_text("Place peak markers with the automatic peak finder in the region or visible range");
                 ui.label("S: Store Fit").on_hover_text("Store the current fit as a permanent fit which can be saved and loaded later");
                 ui.separator();
                 ui.label("Plot");
                 ui.label("I: Toggle Stats");
                 ui.label("L: Toggle Log Y");
+                ui.separator();
+                ui.label("Cuts");
+                ui.label("C: Add Cut").on_hover_text("Add a range cut between the region markers");
             });
         });
     }
@@ -398,13 +1000,28 @@ impl Histogram {
         self.line.log_x = log_x;
         self.line.draw(plot_ui);
 
+        self.spectrum_background.line.log_y = log_y;
+        self.spectrum_background.line.log_x = log_x;
+        self.spectrum_background.draw(plot_ui);
+
         self.fits.set_log(log_y, log_x);
         self.fits.draw(plot_ui);
 
+        if !self.peak_library.is_empty() {
+            self.peak_library.draw(plot_ui, log_x);
+        }
+
         self.show_stats(plot_ui);
 
         self.plot_settings.markers.draw_all_markers(plot_ui);
 
+        for cut in &self.cuts {
+            cut.draw(plot_ui);
+        }
+
+        let bounds = plot_ui.plot_bounds();
+        self.plot_settings.view_range = Some((bounds.min()[0], bounds.max()[0]));
+
         if plot_ui.response().hovered() {
             self.plot_settings.cursor_position = plot_ui.pointer_coordinate();
         } else {
@@ -417,6 +1034,10 @@ impl Histogram {
         self.line.menu_button(ui);
         self.plot_settings.settings_ui(ui);
         self.fits.fit_context_menu_ui(ui);
+        self.peak_finder_ui(ui);
+        self.peak_library_ui(ui);
+        self.spectrum_background_ui(ui);
+        self.cuts_ui(ui);
         self.keybinds_ui(ui);
 
         ui.separator();
@@ -445,14 +1066,29 @@ impl Histogram {
     // Renders the histogram using egui_plot
     pub fn render(&mut self, ui: &mut egui::Ui) {
         self.update_line_points(); // Ensure line points are updated
+        self.poll_fit_worker(); // Swap in a finished background fit
+        if let Some(index) = self.fits.reopen_request.take() {
+            self.reopen_stored_fit(index);
+        }
         self.interactive(ui); // Handle interactive elements
 
         let mut plot = egui_plot::Plot::new(self.name.clone());
         plot = self.plot_settings.egui_settings.apply_to_plot(plot);
 
         ui.vertical(|ui| {
+            FitWorker::ui(&mut self.fit_worker, ui, "Fitting...", &self.name);
             self.fits.fit_stats_ui(ui);
 
+            // shrink the histogram to make room for the residual plot
+            let show_residuals = self.fits.settings.show_residuals && self.fits.has_residuals();
+            let link_id = egui::Id::new(format!("{} Residual Link", self.name));
+            if show_residuals {
+                plot = plot
+                    .height(ui.available_height() * 0.75)
+                    .link_axis(link_id, true, false)
+                    .link_cursor(link_id, true, false);
+            }
+
             let plot_response = plot.show(ui, |plot_ui| {
                 self.draw(plot_ui);
             });
@@ -462,6 +1098,82 @@ impl Histogram {
             });
 
             self.plot_settings.interactive_response(&plot_response);
+            for cut in &mut self.cuts {
+                cut.interactive_dragging(&plot_response);
+            }
+
+            if show_residuals {
+                self.fits.residual_plot_ui(ui, &self.name, link_id);
+            }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Gaussian peak on a flat background of 5 counts per bin
+    fn peak_histogram(center: f64) -> Histogram {
+        let mut hist = Histogram::new("Peak", 100, (0.0, 100.0));
+        for (index, bin) in hist.bins.iter_mut().enumerate() {
+            let x = index as f64 + 0.5;
+            *bin = 5 + (500.0 * (-0.5 * ((x - center) / 2.0).powi(2)).exp()) as u32;
+        }
+        hist.original_bins = hist.bins.clone();
+        hist
+    }
+
+    #[test]
+    fn test_find_and_fit_peak_at_upper_edge() {
+        // the fit window of the peak reaches past the end of the histogram
+        let mut hist = peak_histogram(94.0);
+        assert_eq!(hist.cluster_fitters().len(), 1);
+
+        // the upper edge is outside the last bin
+        assert_eq!(hist.get_bin(100.0), None);
+        assert_eq!(hist.get_bin(99.9), Some(99));
+    }
+
+    #[test]
+    fn test_edge_background_is_flat_for_equal_edges() {
+        let hist = peak_histogram(50.0);
+        let background = hist.edge_background(30.0, 70.0).unwrap();
+        assert_eq!(
+            background.get_background(&[30.0, 50.0, 70.0]).unwrap(),
+            vec![5.0, 5.0, 5.0]
+        );
+
+        // both edges in one bin
+        assert!(hist.edge_background(40.2, 40.7).is_some());
+    }
+
+    #[test]
+    fn test_apply_fit_template_keeps_region_and_markers() {
+        let mut source = peak_histogram(50.0);
+        source.plot_settings.markers.add_region_marker(40.0);
+        source.plot_settings.markers.add_region_marker(60.0);
+        source.plot_settings.markers.add_peak_marker(50.0);
+        source.fit_gaussians();
+        let fit = source.fits.temp_fit.as_ref().unwrap();
+        let template = FitTemplate::from_fitter("Peak", fit).unwrap();
+        assert_eq!(template.region, (40.0, 60.0));
+
+        let mut target = peak_histogram(50.0);
+        target.plot_settings.markers.add_region_marker(10.0);
+        target.plot_settings.markers.add_region_marker(20.0);
+        for _ in 0..3 {
+            assert!(!target.apply_fit_template(&template).is_empty());
+        }
+
+        // repeated applications fit the same region
+        let refit = target.fits.stored_fits.last().unwrap();
+        let again = FitTemplate::from_fitter("Peak", refit).unwrap();
+        assert_eq!(again.region, template.region);
+
+        assert_eq!(
+            target.plot_settings.markers.get_region_marker_positions(),
+            vec![10.0, 20.0]
+        );
+    }
+}
@@ -1,18 +1,22 @@
 use super::colormaps::ColorMap;
 use super::histogram1d::Histogram;
 use super::plot_settings::EguiPlotSettings;
+use crate::cutter::cuts::{unique_cut_name, Cut};
 use crate::egui_plot_stuff::egui_horizontal_line::EguiHorizontalLine;
 use crate::egui_plot_stuff::egui_image::EguiImage;
-use crate::egui_plot_stuff::egui_polygon::EguiPolygon;
 use crate::egui_plot_stuff::egui_vertical_line::EguiVerticalLine;
+use crate::fitter::fit_worker::FitWorker;
+use crate::fitter::gaussian2d::Gaussian2DFitter;
 use fnv::FnvHashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlotSettings {
     #[serde(skip)]
     cursor_position: Option<egui_plot::PlotPoint>,
+    #[serde(skip)]
+    view_bounds: Option<[f64; 4]>, // x min, x max, y min, y max of the last drawn plot
     egui_settings: EguiPlotSettings,
-    cut_polygons: Vec<EguiPolygon>,
     stats_info: bool,
     colormap: ColorMap,
     log_norm_colormap: bool,
@@ -22,8 +26,8 @@ impl Default for PlotSettings {
     fn default() -> Self {
         PlotSettings {
             cursor_position: None,
+            view_bounds: None,
             egui_settings: EguiPlotSettings::default(),
-            cut_polygons: Vec::new(),
             stats_info: false,
             colormap: ColorMap::default(),
             log_norm_colormap: true,
@@ -48,49 +52,14 @@ impl PlotSettings {
         ui.separator();
 
         self.projections.menu_button(ui);
-
-        ui.separator();
-
-        ui.horizontal(|ui| {
-            ui.heading("Cuts");
-            if ui.button("Add Cut").clicked() {
-                let name = format!("Cut {}", self.cut_polygons.len() + 1);
-                self.cut_polygons.push(EguiPolygon::new(&name));
-            }
-        });
-
-        let mut index_to_remove = None;
-        for (index, polygon) in self.cut_polygons.iter_mut().enumerate() {
-            ui.horizontal(|ui| {
-                if ui.button("🗙").clicked() {
-                    index_to_remove = Some(index);
-                }
-
-                ui.separator();
-
-                polygon.menu_button(ui);
-            });
-        }
-
-        if let Some(index) = index_to_remove {
-            self.cut_polygons.remove(index);
-        }
     }
 
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
-        for polygon in self.cut_polygons.iter_mut() {
-            // polygon.mouse_interactions(plot_ui);
-            polygon.draw(plot_ui);
-        }
-
         self.projections.draw(plot_ui);
     }
 
     pub fn interactive_response(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
         self.projections.interactive_dragging(plot_response);
-        for polygon in self.cut_polygons.iter_mut() {
-            polygon.handle_interactions(plot_response);
-        }
     }
 
     pub fn keybinds(&mut self, ui: &mut egui::Ui) {
@@ -309,6 +278,22 @@ pub struct Histogram2D {
     pub range: Range,
     pub plot_settings: PlotSettings,
     pub image: EguiImage,
+    #[serde(default)]
+    pub gaussian_fit: Gaussian2DFitter,
+    #[serde(skip)]
+    gaussian_fit_worker: Option<Arc<FitWorker<Gaussian2DFitter>>>,
+    #[serde(default)]
+    pub x_column: String,
+    #[serde(default)]
+    pub y_column: String,
+    #[serde(default)]
+    pub cuts: Vec<Cut>,
+    #[serde(default)]
+    pub gate: Option<String>, // only events passing this gate were filled
+    #[serde(skip)]
+    pub used_names: Vec<String>, // names of the gates and other histograms' cuts, set by the app
+    #[serde(skip)]
+    pub renamed_cuts: Vec<(String, String)>, // old and new names, picked up by the app
 }
 impl Histogram2D {
     // Create a new 2D Histogram with specified ranges and number of bins for each axis
@@ -340,9 +325,32 @@ impl Histogram2D {
                 [range.0 .0, range.0 .1],
                 [range.1 .0, range.1 .1],
             ),
+            gaussian_fit: Gaussian2DFitter::default(),
+            gaussian_fit_worker: None,
+            x_column: String::new(),
+            y_column: String::new(),
+            cuts: Vec::new(),
+            gate: None,
+            used_names: Vec::new(),
+            renamed_cuts: Vec::new(),
         }
     }
 
+    // Empty histogram with the same binning and columns
+    pub fn empty_copy(&self, name: &str) -> Self {
+        let mut hist = Histogram2D::new(
+            name,
+            (self.bins.x, self.bins.y),
+            (
+                (self.range.x.min, self.range.x.max),
+                (self.range.y.min, self.range.y.max),
+            ),
+        );
+        hist.x_column.clone_from(&self.x_column);
+        hist.y_column.clone_from(&self.y_column);
+        hist
+    }
+
     // Add a value to the histogram
     pub fn fill(&mut self, x_value: f64, y_value: f64) {
         if x_value >= self.range.x.min
@@ -672,14 +680,208 @@ impl Histogram2D {
         }
     }
 
+    // (x, y, counts) of every bin with its center in the fit region, empty bins included
+    fn gaussian_fit_data(&self) -> Vec<[f64; 3]> {
+        let [x_min, x_max, y_min, y_max] = self.gaussian_fit.region;
+        let mut data = Vec::new();
+
+        for x_index in 0..self.bins.x {
+            let x = self.range.x.min + (x_index as f64 + 0.5) * self.bins.x_width;
+            if x < x_min || x > x_max {
+                continue;
+            }
+
+            for y_index in 0..self.bins.y {
+                let y = self.range.y.min + (y_index as f64 + 0.5) * self.bins.y_width;
+                if y < y_min || y > y_max {
+                    continue;
+                }
+
+                let count = self
+                    .bins
+                    .counts
+                    .get(&(x_index, y_index))
+                    .copied()
+                    .unwrap_or(0);
+                data.push([x, y, count as f64]);
+            }
+        }
+
+        data
+    }
+
+    fn use_view_as_gaussian_fit_region(&mut self) {
+        if let Some(bounds) = self.plot_settings.view_bounds {
+            self.gaussian_fit.region = bounds;
+        }
+    }
+
+    // Fit on a worker thread, the result is picked up by poll_gaussian_fit_worker
+    fn fit_gaussian_2d(&mut self, ctx: &egui::Context) {
+        if self.gaussian_fit_worker.is_some() {
+            log::info!("{}: a 2D fit is already running", self.name);
+            return;
+        }
+
+        let [x_min, x_max, y_min, y_max] = self.gaussian_fit.region;
+        if x_min >= x_max || y_min >= y_max {
+            self.use_view_as_gaussian_fit_region();
+        }
+
+        let data = self.gaussian_fit_data();
+        let bin_area = self.bins.x_width * self.bins.y_width;
+        let mut fitter = self.gaussian_fit.clone();
+        self.gaussian_fit_worker = Some(Arc::new(FitWorker::spawn_with(
+            move || {
+                fitter.fit(&data, bin_area);
+                fitter
+            },
+            ctx.clone(),
+        )));
+    }
+
+    fn poll_gaussian_fit_worker(&mut self) {
+        // the settings may have changed during the fit, only the result is taken
+        if let Some(fitter) = FitWorker::poll(&mut self.gaussian_fit_worker, &self.name) {
+            self.gaussian_fit.params = fitter.params;
+        }
+    }
+
+    fn gaussian_fit_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("2D Gaussian Fit", |ui| {
+            self.gaussian_fit.settings_ui(ui);
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Use View")
+                    .on_hover_text("Set the fit region to the visible part of the plot")
+                    .clicked()
+                {
+                    self.use_view_as_gaussian_fit_region();
+                }
+
+                if ui
+                    .add_enabled(self.gaussian_fit_worker.is_none(), egui::Button::new("Fit"))
+                    .clicked()
+                {
+                    self.fit_gaussian_2d(ui.ctx());
+                }
+
+                if ui.button("Clear").clicked() {
+                    self.gaussian_fit.params = None;
+                }
+            });
+
+            if let Some(params) = &self.gaussian_fit.params {
+                ui.separator();
+                params.params_ui(ui);
+            }
+        });
+    }
+
+    // Takes the cuts of this histogram from a saved or imported set. Cuts without a histogram
+    // only go to ungated histograms, the gated copies share their columns.
+    pub fn place_cuts(&mut self, cuts: &[Cut]) {
+        self.cuts = cuts
+            .iter()
+            .filter(|cut| {
+                cut.belongs_to(&self.name, &self.x_column, &self.y_column)
+                    && (self.gate.is_none() || !cut.histogram.is_empty())
+            })
+            .cloned()
+            .collect();
+    }
+
+    // A cut is used as a gate by name, so a rename to an empty or taken name is undone
+    fn finish_cut_rename(&mut self, index: usize, old: String, new: String) {
+        let taken = self.used_names.contains(&new)
+            || self
+                .cuts
+                .iter()
+                .enumerate()
+                .any(|(i, cut)| i != index && cut.name() == new);
+        if new.is_empty() || taken {
+            log::error!(
+                "Cut name '{}' is empty or already used, keeping '{}'",
+                new,
+                old
+            );
+            self.cuts[index].set_name(&old);
+        } else {
+            self.renamed_cuts.push((old, new));
+        }
+    }
+
+    // Names in use, including the cuts added since the app last collected them
+    fn taken_cut_names(&self) -> Vec<String> {
+        let mut taken = self.used_names.clone();
+        taken.extend(self.cuts.iter().map(|cut| cut.name().to_string()));
+        taken
+    }
+
+    fn cuts_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Cuts");
+
+            let has_columns = !self.x_column.is_empty() && !self.y_column.is_empty();
+            if ui
+                .add_enabled(has_columns, egui::Button::new("Add Cut"))
+                .on_disabled_hover_text("Recalculate the histograms to bind cuts to the columns")
+                .clicked()
+            {
+                let name = unique_cut_name(&self.name, &self.taken_cut_names());
+                let mut cut = Cut::new(&name, &self.x_column, &self.y_column);
+                cut.histogram.clone_from(&self.name);
+                self.cuts.push(cut);
+            }
+        });
+
+        let mut index_to_remove = None;
+        let mut renamed = None;
+        for (index, cut) in self.cuts.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("🗙").clicked() {
+                    index_to_remove = Some(index);
+                }
+
+                ui.separator();
+
+                if let Some(names) = cut.menu_button(ui) {
+                    renamed = Some((index, names));
+                }
+            });
+        }
+
+        if let Some((index, (old, new))) = renamed {
+            self.finish_cut_rename(index, old, new);
+        }
+
+        if let Some(index) = index_to_remove {
+            self.cuts.remove(index);
+        }
+    }
+
     fn keybinds(&mut self, ui: &mut egui::Ui) {
         self.plot_settings.keybinds(ui);
+
+        if self.plot_settings.cursor_position.is_some() && ui.input(|i| i.key_pressed(egui::Key::F))
+        {
+            self.fit_gaussian_2d(ui.ctx());
+        }
     }
 
     // Context menu for the plot (when you right-click on the plot)
     fn context_menu(&mut self, ui: &mut egui::Ui) {
         self.image.menu_button(ui);
         self.plot_settings.settings_ui(ui);
+
+        ui.separator();
+
+        self.cuts_ui(ui);
+
+        ui.separator();
+
+        self.gaussian_fit_ui(ui);
     }
 
     // Draw the histogram on the plot
@@ -694,6 +896,18 @@ impl Histogram2D {
         // self.image.draw(plot_ui, plot_image);
         self.plot_settings.cursor_position = plot_ui.pointer_coordinate();
         self.plot_settings.draw(plot_ui);
+        for cut in self.cuts.iter_mut() {
+            cut.draw(plot_ui);
+        }
+        self.gaussian_fit.draw(plot_ui);
+
+        let bounds = plot_ui.plot_bounds();
+        self.plot_settings.view_bounds = Some([
+            bounds.min()[0],
+            bounds.max()[0],
+            bounds.min()[1],
+            bounds.max()[1],
+        ]);
     }
 
     // Render the histogram using egui_plot
@@ -710,6 +924,31 @@ impl Histogram2D {
         self.check_projections();
         self.plot_settings.projections.show(ui);
 
+        self.poll_gaussian_fit_worker();
+        FitWorker::ui(
+            &mut self.gaussian_fit_worker,
+            ui,
+            "Fitting 2D Gaussian...",
+            &self.name,
+        );
+
+        if let Some(params) = &self.gaussian_fit.params {
+            ui.label(format!(
+                "2D Gaussian: volume {:.1} ± {:.1}, mean ({:.2} ± {:.2}, {:.2} ± {:.2}), σ ({:.2} ± {:.2}, {:.2} ± {:.2}), ρ {:.2}",
+                params.volume.value,
+                params.volume.uncertainty,
+                params.mean_x.value,
+                params.mean_x.uncertainty,
+                params.mean_y.value,
+                params.mean_y.uncertainty,
+                params.sigma_x.value,
+                params.sigma_x.uncertainty,
+                params.sigma_y.value,
+                params.sigma_y.uncertainty,
+                params.rho.value,
+            ));
+        }
+
         let plot_response = plot.show(ui, |plot_ui| {
             self.draw(plot_ui);
         });
@@ -722,5 +961,15 @@ impl Histogram2D {
         });
 
         self.plot_settings.interactive_response(&plot_response);
+        let bin_grid = [
+            self.range.x.min,
+            self.bins.x_width,
+            self.range.y.min,
+            self.bins.y_width,
+        ];
+        for cut in self.cuts.iter_mut() {
+            cut.polygon.bin_grid = Some(bin_grid);
+            cut.handle_interactions(&plot_response);
+        }
     }
 }
@@ -1,17 +1,13 @@
 use super::histogrammer::Histogrammer;
+use crate::cutter::cut_handler::CutHandler;
+use crate::cutter::gate_statistics::GateStatistics;
 use polars::prelude::*;
 use std::f64::consts::PI;
+use std::path::PathBuf;
 
-pub fn add_histograms(lf: LazyFrame) -> Result<Histogrammer, PolarsError> {
-    let mut h = Histogrammer::new();
-
-    let fp_bins = 600;
-    let fp_range = (-300.0, 300.0);
-
-    let caen_bins = 512;
-    let caen_range = (0.0, 4096.0);
-
-    let lf = lf.with_columns(vec![
+// Columns calculated from the raw data, also used when exporting events
+pub fn add_columns(lf: LazyFrame) -> LazyFrame {
+    lf.with_columns(vec![
         (col("DelayFrontRightEnergy") + col("DelayFrontLeftEnergy") / lit(2.0))
             .alias("DelayFrontAverageEnergy"),
         (col("DelayBackRightEnergy") + col("DelayBackLeftEnergy") / lit(2.0))
@@ -43,7 +39,35 @@ pub fn add_histograms(lf: LazyFrame) -> Result<Histogrammer, PolarsError> {
         (col("DelayBackRightTime") - col("ScintLeftTime"))
             .alias("DelayBackRightTime_ScintLeftTime"),
         (col("ScintRightTime") - col("ScintLeftTime")).alias("ScintRightTime_ScintLeftTime"),
-    ]);
+    ])
+}
+
+pub fn add_histograms(
+    lf: LazyFrame,
+    cut_handler: &CutHandler,
+    files: &[PathBuf],
+) -> Result<Histogrammer, PolarsError> {
+    let mut h = Histogrammer::new();
+
+    let fp_bins = 600;
+    let fp_range = (-300.0, 300.0);
+
+    let caen_bins = 512;
+    let caen_range = (0.0, 4096.0);
+
+    // gates can use the columns added here
+    let lf = add_columns(lf);
+    let lf_ungated = lf.clone();
+
+    let lf = cut_handler.filter_lf_with_active_gate(&lf);
+    h.set_gates(cut_handler);
+
+    // gates from the gate manager can also be used by name for single histograms, e.g.
+    // if let Ok(lf_pid) = cut_handler.filter_lf_with_gate(&lf, "PID") {
+    //     h.add_fill_hist1d("Xavg PID", &lf_pid, "Xavg", fp_bins, fp_range);
+    // }
+    // or as a gated copy filled in the same pass as the definition:
+    // h.add_fill_gated_hist1d("Xavg", &lf, "Xavg", fp_bins, fp_range, &["PID"]);
 
     h.add_fill_hist1d("Cebra0Energy", &lf, "Cebra0Energy", caen_bins, caen_range);
     h.add_fill_hist1d("Cebra1Energy", &lf, "Cebra1Energy", caen_bins, caen_range);
@@ -635,5 +659,20 @@ pub fn add_histograms(lf: LazyFrame) -> Result<Histogrammer, PolarsError> {
         (fp_range, (-3200.0, 3200.0)),
     );
 
+    // the active gate and the gates of the gated copies are counted on all events
+    let mut used_gates = h.used_gates.clone();
+    if let Some(active_gate) = &cut_handler.active_gate {
+        if !used_gates.contains(active_gate) {
+            used_gates.insert(0, active_gate.clone());
+        }
+    }
+    if !used_gates.is_empty() {
+        let file_rows = GateStatistics::file_rows(files);
+        match GateStatistics::compute(&lf_ungated, cut_handler, &used_gates, &file_rows) {
+            Ok(statistics) => h.gate_statistics = Some(statistics),
+            Err(e) => log::error!("Failed to count the gates: {}", e),
+        }
+    }
+
     Ok(h)
 }
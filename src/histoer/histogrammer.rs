@@ -7,6 +7,12 @@ use polars::prelude::*;
 use super::histogram1d::Histogram;
 use super::histogram2d::Histogram2D;
 
+use crate::cutter::cut_handler::CutHandler;
+use crate::cutter::gate_statistics::GateStatistics;
+use crate::cutter::gates::GatedHistogram;
+use crate::fitter::batch_fit::BatchFitter;
+use crate::fitter::fit_results::FitResultsTable;
+use crate::fitter::trend_plot::TrendPlot;
 use crate::pane::Pane;
 
 use std::collections::HashMap;
@@ -18,6 +24,18 @@ pub struct Histogrammer {
 
     #[serde(skip)]
     pub tabs: HashMap<String, Vec<Pane>>,
+
+    #[serde(skip)]
+    pub gate_statistics: Option<GateStatistics>, // counted by the script, picked up by the processer
+
+    #[serde(skip)]
+    pub used_gates: Vec<String>, // gates the gated copies were filled with
+
+    #[serde(skip)]
+    gate_exprs: HashMap<String, Result<Expr, String>>, // gates by name, set before filling
+
+    #[serde(skip)]
+    assigned_gates: Vec<GatedHistogram>, // gated copies assigned in the gate manager
 }
 
 impl Histogrammer {
@@ -27,6 +45,10 @@ impl Histogrammer {
             histograms1d: Vec::new(),
             histograms2d: Vec::new(),
             tabs: HashMap::new(),
+            gate_statistics: None,
+            used_gates: Vec::new(),
+            gate_exprs: HashMap::new(),
+            assigned_gates: Vec::new(),
         }
     }
 
@@ -36,51 +58,109 @@ impl Histogrammer {
         self.histograms1d.push(hist); // Store it in the vector.
     }
 
-    // Fills a 1D histogram with data from a polars dataframe/column.
-    pub fn fill_hist1d(&mut self, name: &str, lf: &LazyFrame, column_name: &str) -> bool {
+    /// Takes the gates and the gated copies assigned in the gate manager, used by the
+    /// fills that follow.
+    pub fn set_gates(&mut self, cut_handler: &CutHandler) {
+        self.gate_exprs = cut_handler
+            .gate_names()
+            .into_iter()
+            .map(|name| {
+                let expr = cut_handler.gate_expr(&name);
+                (name, expr)
+            })
+            .collect();
+        self.assigned_gates = cut_handler.gated_histograms.clone();
+    }
+
+    fn note_used_gates(&mut self, gates: &[String]) {
+        for gate in gates {
+            if !self.used_gates.contains(gate) {
+                self.used_gates.push(gate.clone());
+            }
+        }
+    }
+
+    // Gates of a histogram from its definition and the gate manager, with their masks
+    // aliased "gate i" in the same order
+    fn gate_masks(&self, histogram: &str, gates: &[&str]) -> (Vec<String>, Vec<Expr>) {
+        let assigned = self
+            .assigned_gates
+            .iter()
+            .filter(|gated| gated.histogram == histogram)
+            .map(|gated| gated.gate.as_str());
+
+        let mut names: Vec<String> = Vec::new();
+        let mut exprs = Vec::new();
+        for gate in gates.iter().copied().chain(assigned) {
+            if names.iter().any(|name| name == gate) {
+                continue;
+            }
+
+            match self.gate_exprs.get(gate) {
+                Some(Ok(expr)) => {
+                    exprs.push(expr.clone().alias(&format!("gate {}", names.len())));
+                    names.push(gate.to_string());
+                }
+                Some(Err(e)) => {
+                    log::error!("Skipping gated histogram {} [{}]: {}", histogram, gate, e)
+                }
+                None => log::error!(
+                    "Skipping gated histogram {} [{}]: unknown gate",
+                    histogram,
+                    gate
+                ),
+            }
+        }
+
+        (names, exprs)
+    }
+
+    // Fills a 1D histogram with data from a polars dataframe/column, and a gated copy for
+    // each gate. The gate masks are collected with the column, so the copies take no extra pass.
+    pub fn fill_hist1d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        column_name: &str,
+        gates: &[&str],
+    ) -> bool {
         // find the histogram by name
-        let hist: &mut Histogram = match self.histograms1d.iter_mut().find(|h| h.name == name) {
-            Some(h) => h,
+        let index = match self.histograms1d.iter().position(|h| h.name == name) {
+            Some(index) => index,
             None => return false, // Return false if the histogram doesn't exist.
         };
 
-        // Attempt to collect the LazyFrame into a DataFrame
-        let df_result = lf
-            .clone()
-            .filter(col(column_name).neq(lit(-1e6))) // Filter out the -1e6 values.
-            .select([col(column_name)])
-            .collect();
+        let (gates, mut exprs) = self.gate_masks(name, gates);
+        self.note_used_gates(&gates);
+        exprs.insert(0, col(column_name).cast(DataType::Float64));
 
-        // Handle the Result before proceeding
-        match df_result {
-            Ok(df) => {
-                // Now that we have a DataFrame, we can attempt to convert it to an ndarray
-                let ndarray_df_result = df.to_ndarray::<Float64Type>(IndexOrder::Fortran);
-
-                match ndarray_df_result {
-                    Ok(ndarray_df) => {
-                        // You now have the ndarray and can proceed with your logic
-                        let shape = ndarray_df.shape();
-                        let rows = shape[0];
-
-                        // Iterating through the ndarray and filling the histogram
-                        for i in 0..rows {
-                            let value = ndarray_df[[i, 0]];
-                            hist.fill(value);
-                        }
-
-                        true
-                    }
-                    Err(e) => {
-                        // Handle the error, for example, log it or return an error
-                        log::error!("Failed to convert DataFrame to ndarray: {}", e);
-                        false
-                    }
-                }
-            }
+        let df = match lf.clone().select(exprs).collect() {
+            Ok(df) => df,
             Err(e) => {
-                // Handle the error, for example, log it or return an error
                 log::error!("Failed to collect LazyFrame: {}", e);
+                return false;
+            }
+        };
+
+        let hist = &mut self.histograms1d[index];
+        hist.column = column_name.to_string();
+
+        let mut copies: Vec<Histogram> = gates
+            .iter()
+            .map(|gate| {
+                let mut copy = hist.empty_copy(&gated_name(name, gate));
+                copy.gate = Some(gate.clone());
+                copy
+            })
+            .collect();
+
+        let filled = fill_1d(&df, column_name, hist, &mut copies);
+        self.histograms1d.extend(copies);
+
+        match filled {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Failed to fill {}: {}", name, e);
                 false
             }
         }
@@ -94,9 +174,23 @@ impl Histogrammer {
         column_name: &str,
         bins: usize,
         range: (f64, f64),
+    ) {
+        self.add_fill_gated_hist1d(name, lf, column_name, bins, range, &[]);
+    }
+
+    /// Adds and fills a 1D histogram with a gated copy for each gate, in the same pass.
+    /// Copies assigned in the gate manager are added as well.
+    pub fn add_fill_gated_hist1d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        column_name: &str,
+        bins: usize,
+        range: (f64, f64),
+        gates: &[&str],
     ) {
         self.add_hist1d(name, bins, range); // Add the histogram.
-        self.fill_hist1d(name, lf, column_name); // Fill it with data.
+        self.fill_hist1d(name, lf, column_name, gates); // Fill it and its gated copies.
     }
 
     // Adds a new 2D histogram to the histogram list.
@@ -110,60 +204,55 @@ impl Histogrammer {
         self.histograms2d.push(hist); // Store it in the vector.
     }
 
-    // Fills a 2D histogram with x and y data.
+    // Fills a 2D histogram with x and y data, and a gated copy for each gate in the same pass.
     pub fn fill_hist2d(
         &mut self,
         name: &str,
         lf: &LazyFrame,
         x_column_name: &str,
         y_column_name: &str,
+        gates: &[&str],
     ) -> bool {
         // find the histogram by name
-        let hist: &mut Histogram2D = match self.histograms2d.iter_mut().find(|h| h.name == name) {
-            Some(h) => h,
+        let index = match self.histograms2d.iter().position(|h| h.name == name) {
+            Some(index) => index,
             None => return false, // Return false if the histogram doesn't exist.
         };
 
-        // Attempt to collect the LazyFrame into a DataFrame
-        let df_result = lf
-            .clone()
-            .select([col(x_column_name), col(y_column_name)])
-            .filter(col(x_column_name).neq(lit(-1e6)))
-            .filter(col(y_column_name).neq(lit(-1e6)))
-            .collect();
+        let (gates, mut exprs) = self.gate_masks(name, gates);
+        self.note_used_gates(&gates);
+        exprs.insert(0, col(y_column_name).cast(DataType::Float64));
+        exprs.insert(0, col(x_column_name).cast(DataType::Float64));
 
-        // Handle the Result before proceeding
-        match df_result {
-            Ok(df) => {
-                // Now that we have a DataFrame, we can attempt to convert it to an ndarray
-                let ndarray_df_result = df.to_ndarray::<Float64Type>(IndexOrder::Fortran);
-
-                match ndarray_df_result {
-                    Ok(ndarray_df) => {
-                        // You now have the ndarray and can proceed with your logic
-                        let shape = ndarray_df.shape();
-                        let rows = shape[0];
-
-                        // Iterating through the ndarray rows and filling the 2D histogram
-                        for i in 0..rows {
-                            let x_value = ndarray_df[[i, 0]];
-                            let y_value = ndarray_df[[i, 1]];
-
-                            hist.fill(x_value, y_value);
-                        }
-
-                        true
-                    }
-                    Err(e) => {
-                        // Handle the error, for example, log it or return an error
-                        log::error!("Failed to convert DataFrame to ndarray: {}", e);
-                        false
-                    }
-                }
-            }
+        let df = match lf.clone().select(exprs).collect() {
+            Ok(df) => df,
             Err(e) => {
-                // Handle the error, for example, log it or return an error
                 log::error!("Failed to collect LazyFrame: {}", e);
+                return false;
+            }
+        };
+
+        // remember the columns so cuts drawn on the histogram can filter on them
+        let hist = &mut self.histograms2d[index];
+        hist.x_column = x_column_name.to_string();
+        hist.y_column = y_column_name.to_string();
+
+        let mut copies: Vec<Histogram2D> = gates
+            .iter()
+            .map(|gate| {
+                let mut copy = hist.empty_copy(&gated_name(name, gate));
+                copy.gate = Some(gate.clone());
+                copy
+            })
+            .collect();
+
+        let filled = fill_2d(&df, [x_column_name, y_column_name], hist, &mut copies);
+        self.histograms2d.extend(copies);
+
+        match filled {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Failed to fill {}: {}", name, e);
                 false
             }
         }
@@ -178,9 +267,25 @@ impl Histogrammer {
         y_column_name: &str,
         bins: (usize, usize),
         range: ((f64, f64), (f64, f64)),
+    ) {
+        self.add_fill_gated_hist2d(name, lf, x_column_name, y_column_name, bins, range, &[]);
+    }
+
+    /// Adds and fills a 2D histogram with a gated copy for each gate, in the same pass.
+    /// Copies assigned in the gate manager are added as well.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_fill_gated_hist2d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        x_column_name: &str,
+        y_column_name: &str,
+        bins: (usize, usize),
+        range: ((f64, f64), (f64, f64)),
+        gates: &[&str],
     ) {
         self.add_hist2d(name, bins, range); // Add the histogram.
-        self.fill_hist2d(name, lf, x_column_name, y_column_name); // Fill it with data.
+        self.fill_hist2d(name, lf, x_column_name, y_column_name, gates); // Fill it and its gated copies.
     }
 
     // Function to save the Histogrammer as JSON using a file dialog
@@ -262,9 +367,14 @@ impl Histogrammer {
         // Insert these pane groups into a tab tile structure
         let tab1 = tiles.insert_grid_tile(hist1d_panes);
         let tab2 = tiles.insert_grid_tile(hist2d_panes);
+        let tab3 = tiles.insert_pane(Pane::FitResults(FitResultsTable::new()));
+        let tab4 = tiles.insert_pane(Pane::BatchFit(Box::new(BatchFitter::new())));
+        let tab5 = tiles.insert_pane(Pane::TrendPlot(Box::new(TrendPlot::new())));
+        let tab6 = tiles.insert_pane(Pane::Gates);
+        let tab7 = tiles.insert_pane(Pane::Columns(Box::default()));
 
         // Collect the tabs into a vector and create the root tab tile
-        let root_tab = tiles.insert_tab_tile(vec![tab1, tab2]);
+        let root_tab = tiles.insert_tab_tile(vec![tab1, tab2, tab3, tab4, tab5, tab6, tab7]);
 
         // Construct the tree with a meaningful title and the root_tab, associating it with the tiles
         egui_tiles::Tree::new("Histogrammer", root_tab, tiles)
@@ -369,3 +479,116 @@ impl Histogrammer {
     //     });
     // }
 }
+
+fn gated_name(histogram: &str, gate: &str) -> String {
+    GatedHistogram {
+        histogram: histogram.to_string(),
+        gate: gate.to_string(),
+    }
+    .name()
+}
+
+// pass or fail of each "gate i" column for the collected rows, a missing value fails
+fn collected_masks(df: &DataFrame, count: usize) -> PolarsResult<Vec<Vec<bool>>> {
+    (0..count)
+        .map(|i| {
+            Ok(df
+                .column(&format!("gate {}", i))?
+                .bool()?
+                .into_iter()
+                .map(|pass| pass == Some(true))
+                .collect())
+        })
+        .collect()
+}
+
+fn fill_1d(
+    df: &DataFrame,
+    column: &str,
+    hist: &mut Histogram,
+    copies: &mut [Histogram],
+) -> PolarsResult<()> {
+    let masks = collected_masks(df, copies.len())?;
+    for (row, value) in df.column(column)?.f64()?.into_iter().enumerate() {
+        let value = match value {
+            Some(value) if value != -1e6 => value,
+            _ => continue,
+        };
+
+        hist.fill(value);
+        for (copy, mask) in copies.iter_mut().zip(&masks) {
+            if mask[row] {
+                copy.fill(value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn fill_2d(
+    df: &DataFrame,
+    [x_column, y_column]: [&str; 2],
+    hist: &mut Histogram2D,
+    copies: &mut [Histogram2D],
+) -> PolarsResult<()> {
+    let masks = collected_masks(df, copies.len())?;
+    let x = df.column(x_column)?.f64()?;
+    let y = df.column(y_column)?.f64()?;
+    for (row, (x, y)) in x.into_iter().zip(y).enumerate() {
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) if x != -1e6 && y != -1e6 => (x, y),
+            _ => continue,
+        };
+
+        hist.fill(x, y);
+        for (copy, mask) in copies.iter_mut().zip(&masks) {
+            if mask[row] {
+                copy.fill(x, y);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cutter::gates::{Gate, GateKind};
+
+    #[test]
+    fn test_add_gated_histograms() {
+        let lf = df!(
+            "x" => [1.5, 2.5, 3.5, -1e6],
+            "e" => [10.0, 20.0, 30.0, 20.0],
+        )
+        .unwrap()
+        .lazy();
+
+        let mut cut_handler = CutHandler::new();
+        cut_handler.gates.push(Gate::new(
+            "Window",
+            GateKind::Range {
+                column: "e".to_string(),
+                min: 15.0,
+                max: 35.0,
+            },
+        ));
+        let gated = |histogram: &str| GatedHistogram {
+            histogram: histogram.to_string(),
+            gate: "Window".to_string(),
+        };
+
+        // the same copy from the definition and the gate manager is filled once
+        cut_handler.gated_histograms = vec![gated("X"), gated("E v X")];
+        let mut h = Histogrammer::new();
+        h.set_gates(&cut_handler);
+        h.add_fill_gated_hist1d("X", &lf, "x", 4, (0.0, 4.0), &["Window"]);
+        h.add_fill_hist2d("E v X", &lf, "x", "e", (4, 4), ((0.0, 4.0), (0.0, 40.0)));
+        assert_eq!(h.histograms1d.len(), 2);
+
+        assert_eq!(h.histograms1d[0].bins, vec![0, 1, 1, 1]);
+        assert_eq!(h.histograms1d[1].name, "X [Window]");
+        assert_eq!(h.histograms1d[1].bins, vec![0, 0, 1, 1]);
+        assert_eq!(h.histograms2d[1].gate.as_deref(), Some("Window"));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod colormaps;
+pub mod column_browser;
 pub mod histogram1d;
 pub mod histogram2d;
 pub mod histogram_script;
@@ -117,17 +117,15 @@ impl LazyFramer {
         self.columns.clone()
     }
 
-    // Adjusted signature to match context
+    // The names come from the schema, so no rows are read
     pub fn get_column_names_from_lazyframe(lazyframe: &LazyFrame) -> Vec<String> {
-        let lf: LazyFrame = lazyframe.clone().limit(1);
-        let df: DataFrame = lf.collect().unwrap();
-        let columns: Vec<String> = df
-            .get_column_names_owned()
-            .into_iter()
-            .map(|name| name.to_string())
-            .collect();
-
-        columns
+        match lazyframe.schema() {
+            Ok(schema) => schema.iter_names().map(|name| name.to_string()).collect(),
+            Err(e) => {
+                log::error!("Failed to read the column names: {}", e);
+                Vec::new()
+            }
+        }
     }
 
     pub fn add_column(&mut self, expr: Expr) {
@@ -2,8 +2,8 @@
 
 #[cfg(not(target_arch = "wasm32"))]
 mod app;
-// #[cfg(not(target_arch = "wasm32"))]
-// mod cutter;
+#[cfg(not(target_arch = "wasm32"))]
+mod cutter;
 #[cfg(not(target_arch = "wasm32"))]
 mod egui_plot_stuff;
 #[cfg(not(target_arch = "wasm32"))]
@@ -15,6 +15,8 @@ mod lazyframer;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod processer;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod run_metadata;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod workspacer;
 #[cfg(not(target_arch = "wasm32"))]
 pub use app::NATApp;
@@ -1,3 +1,7 @@
+use super::fitter::batch_fit::BatchFitter;
+use super::fitter::fit_results::FitResultsTable;
+use super::fitter::trend_plot::TrendPlot;
+use super::histoer::column_browser::ColumnBrowser;
 use super::histoer::histogram1d::Histogram;
 use super::histoer::histogram2d::Histogram2D;
 use crate::workspacer::Workspacer;
@@ -7,6 +11,11 @@ pub enum Pane {
     Workspace(Workspacer),
     Histogram(Box<Histogram>),
     Histogram2D(Box<Histogram2D>),
+    FitResults(FitResultsTable),
+    BatchFit(Box<BatchFitter>),
+    TrendPlot(Box<TrendPlot>),
+    Gates, // shows the cut handler of the processer, lent by the tree behavior
+    Columns(Box<ColumnBrowser>),
 }
 
 impl Pane {
@@ -23,6 +32,26 @@ impl Pane {
             Pane::Histogram2D(hist) => {
                 hist.render(ui);
             }
+
+            Pane::FitResults(table) => {
+                table.ui(ui);
+            }
+
+            Pane::BatchFit(batch) => {
+                batch.ui(ui);
+            }
+
+            Pane::TrendPlot(trend) => {
+                trend.ui(ui);
+            }
+
+            Pane::Gates => {
+                ui.label("The gate manager is not available");
+            }
+
+            Pane::Columns(browser) => {
+                browser.ui(ui);
+            }
         }
         // if ui
         //     .add(egui::Button::new("").sense(egui::Sense::drag()))
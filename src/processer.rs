@@ -1,8 +1,10 @@
-// use super::cutter::cut_handler::CutHandler;
-use super::histoer::histogram_script::add_histograms;
+use super::cutter::cut_handler::CutHandler;
+use super::cutter::event_export::EventExport;
+use super::histoer::histogram_script::{add_columns, add_histograms};
 use super::histoer::histogrammer::Histogrammer;
 use super::lazyframer::LazyFramer;
 
+use polars::prelude::*;
 use std::path::PathBuf;
 
 #[derive(Default, serde::Deserialize, serde::Serialize)]
@@ -11,7 +13,10 @@ pub struct Processer {
     pub lazyframer: Option<LazyFramer>,
     pub files: Vec<PathBuf>,
     pub histogrammer: Histogrammer,
-    // pub cut_handler: CutHandler,
+    #[serde(default)]
+    pub cut_handler: CutHandler,
+    #[serde(default)]
+    pub event_export: EventExport,
 }
 
 impl Processer {
@@ -20,26 +25,22 @@ impl Processer {
             lazyframer: None,
             files: Vec::new(),
             histogrammer: Histogrammer::new(),
-            // cut_handler: CutHandler::new(),
+            cut_handler: CutHandler::new(),
+            event_export: EventExport::default(),
         }
     }
 
     fn create_lazyframe(&mut self) {
         self.lazyframer = Some(LazyFramer::new(self.files.clone()));
-
-        // // Update CutHandler with column names from LazyFramer
-        // if let Some(ref lazyframer) = self.lazyframer {
-        //     let column_names = lazyframer.get_column_names();
-        //     self.cut_handler.update_column_names(column_names);
-        //     log::info!("Column names: {:?}", self.cut_handler.column_names.clone());
-        // }
+        self.event_export.available_columns.clear();
     }
 
     fn perform_histogrammer_from_lazyframe(&mut self) {
         if let Some(lazyframer) = &self.lazyframer {
             if let Some(lf) = &lazyframer.lazyframe {
-                match add_histograms(lf.clone()) {
-                    Ok(h) => {
+                match add_histograms(lf.clone(), &self.cut_handler, &self.files) {
+                    Ok(mut h) => {
+                        self.cut_handler.statistics = h.gate_statistics.take();
                         self.histogrammer = h;
                     }
                     Err(e) => {
@@ -59,25 +60,6 @@ impl Processer {
         self.perform_histogrammer_from_lazyframe();
     }
 
-    // pub fn filter_lazyframe_with_cuts(&mut self) {
-    //     // First, check if `self.lazyframer` is Some and get a mutable reference to it
-    //     if let Some(ref mut lazyframer) = self.lazyframer {
-    //         // Now you can access `lazyframer.lazyframe` because `lazyframer` is a mutable reference to `LazyFramer`
-    //         if let Some(ref lazyframe) = lazyframer.lazyframe {
-    //             match self.cut_handler.filter_lf_with_all_cuts(lazyframe) {
-    //                 Ok(filtered_lf) => {
-    //                     // Use the setter method to update the lazyframe
-    //                     lazyframer.set_lazyframe(filtered_lf);
-    //                     self.perform_histogrammer_from_lazyframe();
-    //                 }
-    //                 Err(e) => {
-    //                     log::error!("Failed to filter LazyFrame with cuts: {}", e);
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
-
     pub fn save_current_lazyframe(&mut self) {
         // First, check if `self.lazyframer` is Some and get a mutable reference to it
         // if let Some(ref mut lazyframer) = self.lazyframer {
@@ -100,6 +82,37 @@ impl Processer {
         // }
     }
 
+    // Raw and calculated columns of the selected files
+    fn export_columns(&self) -> Vec<String> {
+        let Some(file) = self.files.first() else {
+            return Vec::new();
+        };
+
+        let schema = LazyFrame::scan_parquet(file, ScanArgsParquet::default())
+            .and_then(|lf| add_columns(lf).schema());
+
+        match schema {
+            Ok(schema) => schema.iter_names().map(|name| name.to_string()).collect(),
+            Err(e) => {
+                log::error!("Failed to read the columns of {}: {}", file.display(), e);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn event_export_ui(&mut self, ui: &mut egui::Ui) {
+        if self.event_export.available_columns.is_empty() {
+            self.event_export.available_columns = self.export_columns();
+        }
+
+        self.event_export
+            .ui(ui, &self.files, &self.cut_handler, add_columns);
+    }
+
+    pub fn cuts_ui(&mut self, ui: &mut egui::Ui) {
+        self.cut_handler.active_gate_ui(ui);
+    }
+
     pub fn calculation_ui(&mut self, ui: &mut egui::Ui) {
         ui.separator();
 
@@ -109,6 +122,8 @@ impl Processer {
                 self.calculate_histograms();
             }
 
+            self.cuts_ui(ui);
+
             // check to see if there is a lazyframe to cut
             if self.lazyframer.is_some() {
 
@@ -118,18 +133,6 @@ impl Processer {
                     self.save_current_lazyframe();
                 }
 
-            //     if !self.cut_handler.cuts.is_empty() {
-
-            //         ui.separator();
-            //         if ui.button("Filter with Cuts").on_hover_text("CAUTION: The collected lazyframe must fit it memory").clicked() {
-            //             self.filter_lazyframe_with_cuts();
-            //         }
-            //     }
-
-            // } else if !self.cut_handler.cuts.is_empty() {
-            //     ui.separator();
-
-            //     ui.label("Recalculate histograms to filter with cuts");
             }
         });
 
@@ -0,0 +1,482 @@
+use crate::workspacer::run_number;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use polars::prelude::*;
+
+/// What is known about a file without loading its data.
+#[derive(Debug, Clone, Default)]
+pub struct RunMetadata {
+    pub path: PathBuf,
+    pub run: Option<u32>,
+    pub size: u64,
+    pub rows: usize,
+    pub columns: usize,
+    pub modified: Option<SystemTime>,
+}
+
+impl RunMetadata {
+    // Reads the size and time from the file system and the rows and schema from the parquet footer
+    pub fn read(path: &Path) -> Self {
+        let mut metadata = Self {
+            path: path.to_path_buf(),
+            run: run_number(path),
+            ..Default::default()
+        };
+
+        if let Ok(file_metadata) = fs::metadata(path) {
+            metadata.size = file_metadata.len();
+            metadata.modified = file_metadata.modified().ok();
+        }
+
+        let footer = File::open(path)
+            .map_err(PolarsError::from)
+            .and_then(|file| {
+                let mut reader = ParquetReader::new(file);
+                Ok((reader.num_rows()?, reader.schema()?.fields.len()))
+            });
+
+        match footer {
+            Ok((rows, columns)) => {
+                metadata.rows = rows;
+                metadata.columns = columns;
+            }
+            Err(e) => log::error!("Failed to read the metadata of {}: {}", path.display(), e),
+        }
+
+        metadata
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// User notes for a run, the tags are used to select runs taken with the same setup.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RunNotes {
+    pub target: String,
+    pub beam: String,
+    pub b_field: String,
+    pub notes: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub enum RunTag {
+    #[default]
+    Target,
+    Beam,
+    BField,
+}
+
+impl RunTag {
+    pub const ALL: [RunTag; 3] = [RunTag::Target, RunTag::Beam, RunTag::BField];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RunTag::Target => "Target",
+            RunTag::Beam => "Beam",
+            RunTag::BField => "B-Field",
+        }
+    }
+
+    pub fn value<'a>(&self, notes: &'a RunNotes) -> &'a str {
+        match self {
+            RunTag::Target => &notes.target,
+            RunTag::Beam => &notes.beam,
+            RunTag::BField => &notes.b_field,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub enum RunSortColumn {
+    #[default]
+    Run,
+    Name,
+    Size,
+    Rows,
+    Columns,
+    Modified,
+    Tag(RunTag),
+}
+
+/// Table of the workspace files with their metadata and notes.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RunTable {
+    #[serde(default)]
+    pub notes: BTreeMap<PathBuf, RunNotes>,
+    #[serde(default)]
+    pub sort_column: RunSortColumn,
+    #[serde(default = "default_ascending")]
+    pub ascending: bool,
+    #[serde(default)]
+    pub tag: RunTag,
+    #[serde(default)]
+    pub tag_value: String,
+    #[serde(skip)]
+    pub metadata: Vec<RunMetadata>,
+    #[serde(skip)]
+    order: Vec<usize>, // rows as shown, sorted again on a header click so edited tags stay put
+}
+
+fn default_ascending() -> bool {
+    true
+}
+
+impl Default for RunTable {
+    fn default() -> Self {
+        Self {
+            notes: BTreeMap::new(),
+            sort_column: RunSortColumn::Run,
+            ascending: true,
+            tag: RunTag::Target,
+            tag_value: String::new(),
+            metadata: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "kB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < units.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+// UTC date and time, days to civil date from Howard Hinnant's algorithm
+pub fn format_time(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => return String::new(),
+    };
+
+    let days = seconds.div_euclid(86400);
+    let seconds_of_day = seconds.rem_euclid(86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        (seconds_of_day % 3600) / 60
+    )
+}
+
+impl RunTable {
+    // Reads the metadata of new files and keeps what was already read for the others
+    pub fn update_metadata(&mut self, files: &[PathBuf]) {
+        let mut previous = std::mem::take(&mut self.metadata);
+        self.metadata = files
+            .iter()
+            .map(|file| match previous.iter().position(|m| &m.path == file) {
+                Some(index) => previous.swap_remove(index),
+                None => RunMetadata::read(file),
+            })
+            .collect();
+    }
+
+    pub fn notes(&self, path: &Path) -> RunNotes {
+        self.notes.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Indices into the metadata in the order of the sort column.
+    pub fn sorted_indices(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.metadata.len()).collect();
+
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&self.metadata[a], &self.metadata[b]);
+            let ordering = match self.sort_column {
+                RunSortColumn::Run => a.run.cmp(&b.run),
+                RunSortColumn::Name => a.name().cmp(&b.name()),
+                RunSortColumn::Size => a.size.cmp(&b.size),
+                RunSortColumn::Rows => a.rows.cmp(&b.rows),
+                RunSortColumn::Columns => a.columns.cmp(&b.columns),
+                RunSortColumn::Modified => a.modified.cmp(&b.modified),
+                RunSortColumn::Tag(tag) => tag
+                    .value(&self.notes(&a.path))
+                    .cmp(tag.value(&self.notes(&b.path))),
+            }
+            .then_with(|| a.path.cmp(&b.path));
+
+            if self.ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+
+        indices
+    }
+
+    // Distinct values of a tag over the current files
+    pub fn tag_values(&self, tag: RunTag) -> Vec<String> {
+        let mut values: Vec<String> = self
+            .metadata
+            .iter()
+            .map(|m| tag.value(&self.notes(&m.path)).to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+        values.sort();
+        values.dedup();
+        values
+    }
+
+    pub fn files_with_tag(&self, tag: RunTag, value: &str) -> Vec<PathBuf> {
+        self.sorted_indices()
+            .into_iter()
+            .map(|index| &self.metadata[index].path)
+            .filter(|path| tag.value(&self.notes(path)) == value)
+            .cloned()
+            .collect()
+    }
+
+    fn header_button(&mut self, ui: &mut egui::Ui, label: &str, column: RunSortColumn) {
+        let text = if self.sort_column == column {
+            format!("{} {}", label, if self.ascending { "⏶" } else { "⏷" })
+        } else {
+            label.to_string()
+        };
+
+        if ui.button(text).clicked() {
+            self.order.clear();
+            if self.sort_column == column {
+                self.ascending = !self.ascending;
+            } else {
+                self.sort_column = column;
+                self.ascending = true;
+            }
+        }
+    }
+
+    fn tag_selection_ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>) {
+        ui.horizontal(|ui| {
+            ui.label("Select by Tag:");
+
+            egui::ComboBox::from_id_source("run_table_tag")
+                .selected_text(self.tag.label())
+                .show_ui(ui, |ui| {
+                    for tag in RunTag::ALL {
+                        ui.selectable_value(&mut self.tag, tag, tag.label());
+                    }
+                });
+
+            let values = self.tag_values(self.tag);
+            egui::ComboBox::from_id_source("run_table_tag_value")
+                .selected_text(self.tag_value.clone())
+                .show_ui(ui, |ui| {
+                    for value in values {
+                        ui.selectable_value(&mut self.tag_value, value.clone(), value);
+                    }
+                });
+
+            let files = self.files_with_tag(self.tag, &self.tag_value);
+
+            if ui
+                .add_enabled(!files.is_empty(), egui::Button::new("Select"))
+                .on_hover_text("Select only the runs with this tag")
+                .clicked()
+            {
+                *selected_files = files.clone();
+            }
+
+            if ui
+                .add_enabled(!files.is_empty(), egui::Button::new("Add"))
+                .on_hover_text("Add the runs with this tag to the selection")
+                .clicked()
+            {
+                for file in files {
+                    if !selected_files.contains(&file) {
+                        selected_files.push(file);
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, files: &[PathBuf], selected_files: &mut Vec<PathBuf>) {
+        if self.metadata.len() != files.len()
+            || self.metadata.iter().zip(files).any(|(m, f)| &m.path != f)
+        {
+            self.update_metadata(files);
+            self.order.clear();
+        }
+
+        self.tag_selection_ui(ui, selected_files);
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("run_table")
+                .striped(true)
+                .num_columns(10)
+                .show(ui, |ui| {
+                    self.header_button(ui, "Run", RunSortColumn::Run);
+                    self.header_button(ui, "File", RunSortColumn::Name);
+                    self.header_button(ui, "Size", RunSortColumn::Size);
+                    self.header_button(ui, "Rows", RunSortColumn::Rows);
+                    self.header_button(ui, "Columns", RunSortColumn::Columns);
+                    self.header_button(ui, "Modified", RunSortColumn::Modified);
+                    for tag in RunTag::ALL {
+                        self.header_button(ui, tag.label(), RunSortColumn::Tag(tag));
+                    }
+                    ui.label("Notes");
+                    ui.end_row();
+
+                    if self.order.len() != self.metadata.len() {
+                        self.order = self.sorted_indices();
+                    }
+
+                    for index in self.order.clone() {
+                        let metadata = &self.metadata[index];
+                        let path = metadata.path.clone();
+
+                        ui.label(metadata.run.map(|run| run.to_string()).unwrap_or_default());
+
+                        let is_selected = selected_files.contains(&path);
+                        if ui
+                            .selectable_label(is_selected, metadata.name())
+                            .on_hover_text(path.to_string_lossy())
+                            .clicked()
+                        {
+                            if is_selected {
+                                selected_files.retain(|f| f != &path);
+                            } else {
+                                selected_files.push(path.clone());
+                            }
+                        }
+
+                        ui.label(format_size(metadata.size));
+                        ui.label(metadata.rows.to_string());
+                        ui.label(metadata.columns.to_string());
+                        ui.label(metadata.modified.map(format_time).unwrap_or_default());
+
+                        let mut notes = self.notes(&path);
+                        let mut changed = false;
+                        // ids by file, so a focused field stays with its run when the rows move
+                        for (field, text) in [
+                            &mut notes.target,
+                            &mut notes.beam,
+                            &mut notes.b_field,
+                            &mut notes.notes,
+                        ]
+                        .into_iter()
+                        .enumerate()
+                        {
+                            changed |= ui
+                                .push_id((&path, field), |ui| {
+                                    ui.add(egui::TextEdit::singleline(text).desired_width(80.0))
+                                })
+                                .inner
+                                .changed();
+                        }
+
+                        if changed {
+                            if notes == RunNotes::default() {
+                                self.notes.remove(&path);
+                            } else {
+                                self.notes.insert(path, notes);
+                            }
+                        }
+
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, run: u32, rows: usize) -> RunMetadata {
+        RunMetadata {
+            path: PathBuf::from(name),
+            run: Some(run),
+            rows,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_and_select_by_tag() {
+        let mut table = RunTable {
+            metadata: vec![
+                metadata("run_3", 3, 10),
+                metadata("run_1", 1, 30),
+                metadata("run_2", 2, 20),
+            ],
+            ..Default::default()
+        };
+        for (file, target) in [("run_1", "12C"), ("run_3", "12C"), ("run_2", "CH2")] {
+            table.notes.insert(
+                PathBuf::from(file),
+                RunNotes {
+                    target: target.to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert_eq!(table.sorted_indices(), vec![1, 2, 0]);
+
+        table.sort_column = RunSortColumn::Rows;
+        table.ascending = false;
+        assert_eq!(table.sorted_indices(), vec![1, 2, 0]);
+
+        assert_eq!(table.tag_values(RunTag::Target), vec!["12C", "CH2"]);
+        assert_eq!(
+            table.files_with_tag(RunTag::Target, "12C"),
+            vec![PathBuf::from("run_1"), PathBuf::from("run_3")]
+        );
+    }
+
+    #[test]
+    fn test_read_metadata_and_format() {
+        let path = std::env::temp_dir().join(format!("run_77_{}.parquet", std::process::id()));
+        let mut df = df!("x" => [1.0, 2.0, 3.0], "y" => [1, 2, 3]).unwrap();
+        ParquetWriter::new(File::create(&path).unwrap())
+            .finish(&mut df)
+            .unwrap();
+
+        let metadata = RunMetadata::read(&path);
+        assert_eq!(metadata.run, Some(77));
+        assert_eq!((metadata.rows, metadata.columns), (3, 2));
+        assert!(metadata.size > 0);
+
+        assert_eq!(format_size(1_234_567), "1.2 MB");
+        assert_eq!(
+            format_time(UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)),
+            "2023-11-14 22:13"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::cutter::cut_handler::CutHandler;
 use crate::pane::Pane;
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -6,6 +7,8 @@ pub struct TreeBehavior {
     simplification_options: egui_tiles::SimplificationOptions,
     tab_bar_height: f32,
     gap_width: f32,
+    #[serde(skip)]
+    pub cut_handler: Option<CutHandler>, // the processer's, lent while the tree is shown
 }
 
 impl Default for TreeBehavior {
@@ -21,6 +24,7 @@ impl Default for TreeBehavior {
             },
             tab_bar_height: 24.0,
             gap_width: 2.0,
+            cut_handler: None,
         }
     }
 }
@@ -31,6 +35,7 @@ impl TreeBehavior {
             simplification_options,
             tab_bar_height,
             gap_width,
+            ..
         } = self;
 
         egui::Grid::new("behavior_ui")
@@ -73,6 +78,11 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
         _tile_id: egui_tiles::TileId,
         pane: &mut Pane,
     ) -> egui_tiles::UiResponse {
+        if let (Pane::Gates, Some(cut_handler)) = (&pane, &mut self.cut_handler) {
+            cut_handler.gate_manager_ui(ui);
+            return egui_tiles::UiResponse::None;
+        }
+
         pane.ui(ui)
     }
 
@@ -81,6 +91,13 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
             Pane::Workspace(_workspace) => "Workspace".into(),
             Pane::Histogram(hist) => hist.name.clone().into(),
             Pane::Histogram2D(hist) => hist.name.clone().into(),
+            Pane::FitResults(_table) => "Fit Results".into(),
+            Pane::BatchFit(_batch) => "Batch Fit".into(),
+            Pane::TrendPlot(trend) => {
+                format!("{} vs {}", trend.y_quantity.name(), trend.x_quantity.name()).into()
+            }
+            Pane::Gates => "Gates".into(),
+            Pane::Columns(_columns) => "Columns".into(),
         }
     }
 
@@ -4,11 +4,145 @@ use std::path::{Path, PathBuf};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::run_metadata::RunTable;
+
 #[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Workspacer {
     pub directory: Option<PathBuf>,
     pub files: Rc<RefCell<Vec<PathBuf>>>,
     pub selected_files: Rc<RefCell<Vec<PathBuf>>>,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub include_patterns: String, // comma separated globs, empty includes every file
+    #[serde(default)]
+    pub exclude_patterns: String,
+    #[serde(default)]
+    pub run_selection: String, // e.g. "100-120,125"
+    #[serde(default)]
+    pub run_table: RunTable,
+}
+
+fn digit_groups(s: &str) -> Vec<String> {
+    s.split(|c: char| !c.is_ascii_digit())
+        .filter(|group| !group.is_empty())
+        .map(|group| group.to_string())
+        .collect()
+}
+
+/// Run number written after "run" in a name, e.g. `Xavg_run_0042` -> 42.
+pub fn labelled_run_number(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let index = name.find("run")?;
+    digit_groups(&name[index + 3..])
+        .into_iter()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Run number of a file, taken from the digits after "run" in the file stem
+/// (`run_123.parquet`) or else the last group of digits.
+pub fn run_number(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_string_lossy();
+
+    labelled_run_number(&stem).or_else(|| digit_groups(&stem).pop()?.parse().ok())
+}
+
+/// Parses run ranges like `100-120,125` into inclusive (first, last) pairs.
+pub fn parse_run_ranges(text: &str) -> Result<Vec<(u32, u32)>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let parse = |s: &str| {
+                s.trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("'{}' is not a run number", s.trim()))
+            };
+
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("Run range '{}' is reversed", part));
+                    }
+                    Ok((first, last))
+                }
+                None => parse(part).map(|run| (run, run)),
+            }
+        })
+        .collect()
+}
+
+fn parse_patterns(patterns: &str) -> Vec<glob::Pattern> {
+    patterns
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .filter_map(|pattern| match glob::Pattern::new(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                log::error!("Invalid file pattern '{}': {}", pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Collects the .parquet files under `dir`, patterns match the path relative to `root` or the file name.
+// Symlinked directories are not followed, a link back up the tree would recurse forever.
+fn collect_parquet_files(
+    root: &Path,
+    dir: &Path,
+    recursive: bool,
+    include: &[glob::Pattern],
+    exclude: &[glob::Pattern],
+    files: &mut Vec<PathBuf>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if path.is_dir() {
+            let is_link = entry.file_type().map_or(true, |t| t.is_symlink());
+            if recursive && !is_link {
+                collect_parquet_files(root, &path, recursive, include, exclude, files);
+            }
+            continue;
+        }
+
+        if !path.is_file() || path.extension().and_then(|s| s.to_str()) != Some("parquet") {
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let file_name = Path::new(path.file_name().unwrap_or_default());
+        let matches = |pattern: &glob::Pattern| {
+            pattern.matches_path(relative) || pattern.matches_path(file_name)
+        };
+
+        if (include.is_empty() || include.iter().any(matches)) && !exclude.iter().any(matches) {
+            files.push(path);
+        }
+    }
+}
+
+// Sorts by run number, files without one go last
+fn sort_by_run_number(files: &mut [PathBuf]) {
+    files.sort_by(|a, b| match (run_number(a), run_number(b)) {
+        (Some(run_a), Some(run_b)) => run_a.cmp(&run_b).then_with(|| a.cmp(b)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
 }
 
 impl Workspacer {
@@ -17,6 +151,11 @@ impl Workspacer {
             directory: None,
             files: Rc::new(RefCell::new(Vec::new())),
             selected_files: Rc::new(RefCell::new(Vec::new())),
+            recursive: false,
+            include_patterns: String::new(),
+            exclude_patterns: String::new(),
+            run_selection: String::new(),
+            run_table: RunTable::default(),
         }
     }
 
@@ -33,17 +172,14 @@ impl Workspacer {
 
     // Helper method to load .parquet files from the selected directory
     fn get_parquet_files_in_directory(&mut self, dir: &Path) {
+        let include = parse_patterns(&self.include_patterns);
+        let exclude = parse_patterns(&self.exclude_patterns);
+
         let mut files = self.files.borrow_mut();
         files.clear(); // Clear any existing files
 
-        if let Ok(entries) = fs::read_dir(dir) {
-            for entry in entries.filter_map(Result::ok) {
-                let path = entry.path();
-                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("parquet") {
-                    files.push(path);
-                }
-            }
-        }
+        collect_parquet_files(dir, dir, self.recursive, &include, &exclude, &mut files);
+        sort_by_run_number(&mut files);
     }
 
     fn refresh_files(&mut self) {
@@ -71,6 +207,30 @@ impl Workspacer {
         *self.selected_files.borrow_mut() = files;
     }
 
+    // select the files with a run number in the run selection
+    pub fn select_runs(&self) {
+        let ranges = match parse_run_ranges(&self.run_selection) {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                log::error!("Invalid run selection: {}", e);
+                return;
+            }
+        };
+
+        let files = self.files.borrow();
+        *self.selected_files.borrow_mut() = files
+            .iter()
+            .filter(|file| {
+                run_number(file).map_or(false, |run| {
+                    ranges
+                        .iter()
+                        .any(|&(first, last)| run >= first && run <= last)
+                })
+            })
+            .cloned()
+            .collect();
+    }
+
     // Method to get the selected directory
     fn get_directory(&self) -> Option<&PathBuf> {
         self.directory.as_ref()
@@ -98,6 +258,68 @@ impl Workspacer {
         });
     }
 
+    pub fn scan_settings_ui(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut self.recursive, "Recursive")
+                .on_hover_text("Include the files in subdirectories")
+                .changed();
+
+            ui.separator();
+
+            ui.label("Include:");
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.include_patterns)
+                        .hint_text("run_*.parquet")
+                        .desired_width(150.0),
+                )
+                .on_hover_text("Comma separated glob patterns, empty includes every file")
+                .lost_focus();
+
+            ui.label("Exclude:");
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.exclude_patterns)
+                        .hint_text("*_gated.parquet")
+                        .desired_width(150.0),
+                )
+                .on_hover_text("Comma separated glob patterns")
+                .lost_focus();
+        });
+
+        if changed {
+            self.refresh_files();
+        }
+    }
+
+    pub fn run_selection_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Runs:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.run_selection)
+                    .hint_text("100-120,125")
+                    .desired_width(150.0),
+            );
+
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui
+                .button("Select Runs")
+                .on_hover_text("Select the files with a run number in the ranges")
+                .clicked()
+                || entered
+            {
+                self.select_runs();
+            }
+
+            if let Err(e) = parse_run_ranges(&self.run_selection) {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+    }
+
     pub fn file_selection_settings_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             if ui
@@ -123,40 +345,85 @@ impl Workspacer {
     pub fn file_selection_ui_in_menu(&mut self, ui: &mut egui::Ui) {
         ui.label("Parquet Files in Directory:");
 
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            // Use egui's Grid to allow side by side file display
-            egui::Grid::new("file_selection_grid")
-                .num_columns(5)
-                .show(ui, |ui| {
-                    let files = self.files.borrow();
-                    let mut selected_files = self.selected_files.borrow_mut();
-
-                    for (index, file) in files.iter().enumerate() {
-                        let file_stem = file.file_stem().unwrap_or_default().to_string_lossy();
-                        let is_selected = selected_files.contains(file);
-
-                        let response = ui.selectable_label(is_selected, file_stem);
-
-                        if response.clicked() {
-                            if is_selected {
-                                selected_files.retain(|f| f != file);
-                            } else {
-                                selected_files.push(file.clone());
-                            }
-                        }
-
-                        // After adding each file, check if it's time to end the row
-                        if (index + 1) % 5 == 0 {
-                            ui.end_row(); // End the current row after every 5 files
-                        }
-                    }
-                });
-        });
+        let files = self.files.borrow();
+        let mut selected_files = self.selected_files.borrow_mut();
+        self.run_table.ui(ui, &files, &mut selected_files);
     }
 
     pub fn workspace_ui(&mut self, ui: &mut egui::Ui) {
         self.select_directory_ui(ui);
+        self.scan_settings_ui(ui);
         self.file_selection_settings_ui(ui);
+        self.run_selection_ui(ui);
         self.file_selection_ui_in_menu(ui);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_numbers_and_ranges() {
+        assert_eq!(run_number(Path::new("data/run_123.parquet")), Some(123));
+        assert_eq!(run_number(Path::new("Run42_2024.parquet")), Some(42));
+        assert_eq!(run_number(Path::new("2024_file_7.parquet")), Some(7));
+        assert_eq!(run_number(Path::new("calibration.parquet")), None);
+
+        // histogram names only have a run number when it is labelled
+        assert_eq!(labelled_run_number("Xavg_run_0042"), Some(42));
+        assert_eq!(labelled_run_number("Cebra3Energy"), None);
+
+        assert_eq!(
+            parse_run_ranges("100-120, 125").unwrap(),
+            vec![(100, 120), (125, 125)]
+        );
+        assert!(parse_run_ranges("120-100").is_err());
+        assert!(parse_run_ranges("12a").is_err());
+    }
+
+    #[test]
+    fn test_recursive_scan_with_patterns() {
+        let dir = std::env::temp_dir().join(format!("workspacer_test_{}", std::process::id()));
+        let sub = dir.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        for path in [
+            dir.join("run_10.parquet"),
+            dir.join("run_9.parquet"),
+            dir.join("run_9_gated.parquet"),
+            sub.join("run_100.parquet"),
+            dir.join("notes.txt"),
+        ] {
+            fs::write(path, "").unwrap();
+        }
+
+        let mut workspacer = Workspacer::new();
+        workspacer.exclude_patterns = "*_gated.parquet".to_string();
+        workspacer.get_parquet_files_in_directory(&dir);
+        assert_eq!(
+            *workspacer.files.borrow(),
+            vec![dir.join("run_9.parquet"), dir.join("run_10.parquet")]
+        );
+
+        workspacer.recursive = true;
+        workspacer.get_parquet_files_in_directory(&dir);
+        assert_eq!(workspacer.files.borrow().len(), 3);
+
+        workspacer.run_selection = "10-200".to_string();
+        workspacer.select_runs();
+        assert_eq!(
+            *workspacer.selected_files.borrow(),
+            vec![dir.join("run_10.parquet"), sub.join("run_100.parquet")]
+        );
+
+        // a link back to the top is not followed
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, sub.join("loop")).unwrap();
+            workspacer.get_parquet_files_in_directory(&dir);
+            assert_eq!(workspacer.files.borrow().len(), 3);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}